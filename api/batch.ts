@@ -18,14 +18,14 @@ export default async function handler(req: Request) {
   }
 
   try {
-    const { from, payments, wallet } = await req.json();
+    const { from, payments, wallet, atomic = true, maxPerRecipient = 0 } = await req.json();
 
     if (!from || !payments || !Array.isArray(payments)) {
       return errorResponse("Missing from or payments array");
     }
 
-    if (payments.length < 1 || payments.length > 10) {
-      return errorResponse("Batch must contain 1-10 payments");
+    if (payments.length < 1 || payments.length > 61) {
+      return errorResponse("Batch must contain 1-61 payments");
     }
 
     const senderPDAs = getAgentPDAs(from);
@@ -45,8 +45,8 @@ export default async function handler(req: Request) {
         return errorResponse("Each payment must have 'to' and 'amount'");
       }
 
-      // v3: Validate memo length
-      if (payment.memo && payment.memo.length > 128) {
+      // v3: Validate memo length (v5: best-effort batches skip the entry instead)
+      if (atomic && payment.memo && payment.memo.length > 128) {
         return errorResponse(`Memo for "${payment.to}" exceeds 128 characters`);
       }
 
@@ -96,7 +96,11 @@ export default async function handler(req: Request) {
             tokenProgram: TOKEN_PROGRAM_ID.toBase58(),
           },
           remainingAccounts,
-          args: { payments: paymentEntries },
+          args: {
+            payments: paymentEntries,
+            atomic,
+            maxPerRecipient: Math.floor(parseFloat(maxPerRecipient) * 1_000_000),
+          },
         },
       },
     });
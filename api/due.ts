@@ -17,14 +17,25 @@ export default async function handler(req: Request) {
 
     const dueSubscriptions = accounts
       .filter(
-        (a: any) => a.account.isActive && Number(a.account.nextDue) <= now
+        (a: any) =>
+          a.account.isActive &&
+          Number(a.account.pausedAt) === 0 &&
+          Number(a.account.nextDue) <= now
       )
       .map((a: any) => ({
+        subscriptionId: Number(a.account.id),
         senderName: a.account.senderName,
         receiverName: a.account.receiverName,
         amount: Number(a.account.amount) / 1_000_000,
         nextDue: Number(a.account.nextDue),
         overdueSecs: now - Number(a.account.nextDue),
+        periodsDue: a.account.schedule.interval
+          ? Math.floor(
+              (now - Number(a.account.nextDue)) / Number(a.account.schedule.interval.seconds)
+            ) + 1
+          : null,
+        accruedDebt: Number(a.account.accruedDebt) / 1_000_000,
+        consecutiveFailures: a.account.consecutiveFailures,
         totalPaid: Number(a.account.totalPaid) / 1_000_000,
         executionCount: Number(a.account.executionCount),
       }));
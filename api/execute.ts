@@ -4,6 +4,7 @@ import {
   corsHeaders,
   getAgentPDAs,
   getSubscriptionPDA,
+  getProtocolConfigPDA,
   connection,
   TOKEN_PROGRAM_ID,
 } from "./shared";
@@ -19,7 +20,7 @@ export default async function handler(req: Request) {
   }
 
   try {
-    const { from, to, wallet } = await req.json();
+    const { from, to, wallet, subscriptionId = 0, tipAccount } = await req.json();
 
     if (!from || !to) {
       return errorResponse("Missing from or to");
@@ -27,7 +28,7 @@ export default async function handler(req: Request) {
 
     const senderPDAs = getAgentPDAs(from);
     const receiverPDAs = getAgentPDAs(to);
-    const subscription = getSubscriptionPDA(from, to);
+    const subscription = getSubscriptionPDA(from, to, subscriptionId);
 
     // Verify subscription exists
     const subscriptionAccount = await connection.getAccountInfo(subscription);
@@ -35,12 +36,17 @@ export default async function handler(req: Request) {
       return errorResponse("Subscription not found", 404);
     }
 
+    // v5: Optional accounts are passed as the program ID when absent
+    const protocolConfig = getProtocolConfigPDA();
+    const protocolConfigAccount = await connection.getAccountInfo(protocolConfig);
+
     return jsonResponse({
       success: true,
       message: "Ready to execute subscription",
       data: {
         from,
         to,
+        subscriptionId,
         subscription: subscription.toBase58(),
         instruction: {
           name: "executeSubscription",
@@ -52,6 +58,8 @@ export default async function handler(req: Request) {
             receiverVault: receiverPDAs.vault.toBase58(),
             tokenProgram: TOKEN_PROGRAM_ID.toBase58(),
             cranker: wallet || "SIGNER_REQUIRED",
+            crankerTokenAccount: tipAccount || null,
+            protocolConfig: protocolConfigAccount ? protocolConfig.toBase58() : null,
           },
         },
       },
@@ -14,7 +14,7 @@ import { PublicKey, Transaction } from "@solana/web3.js";
 
 /**
  * POST /api/invoice/:id/pay - Pay a pending invoice
- * Body: { wallet: string }
+ * Body: { wallet: string, references?: string[] }
  */
 export default async function handler(req: Request) {
   if (req.method === "OPTIONS") {
@@ -37,11 +37,27 @@ export default async function handler(req: Request) {
       return errorResponse("Invalid invoice ID");
     }
 
-    const { wallet } = await req.json();
+    const { wallet, references } = await req.json();
     if (!wallet) {
       return errorResponse("Missing wallet address");
     }
 
+    // v5: Solana Pay reference keys (read-only, max 5)
+    const referenceKeys: string[] = references || [];
+    if (!Array.isArray(referenceKeys) || referenceKeys.length > 5) {
+      return errorResponse("references must be an array of at most 5 public keys");
+    }
+    let remainingAccounts: any[];
+    try {
+      remainingAccounts = referenceKeys.map((ref: string) => ({
+        pubkey: new PublicKey(ref),
+        isSigner: false,
+        isWritable: false,
+      }));
+    } catch (e) {
+      return errorResponse("Invalid reference public key");
+    }
+
     const walletPubkey = new PublicKey(wallet);
 
     const program = await getProgram();
@@ -92,6 +108,7 @@ export default async function handler(req: Request) {
         authority: walletPubkey,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
+      .remainingAccounts(remainingAccounts)
       .instruction();
 
     const transaction = new Transaction().add(payInvoiceIx);
@@ -83,13 +83,16 @@ export default async function handler(req: Request) {
     const originalReceiver = invoice.requesterName;
     const originalSender = invoice.payerName;
     const originalAmount = invoice.amount.toNumber() / 1_000_000;
+    // v5: Partial refunds are tracked on-chain
+    const alreadyRefunded = invoice.refundedAmount.toNumber() / 1_000_000;
+    const refundableAmount = originalAmount - alreadyRefunded;
 
     // Determine refund amount
-    const refundAmount = amount || originalAmount;
+    const refundAmount = amount || refundableAmount;
 
-    if (refundAmount > originalAmount) {
+    if (refundAmount > refundableAmount) {
       return errorResponse(
-        `Refund amount (${refundAmount}) exceeds original payment (${originalAmount})`
+        `Refund amount (${refundAmount}) exceeds refundable amount (${refundableAmount} of ${originalAmount})`
       );
     }
 
@@ -107,22 +110,23 @@ export default async function handler(req: Request) {
     const senderPDAs = getAgentPDAs(originalReceiver); // refund sender = original receiver
     const receiverPDAs = getAgentPDAs(originalSender); // refund recipient = original sender
 
-    // Build transfer_by_name instruction for the refund
+    // v5: Build the on-chain refund instruction, linked to the invoice
     const refundAmountUnits = new BN(Math.floor(refundAmount * 1_000_000));
 
-    const transferIx = await (program.methods as any)
-      .transferByName(refundAmountUnits, refundMemo)
+    const refundIx = await (program.methods as any)
+      .refund(refundAmountUnits, refundMemo)
       .accounts({
-        senderRegistry: senderPDAs.agentRegistry,
-        senderVault: senderPDAs.vault,
-        receiverRegistry: receiverPDAs.agentRegistry,
-        receiverVault: receiverPDAs.vault,
+        invoice: invoicePDA,
+        requesterRegistry: senderPDAs.agentRegistry,
+        payerRegistry: receiverPDAs.agentRegistry,
+        requesterVault: senderPDAs.vault,
+        payerVault: receiverPDAs.vault,
         authority: walletPubkey,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
       .instruction();
 
-    const transaction = new Transaction().add(transferIx);
+    const transaction = new Transaction().add(refundIx);
     transaction.recentBlockhash = (
       await connection.getLatestBlockhash()
     ).blockhash;
@@ -142,7 +146,8 @@ export default async function handler(req: Request) {
         from: originalReceiver,
         to: originalSender,
         amount: refundAmount,
-        fullRefund: refundAmount === originalAmount,
+        fullRefund: refundAmount + alreadyRefunded === originalAmount,
+        previouslyRefunded: alreadyRefunded,
         reason: reason || null,
         originalInvoice: invoiceId,
         memo: refundMemo,
@@ -6,6 +6,7 @@ import {
   connection,
   TOKEN_PROGRAM_ID,
 } from "./shared";
+import { PublicKey } from "@solana/web3.js";
 
 
 export default async function handler(req: Request) {
@@ -18,7 +19,7 @@ export default async function handler(req: Request) {
   }
 
   try {
-    const { from, to, amount, wallet, memo } = await req.json();
+    const { from, to, amount, wallet, memo, references } = await req.json();
 
     if (!from || !to || !amount) {
       return errorResponse("Missing from, to, or amount");
@@ -29,6 +30,22 @@ export default async function handler(req: Request) {
       return errorResponse("Memo exceeds 128 characters");
     }
 
+    // v5: Validate Solana Pay reference keys
+    const referenceKeys: string[] = references || [];
+    if (!Array.isArray(referenceKeys) || referenceKeys.length > 5) {
+      return errorResponse("references must be an array of at most 5 public keys");
+    }
+    let remainingAccounts: any[];
+    try {
+      remainingAccounts = referenceKeys.map((ref: string) => ({
+        pubkey: new PublicKey(ref).toBase58(),
+        isSigner: false,
+        isWritable: false,
+      }));
+    } catch (e) {
+      return errorResponse("Invalid reference public key");
+    }
+
     const amountNum = parseFloat(amount);
     if (isNaN(amountNum) || amountNum <= 0) {
       return errorResponse("Invalid amount");
@@ -60,6 +77,7 @@ export default async function handler(req: Request) {
         amount: amountNum,
         amountUnits,
         memo: memo || null,
+        references: referenceKeys,
         senderVault: senderPDAs.vault.toBase58(),
         receiverVault: receiverPDAs.vault.toBase58(),
         instruction: {
@@ -72,6 +90,7 @@ export default async function handler(req: Request) {
             authority: wallet || "SIGNER_REQUIRED",
             tokenProgram: TOKEN_PROGRAM_ID.toBase58(),
           },
+          remainingAccounts,
           args: { amount: amountUnits, memo: memo || null },
         },
       },
@@ -38,15 +38,23 @@ export function getAgentPDAs(name: string) {
 }
 
 // Get Subscription PDA
-export function getSubscriptionPDA(senderName: string, receiverName: string) {
+// v5: Seeded by a client-chosen subscription ID (u64 LE)
+export function getSubscriptionPDA(
+  senderName: string,
+  receiverName: string,
+  subscriptionId: number | bigint = 0
+) {
   const senderPDAs = getAgentPDAs(senderName);
   const receiverPDAs = getAgentPDAs(receiverName);
+  const idBuffer = Buffer.alloc(8);
+  idBuffer.writeBigUInt64LE(BigInt(subscriptionId));
 
   const [subscription] = PublicKey.findProgramAddressSync(
     [
       Buffer.from("subscription"),
       senderPDAs.agentRegistry.toBuffer(),
       receiverPDAs.agentRegistry.toBuffer(),
+      idBuffer,
     ],
     PROGRAM_ID
   );
@@ -78,6 +86,57 @@ export function getInvoiceCounterPDA() {
   return counter;
 }
 
+// v5: Get Protocol Config PDA
+export function getProtocolConfigPDA() {
+  const [config] = PublicKey.findProgramAddressSync(
+    [Buffer.from("protocol_config")],
+    PROGRAM_ID
+  );
+  return config;
+}
+
+// v5: Get an agent's Split Config PDA
+export function getSplitConfigPDA(agentRegistry: PublicKey) {
+  const [config] = PublicKey.findProgramAddressSync(
+    [Buffer.from("split_config"), agentRegistry.toBuffer()],
+    PROGRAM_ID
+  );
+  return config;
+}
+
+// v5: Remaining accounts of one split recipient, in the pre-order the
+// program walks them: [registry, vault] for a direct recipient,
+// [registry, split_config] followed by that config's recipients for a
+// cascading one
+export async function getSplitAccounts(
+  program: Program,
+  agentRegistry: PublicKey,
+  cascade: boolean,
+  depth = 0
+): Promise<{ pubkey: string; isSigner: boolean; isWritable: boolean }[]> {
+  if (!cascade) {
+    const registry = await (program.account as any).agentRegistry.fetch(agentRegistry);
+    return [
+      { pubkey: agentRegistry.toBase58(), isSigner: false, isWritable: true },
+      { pubkey: registry.vault.toBase58(), isSigner: false, isWritable: true },
+    ];
+  }
+
+  if (depth >= 3) {
+    throw new Error("Split cascades more than 3 levels deep");
+  }
+  const splitConfig = getSplitConfigPDA(agentRegistry);
+  const config = await (program.account as any).splitConfig.fetch(splitConfig);
+  const accounts = [
+    { pubkey: agentRegistry.toBase58(), isSigner: false, isWritable: false },
+    { pubkey: splitConfig.toBase58(), isSigner: false, isWritable: false },
+  ];
+  for (const entry of config.recipients) {
+    accounts.push(...(await getSplitAccounts(program, entry.registry, entry.cascade, depth + 1)));
+  }
+  return accounts;
+}
+
 // v4: Get Invoice PDA by ID
 export function getInvoicePDA(invoiceId: number | bigint) {
   const idBuffer = Buffer.alloc(8);
@@ -99,7 +158,7 @@ export const INVOICE_STATUS = {
   EXPIRED: 4,
 };
 
-export const INVOICE_STATUS_NAMES = ["pending", "paid", "rejected", "cancelled", "expired"];
+export const INVOICE_STATUS_NAMES = ["pending", "paid", "rejected", "cancelled", "expired", "refunded"];
 
 // Get balance
 export async function getVaultBalance(vault: PublicKey): Promise<number> {
@@ -119,6 +178,16 @@ export function formatInterval(seconds: number): string {
   return `${Math.floor(seconds / 86400)} days`;
 }
 
+// Format a subscription schedule as decoded by Anchor
+const WEEKDAYS = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+export function formatSchedule(schedule: any): string {
+  if (schedule.interval) return `every ${formatInterval(Number(schedule.interval.seconds))}`;
+  if (schedule.monthly) return `monthly on day ${schedule.monthly.day}`;
+  if (schedule.weekly) return `weekly on ${WEEKDAYS[schedule.weekly.weekday]}`;
+  return "custom (cron)";
+}
+
 // Get program (lazy loaded)
 let program: Program | null = null;
 
@@ -3,6 +3,8 @@ import {
   errorResponse,
   corsHeaders,
   getAgentPDAs,
+  getSplitAccounts,
+  getProgram,
   connection,
   TOKEN_PROGRAM_ID,
 } from "./shared";
@@ -18,7 +20,7 @@ export default async function handler(req: Request) {
   }
 
   try {
-    const { from, totalAmount, recipients, wallet, memo } = await req.json();
+    const { from, totalAmount, recipients, wallet, memo, rounding } = await req.json();
 
     if (!from || !totalAmount || !recipients || !Array.isArray(recipients)) {
       return errorResponse("Missing from, totalAmount, or recipients array");
@@ -29,8 +31,8 @@ export default async function handler(req: Request) {
       return errorResponse("Memo exceeds 128 characters");
     }
 
-    if (recipients.length < 2 || recipients.length > 10) {
-      return errorResponse("Split must have 2-10 recipients");
+    if (recipients.length < 2 || recipients.length > 20) {
+      return errorResponse("Split must have 2-20 recipients");
     }
 
     // Validate shares sum to 10000
@@ -67,20 +69,38 @@ export default async function handler(req: Request) {
         return errorResponse(`Recipient agent "${recipient.name}" not found`, 404);
       }
 
-      remainingAccounts.push({
-        pubkey: recipientPDAs.agentRegistry.toBase58(),
-        isSigner: false,
-        isWritable: true,
-      });
-      remainingAccounts.push({
-        pubkey: recipientPDAs.vault.toBase58(),
-        isSigner: false,
-        isWritable: true,
-      });
+      // v5: Cascading recipients forward their share through their SplitConfig
+      const cascade = recipient.cascade === true;
+      if (cascade) {
+        const program = await getProgram();
+        if (!program) {
+          return errorResponse("Failed to load program", 503);
+        }
+        try {
+          remainingAccounts.push(
+            ...(await getSplitAccounts(program, recipientPDAs.agentRegistry, true))
+          );
+        } catch (error: any) {
+          return errorResponse(`Split config of "${recipient.name}" not usable: ${error.message}`);
+        }
+      } else {
+        remainingAccounts.push({
+          pubkey: recipientPDAs.agentRegistry.toBase58(),
+          isSigner: false,
+          isWritable: true,
+        });
+        remainingAccounts.push({
+          pubkey: recipientPDAs.vault.toBase58(),
+          isSigner: false,
+          isWritable: true,
+        });
+      }
 
       splitRecipients.push({
         name: recipient.name,
         shareBps: recipient.shareBps,
+        minAmount: Math.floor(parseFloat(recipient.minAmount || 0) * 1_000_000),
+        cascade,
       });
     }
 
@@ -97,6 +117,7 @@ export default async function handler(req: Request) {
           name: r.name,
           shareBps: r.shareBps,
           percentage: (r.shareBps / 100).toFixed(2) + "%",
+          cascade: r.cascade,
           estimatedAmount: (parseFloat(totalAmount) * r.shareBps) / 10000,
         })),
         instruction: {
@@ -112,6 +133,7 @@ export default async function handler(req: Request) {
             totalAmount: amountUnits,
             recipients: splitRecipients,
             memo: memo || null,
+            rounding: rounding || { largestRemainder: {} },
           },
         },
       },
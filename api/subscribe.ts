@@ -5,7 +5,7 @@ import {
   getAgentPDAs,
   getSubscriptionPDA,
   connection,
-  formatInterval,
+  formatSchedule,
   SYSTEM_PROGRAM_ID,
 } from "./shared";
 
@@ -18,13 +18,13 @@ export default async function handler(req: Request) {
   // DELETE - Cancel subscription
   if (req.method === "DELETE") {
     try {
-      const { from, to, wallet } = await req.json();
+      const { from, to, wallet, subscriptionId = 0 } = await req.json();
 
       if (!from || !to) {
         return errorResponse("Missing from or to");
       }
 
-      const subscription = getSubscriptionPDA(from, to);
+      const subscription = getSubscriptionPDA(from, to, subscriptionId);
 
       // Verify subscription exists
       const subscriptionAccount = await connection.getAccountInfo(subscription);
@@ -38,6 +38,7 @@ export default async function handler(req: Request) {
         data: {
           from,
           to,
+          subscriptionId,
           subscription: subscription.toBase58(),
           instruction: {
             name: "cancelSubscription",
@@ -59,19 +60,34 @@ export default async function handler(req: Request) {
   }
 
   try {
-    const { from, to, amount, intervalSeconds, wallet } = await req.json();
-
-    if (!from || !to || !amount || !intervalSeconds) {
-      return errorResponse("Missing from, to, amount, or intervalSeconds");
+    const {
+      from,
+      to,
+      amount,
+      intervalSeconds,
+      schedule: requestedSchedule,
+      wallet,
+      subscriptionId = 0,
+      maxExecutions = 0,
+      endAt = 0,
+      maxTotal = 0,
+      missedPeriodPolicy,
+    } = await req.json();
+
+    if (!from || !to || !amount || (!intervalSeconds && !requestedSchedule)) {
+      return errorResponse("Missing from, to, amount, or intervalSeconds/schedule");
     }
 
-    if (intervalSeconds < 60) {
+    if (!requestedSchedule && intervalSeconds < 60) {
       return errorResponse("Interval must be at least 60 seconds");
     }
 
+    // Either a raw interval or an Anchor-encoded schedule, e.g. { monthly: { day: 1 } }
+    const schedule = requestedSchedule || { interval: { seconds: intervalSeconds } };
+
     const senderPDAs = getAgentPDAs(from);
     const receiverPDAs = getAgentPDAs(to);
-    const subscription = getSubscriptionPDA(from, to);
+    const subscription = getSubscriptionPDA(from, to, subscriptionId);
 
     // Verify sender exists
     const senderAccount = await connection.getAccountInfo(senderPDAs.agentRegistry);
@@ -88,10 +104,18 @@ export default async function handler(req: Request) {
     // Check if subscription already exists
     const existingSubscription = await connection.getAccountInfo(subscription);
     if (existingSubscription) {
-      return errorResponse("Subscription already exists between these agents", 409);
+      return errorResponse(
+        `Subscription #${subscriptionId} already exists between these agents, choose another subscriptionId`,
+        409
+      );
     }
 
     const amountUnits = Math.floor(parseFloat(amount) * 1_000_000);
+    const maxTotalUnits = Math.floor(parseFloat(maxTotal) * 1_000_000);
+
+    if (maxTotalUnits !== 0 && maxTotalUnits < amountUnits) {
+      return errorResponse("maxTotal must cover at least one payment");
+    }
 
     return jsonResponse({
       success: true,
@@ -100,8 +124,12 @@ export default async function handler(req: Request) {
         from,
         to,
         amount: parseFloat(amount),
-        intervalSeconds,
-        intervalHuman: formatInterval(intervalSeconds),
+        schedule,
+        scheduleHuman: formatSchedule(schedule),
+        subscriptionId,
+        maxExecutions,
+        endAt,
+        maxTotal: parseFloat(maxTotal),
         subscription: subscription.toBase58(),
         instruction: {
           name: "createSubscription",
@@ -114,9 +142,16 @@ export default async function handler(req: Request) {
             systemProgram: SYSTEM_PROGRAM_ID.toBase58(),
           },
           args: {
+            subscriptionId,
             receiverName: to,
             amount: amountUnits,
-            intervalSeconds,
+            schedule,
+            limits: {
+              maxExecutions,
+              endAt,
+              maxTotal: maxTotalUnits,
+            },
+            missedPeriodPolicy: missedPeriodPolicy || { skip: {} },
           },
         },
       },
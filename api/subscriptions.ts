@@ -1,4 +1,4 @@
-import { jsonResponse, errorResponse, corsHeaders, getProgram, formatInterval } from "./shared";
+import { jsonResponse, errorResponse, corsHeaders, getProgram, formatSchedule } from "./shared";
 
 
 export default async function handler(req: Request) {
@@ -18,14 +18,37 @@ export default async function handler(req: Request) {
     const accounts = await (program.account as any).subscription.all();
 
     let subscriptions = accounts.map((a: any) => ({
+      subscriptionId: Number(a.account.id),
       senderName: a.account.senderName,
       receiverName: a.account.receiverName,
       amount: Number(a.account.amount) / 1_000_000,
-      intervalSeconds: Number(a.account.intervalSeconds),
-      intervalHuman: formatInterval(Number(a.account.intervalSeconds)),
+      schedule: a.account.schedule,
+      scheduleHuman: formatSchedule(a.account.schedule),
       nextDue: Number(a.account.nextDue),
       nextDueHuman: new Date(Number(a.account.nextDue) * 1000).toISOString(),
       isActive: a.account.isActive,
+      paused: Number(a.account.pausedAt) !== 0,
+      completed: Number(a.account.completedAt) !== 0,
+      maxExecutions: Number(a.account.maxExecutions),
+      endAt: Number(a.account.endAt),
+      maxTotal: Number(a.account.maxTotal) / 1_000_000,
+      missedPeriodPolicy: Object.keys(a.account.missedPeriodPolicy)[0],
+      accruedDebt: Number(a.account.accruedDebt) / 1_000_000,
+      crankTip: Number(a.account.crankTip) / 1_000_000,
+      maxCrankTip: Number(a.account.maxCrankTip) / 1_000_000,
+      missedPeriods: Number(a.account.missedPeriods),
+      totalCrankTips: Number(a.account.totalCrankTips) / 1_000_000,
+      pendingChange: a.account.pendingChange
+        ? {
+            amount: Number(a.account.pendingChange.amount) / 1_000_000,
+            scheduleHuman: formatSchedule(a.account.pendingChange.schedule),
+            effectiveAt: Number(a.account.pendingChange.effectiveAt),
+            accepted: a.account.pendingChange.accepted,
+          }
+        : null,
+      consecutiveFailures: a.account.consecutiveFailures,
+      maxConsecutiveFailures: a.account.maxConsecutiveFailures,
+      lastFailureReason: Object.keys(a.account.lastFailureReason)[0],
       totalPaid: Number(a.account.totalPaid) / 1_000_000,
       executionCount: Number(a.account.executionCount),
     }));
@@ -0,0 +1,106 @@
+//! Off-chain helpers for SolClaw payment channel vouchers.
+//!
+//! The payer signs cumulative vouchers with their wallet key. The payee
+//! settles the latest one by sending the Ed25519 program instruction built by
+//! [`ed25519_instruction`] right before `settle_channel`.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+pub use solclaw::voucher_message;
+
+/// A signed promise that the payee may claim `cumulative_amount` in total
+/// from `channel`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Voucher {
+    pub channel: Pubkey,
+    pub cumulative_amount: u64,
+    pub signature: [u8; 64],
+}
+
+impl Voucher {
+    /// The exact bytes covered by the signature.
+    pub fn message(&self) -> Vec<u8> {
+        voucher_message(&self.channel, self.cumulative_amount)
+    }
+}
+
+/// Sign a voucher with the payer's wallet key.
+pub fn sign_voucher(signing_key: &SigningKey, channel: &Pubkey, cumulative_amount: u64) -> Voucher {
+    let signature = signing_key.sign(&voucher_message(channel, cumulative_amount));
+    Voucher {
+        channel: *channel,
+        cumulative_amount,
+        signature: signature.to_bytes(),
+    }
+}
+
+/// Check that `voucher` was signed by `signer` (the channel's voucher signer).
+pub fn verify_voucher(voucher: &Voucher, signer: &Pubkey) -> bool {
+    let Ok(key) = VerifyingKey::from_bytes(&signer.to_bytes()) else {
+        return false;
+    };
+    key.verify(&voucher.message(), &Signature::from_bytes(&voucher.signature))
+        .is_ok()
+}
+
+/// Build the Ed25519 program instruction that proves `voucher` was signed by
+/// `signer`. It must precede `settle_channel` in the same transaction.
+pub fn ed25519_instruction(voucher: &Voucher, signer: &Pubkey) -> Instruction {
+    const HEADER_SIZE: u16 = 2;
+    const OFFSETS_SIZE: u16 = 14;
+    const SAME_INSTRUCTION: u16 = u16::MAX;
+
+    let message = voucher.message();
+    let pubkey_offset = HEADER_SIZE + OFFSETS_SIZE;
+    let signature_offset = pubkey_offset + 32;
+    let message_offset = signature_offset + 64;
+
+    let mut data = Vec::with_capacity(message_offset as usize + message.len());
+    data.extend_from_slice(&[1, 0]);
+    for field in [
+        signature_offset,
+        SAME_INSTRUCTION,
+        pubkey_offset,
+        SAME_INSTRUCTION,
+        message_offset,
+        message.len() as u16,
+        SAME_INSTRUCTION,
+    ] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(signer.as_ref());
+    data.extend_from_slice(&voucher.signature);
+    data.extend_from_slice(&message);
+
+    Instruction {
+        program_id: solana_sdk_ids::ed25519_program::ID,
+        accounts: vec![],
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_voucher_round_trips_through_program_check() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let signer = Pubkey::new_from_array(signing_key.verifying_key().to_bytes());
+        let channel = Pubkey::new_unique();
+
+        let voucher = sign_voucher(&signing_key, &channel, 1_500_000);
+        assert!(verify_voucher(&voucher, &signer));
+        assert!(!verify_voucher(&voucher, &Pubkey::new_unique()));
+
+        let ix = ed25519_instruction(&voucher, &signer);
+        assert!(solclaw::ed25519_instruction_has_signature(&ix.data, &signer, &voucher.message()));
+        assert!(!solclaw::ed25519_instruction_has_signature(
+            &ix.data,
+            &signer,
+            &voucher_message(&channel, 2_000_000),
+        ));
+    }
+}
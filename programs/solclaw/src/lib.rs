@@ -1,11 +1,65 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
+
+pub mod schedule;
+pub use schedule::Schedule;
 
 declare_id!("J4qipHcPyaPkVs8ymCLcpgqSDJeoSn3k1LJLK7Q9DZ5H");
 
 // USDC Mint on Solana Devnet
 pub const USDC_MINT: &str = "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU";
 
+// v5: Maximum number of Solana Pay reference keys per payment
+pub const MAX_REFERENCES: usize = 5;
+
+// v5: Maximum distinct recipients in one batch. Each takes a [registry, vault]
+// pair, a transaction can lock at most 128 accounts, and 6 are fixed (fee
+// payer/authority, sender registry, sender vault, token program, this program,
+// compute budget program): (128 - 6) / 2 = 61.
+pub const MAX_BATCH_RECIPIENTS: usize = 61;
+
+// v5: Maximum entries in batch_payment_compact. Entries can repeat a
+// recipient; at 9 bytes each, 64 entries to 61 recipients still fit the
+// 1232-byte packet once the recipients come from a lookup table.
+pub const MAX_COMPACT_BATCH_SIZE: usize = 64;
+
+// v5: Maximum number of recipients in a persistent split configuration
+pub const MAX_SPLIT_CONFIG_RECIPIENTS: usize = 20;
+
+// v5: Maximum nesting of cascading splits below the top-level recipients,
+// and maximum nodes in one payout tree (bounded by compute and account locks)
+pub const MAX_SPLIT_DEPTH: usize = 3;
+pub const MAX_SPLIT_PAYOUT_NODES: usize = 32;
+
+// v5: Maximum number of milestones per contract, and how long the payer has
+// to approve or dispute a submitted milestone before the payee can claim it
+pub const MAX_MILESTONES: usize = 10;
+pub const MILESTONE_REVIEW_SECONDS: i64 = 7 * 86_400;
+
+// v5: How long the provider can still settle signed usage after a metered
+// plan is cancelled
+pub const METERED_SETTLEMENT_GRACE_SECONDS: i64 = 7 * 86_400;
+
+// v5: Maximum missed subscription periods paid back in one crank (CatchUp policy)
+pub const MAX_CATCH_UP_PERIODS: u64 = 12;
+
+// v5: Maximum calendar schedule occurrences walked in one crank (bounded by compute)
+pub const MAX_SCHEDULE_STEPS: u64 = 64;
+
+// v5: Minimum spacing between two recorded failures of the same subscription,
+// and how many consecutive failures suspend a new subscription by default
+pub const FAILURE_RETRY_SECONDS: i64 = 3_600;
+pub const DEFAULT_MAX_CONSECUTIVE_FAILURES: u8 = 3;
+
+// v5: Domain separator for payment channel vouchers
+pub const VOUCHER_DOMAIN: &[u8] = b"solclaw:voucher:v1";
+
+// v5: Domain separator for gasless payment intents
+pub const INTENT_DOMAIN: &[u8] = b"solclaw:intent:v1";
+
+// v5: Domain separator for metered usage checkpoints
+pub const USAGE_DOMAIN: &[u8] = b"solclaw:usage:v1";
+
 #[program]
 pub mod solclaw {
     use super::*;
@@ -17,7 +71,7 @@ pub mod solclaw {
     /// Register a new agent with a human-readable name
     /// Creates a PDA vault for USDC storage
     pub fn register_agent(ctx: Context<RegisterAgent>, name: String) -> Result<()> {
-        require!(name.len() >= 1 && name.len() <= 32, SolclawError::InvalidNameLength);
+        require!(!name.is_empty() && name.len() <= 32, SolclawError::InvalidNameLength);
 
         let agent = &mut ctx.accounts.agent_registry;
         let clock = Clock::get()?;
@@ -70,6 +124,7 @@ pub mod solclaw {
 
     /// Transfer USDC from sender vault to receiver vault by name
     /// v3: Added optional memo parameter
+    /// v5: Read-only Solana Pay reference keys may be passed as remaining accounts
     pub fn transfer_by_name(
         ctx: Context<TransferByName>,
         amount: u64,
@@ -82,6 +137,9 @@ pub mod solclaw {
             require!(m.len() <= 128, SolclawError::MemoTooLong);
         }
 
+        // v5: Collect Solana Pay reference keys
+        let references = collect_references(ctx.remaining_accounts)?;
+
         let sender_registry = &mut ctx.accounts.sender_registry;
 
         // Verify sender authority
@@ -118,10 +176,10 @@ pub mod solclaw {
         )?;
 
         // Update stats
-        sender_registry.total_sent = sender_registry.total_sent.checked_add(amount).unwrap_or(u64::MAX);
+        sender_registry.total_sent = sender_registry.total_sent.saturating_add(amount);
 
         let receiver = &mut ctx.accounts.receiver_registry;
-        receiver.total_received = receiver.total_received.checked_add(amount).unwrap_or(u64::MAX);
+        receiver.total_received = receiver.total_received.saturating_add(amount);
 
         // v3: Emit transfer event with memo
         emit!(TransferEvent {
@@ -129,6 +187,7 @@ pub mod solclaw {
             receiver: receiver.name.clone(),
             amount,
             memo: memo.unwrap_or_default(),
+            references,
             timestamp: clock.unix_timestamp,
         });
 
@@ -188,11 +247,24 @@ pub mod solclaw {
 
     /// Batch pay multiple agents in one transaction.
     /// v3: Added memo support per payment entry
+    /// v5: Up to MAX_BATCH_RECIPIENTS entries; more than about 10 need a v0
+    /// transaction with the recipient accounts in a lookup table
+    /// v5: Recipient registries must be writable; their total_received is updated
+    /// v5: `max_per_recipient` caps what any one recipient gets in the batch,
+    /// across all of its entries (0 = no limit)
+    /// v5: With `atomic = false`, entries with an over-long memo, a failed
+    /// recipient check, or that would overdraw the vault, exceed the daily cap
+    /// or exceed `max_per_recipient` are skipped and reported in the event
     pub fn batch_payment<'info>(
-        ctx: Context<'_, '_, '_, 'info, BatchPayment<'info>>,
+        ctx: Context<'_, '_, 'info, 'info, BatchPayment<'info>>,
         payments: Vec<BatchPaymentEntry>,
+        atomic: bool,
+        max_per_recipient: u64,
     ) -> Result<()> {
-        require!(payments.len() >= 1 && payments.len() <= 10, SolclawError::InvalidBatchSize);
+        require!(
+            !payments.is_empty() && payments.len() <= MAX_BATCH_RECIPIENTS,
+            SolclawError::InvalidBatchSize
+        );
 
         let sender_registry = &mut ctx.accounts.sender_registry;
 
@@ -203,9 +275,12 @@ pub mod solclaw {
         );
 
         // v3: Validate memo lengths
-        for payment in payments.iter() {
-            if let Some(ref m) = payment.memo {
-                require!(m.len() <= 128, SolclawError::MemoTooLong);
+        // v5: Best-effort batches skip entries with an over-long memo instead
+        if atomic {
+            for payment in payments.iter() {
+                if let Some(ref m) = payment.memo {
+                    require!(m.len() <= 128, SolclawError::MemoTooLong);
+                }
             }
         }
 
@@ -215,10 +290,23 @@ pub mod solclaw {
             SolclawError::InvalidRemainingAccounts
         );
 
-        // v3: Calculate total and check spending cap
-        let total: u64 = payments.iter().map(|p| p.amount).sum();
         let clock = Clock::get()?;
-        check_and_update_spending_cap(sender_registry, total, &clock)?;
+
+        // v3: Atomic batches check the spending cap against the full total up front.
+        // v5: Best-effort batches only charge what is actually sent, after the loop.
+        if atomic {
+            let total = payments
+                .iter()
+                .try_fold(0u64, |acc, p| acc.checked_add(p.amount))
+                .ok_or(SolclawError::Overflow)?;
+            check_and_update_spending_cap(sender_registry, total, &clock)?;
+        }
+        let mut cap_headroom = if atomic {
+            u64::MAX
+        } else {
+            remaining_spending_cap(sender_registry, &clock)
+        };
+        let mut balance = ctx.accounts.sender_vault.amount;
 
         // Create signer seeds for the sender vault PDA
         let name_bytes = sender_registry.name.as_bytes();
@@ -234,32 +322,66 @@ pub mod solclaw {
         let mut recipients: Vec<String> = Vec::new();
         let mut amounts: Vec<u64> = Vec::new();
         let mut memos: Vec<String> = Vec::new();
+        let mut statuses: Vec<u8> = Vec::new();
+        // v5: Amount sent to each recipient registry so far in this batch
+        let mut sent_to: Vec<(Pubkey, u64)> = Vec::new();
 
         for (i, payment) in payments.iter().enumerate() {
-            require!(payment.amount > 0, SolclawError::InvalidAmount);
+            let memo = payment.memo.clone().unwrap_or_default();
+            let memo_len = memo.len();
+            recipients.push(payment.recipient_name.clone());
+            amounts.push(payment.amount);
+            memos.push(if memo_len <= 128 { memo } else { String::new() });
 
             let recipient_registry_info = &remaining[i * 2];
             let recipient_vault_info = &remaining[i * 2 + 1];
 
-            // Validate recipient registry PDA
-            let (expected_registry_pda, _) = Pubkey::find_program_address(
-                &[b"agent", payment.recipient_name.as_bytes()],
-                ctx.program_id,
-            );
-            require!(
-                recipient_registry_info.key() == expected_registry_pda,
-                SolclawError::NameMismatch
-            );
+            let already_sent = sent_to
+                .iter()
+                .find(|(key, _)| *key == recipient_registry_info.key())
+                .map_or(0, |(_, sent)| *sent);
+            let recipient_headroom = if max_per_recipient == 0 {
+                u64::MAX
+            } else {
+                max_per_recipient.saturating_sub(already_sent)
+            };
 
-            // Validate recipient vault PDA
-            let (expected_vault_pda, _) = Pubkey::find_program_address(
-                &[b"vault", payment.recipient_name.as_bytes()],
-                ctx.program_id,
-            );
-            require!(
-                recipient_vault_info.key() == expected_vault_pda,
-                SolclawError::VaultMismatch
+            if atomic {
+                require!(payment.amount > 0, SolclawError::InvalidAmount);
+                require!(
+                    payment.amount <= recipient_headroom,
+                    SolclawError::RecipientLimitExceeded
+                );
+            } else {
+                // v5: Skip entries that would fail rather than aborting the batch
+                let status = BatchPaymentEntry::skip_status(
+                    payment.amount,
+                    memo_len,
+                    balance,
+                    cap_headroom,
+                    recipient_headroom,
+                );
+                if status != BatchPaymentEntry::STATUS_SENT {
+                    statuses.push(status);
+                    continue;
+                }
+            }
+
+            // v5: Validate recipient registry and vault PDAs with stored bumps
+            let loaded = load_recipient(
+                recipient_registry_info,
+                recipient_vault_info,
+                Some(&payment.recipient_name),
+                sender_registry.key(),
             );
+            let mut recipient_registry = match loaded {
+                Ok(registry) => registry,
+                Err(err) if atomic => return Err(err),
+                Err(_) => {
+                    statuses.push(BatchPaymentEntry::STATUS_INVALID_RECIPIENT);
+                    continue;
+                }
+            };
 
             // Transfer USDC
             token::transfer(
@@ -275,10 +397,25 @@ pub mod solclaw {
                 payment.amount,
             )?;
 
+            // v5: Update receiver stats
+            credit_recipient(&mut recipient_registry, payment.amount)?;
+
+            balance = balance.saturating_sub(payment.amount);
+            cap_headroom = cap_headroom.saturating_sub(payment.amount);
+            match sent_to
+                .iter_mut()
+                .find(|(key, _)| *key == recipient_registry_info.key())
+            {
+                Some((_, sent)) => *sent += payment.amount,
+                None => sent_to.push((recipient_registry_info.key(), payment.amount)),
+            }
             total_sent = total_sent.checked_add(payment.amount).ok_or(SolclawError::Overflow)?;
-            recipients.push(payment.recipient_name.clone());
-            amounts.push(payment.amount);
-            memos.push(payment.memo.clone().unwrap_or_default());
+            statuses.push(BatchPaymentEntry::STATUS_SENT);
+        }
+
+        // v5: Only the sent total counts against the cap in best-effort mode
+        if !atomic {
+            check_and_update_spending_cap(sender_registry, total_sent, &clock)?;
         }
 
         // Update sender stats
@@ -290,6 +427,8 @@ pub mod solclaw {
             recipients,
             amounts,
             memos,
+            statuses,
+            atomic,
             total: total_sent,
             timestamp: clock.unix_timestamp,
         });
@@ -299,20 +438,156 @@ pub mod solclaw {
         Ok(())
     }
 
+    /// v5: Compact batch payment for payroll-sized batches.
+    /// Remaining accounts hold each distinct recipient once as a
+    /// (registry, vault) pair; `entries` is a packed array of
+    /// `CompactPaymentEntry` records (u8 recipient index + u64 LE amount)
+    /// read in place without per-entry deserialization. Recipients are
+    /// validated once with their stored bumps and credited once at the end.
+    /// Meant to be sent as a v0 transaction with the recipient accounts in an
+    /// address lookup table (see README, "Large Batches").
+    pub fn batch_payment_compact<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchPayment<'info>>,
+        entries: Vec<u8>,
+    ) -> Result<()> {
+        require!(
+            entries.len() % CompactPaymentEntry::SIZE == 0,
+            SolclawError::InvalidCompactEntries
+        );
+        let entry_count = entries.len() / CompactPaymentEntry::SIZE;
+        require!(
+            (1..=MAX_COMPACT_BATCH_SIZE).contains(&entry_count),
+            SolclawError::InvalidBatchSize
+        );
+
+        let remaining = ctx.remaining_accounts;
+        require!(
+            !remaining.is_empty()
+                && remaining.len() % 2 == 0
+                && remaining.len() / 2 <= MAX_BATCH_RECIPIENTS,
+            SolclawError::InvalidRemainingAccounts
+        );
+
+        // Validate every recipient once. Each must appear once: registries are
+        // credited from their own loaded copy, so a duplicate would overwrite
+        // an earlier credit.
+        let sender = ctx.accounts.sender_registry.key();
+        let mut recipient_registries: Vec<Account<AgentRegistry>> =
+            Vec::with_capacity(remaining.len() / 2);
+        for pair in remaining.chunks_exact(2) {
+            require!(
+                !recipient_registries.iter().any(|r| r.key() == pair[0].key()),
+                SolclawError::DuplicateRecipient
+            );
+            recipient_registries.push(load_recipient(&pair[0], &pair[1], None, sender)?);
+        }
+
+        // Validate entries and compute the total before moving any funds
+        let mut total: u64 = 0;
+        for chunk in entries.chunks_exact(CompactPaymentEntry::SIZE) {
+            let entry = CompactPaymentEntry::read(chunk);
+            require!(entry.amount > 0, SolclawError::InvalidAmount);
+            require!(
+                (entry.recipient_index as usize) < recipient_registries.len(),
+                SolclawError::InvalidRecipientIndex
+            );
+            total = total.checked_add(entry.amount).ok_or(SolclawError::Overflow)?;
+        }
+
+        let sender_registry = &mut ctx.accounts.sender_registry;
+        let clock = Clock::get()?;
+        check_and_update_spending_cap(sender_registry, total, &clock)?;
+
+        // Create signer seeds for the sender vault PDA
+        let name_bytes = sender_registry.name.as_bytes();
+        let vault_bump = sender_registry.vault_bump;
+        let seeds = &[
+            b"vault".as_ref(),
+            name_bytes,
+            &[vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let mut credited = vec![0u64; recipient_registries.len()];
+        let mut recipient_indices: Vec<u8> = Vec::with_capacity(entry_count);
+        let mut amounts: Vec<u64> = Vec::with_capacity(entry_count);
+
+        for chunk in entries.chunks_exact(CompactPaymentEntry::SIZE) {
+            let entry = CompactPaymentEntry::read(chunk);
+            let index = entry.recipient_index as usize;
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.sender_vault.to_account_info(),
+                        to: remaining[index * 2 + 1].to_account_info(),
+                        authority: ctx.accounts.sender_vault.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                entry.amount,
+            )?;
+
+            credited[index] = credited[index]
+                .checked_add(entry.amount)
+                .ok_or(SolclawError::Overflow)?;
+            recipient_indices.push(entry.recipient_index);
+            amounts.push(entry.amount);
+        }
+
+        // Update receiver stats once per recipient
+        for (registry, amount) in recipient_registries.iter_mut().zip(credited.iter()) {
+            if *amount > 0 {
+                credit_recipient(registry, *amount)?;
+            }
+        }
+
+        // Update sender stats
+        sender_registry.total_sent = sender_registry.total_sent.checked_add(total).ok_or(SolclawError::Overflow)?;
+
+        emit!(CompactBatchPaymentEvent {
+            sender: sender_registry.name.clone(),
+            recipients: recipient_registries.iter().map(|r| r.key()).collect(),
+            recipient_indices,
+            amounts,
+            total,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Compact batch payment: {} USDC in {} entries to {} recipients",
+            total,
+            entry_count,
+            recipient_registries.len()
+        );
+
+        Ok(())
+    }
+
     // ============================================================
     // SPLIT PAYMENT
     // ============================================================
 
     /// Split a total USDC amount across multiple agents proportionally.
     /// v3: Added memo support
+    /// v5: Up to MAX_SPLIT_CONFIG_RECIPIENTS recipients, like split configs
+    /// v5: Recipient registries must be writable; their total_received is updated
+    /// v5: Rounding dust is allocated by `rounding`; shares below a recipient's
+    /// `min_amount` fail the split
+    /// v5: A recipient with `cascade` set forwards its share through its own
+    /// SplitConfig. Remaining accounts are laid out in pre-order: [registry, vault]
+    /// for a direct recipient, [registry, split_config] followed by that config's
+    /// recipients for a cascading one
     pub fn split_payment<'info>(
-        ctx: Context<'_, '_, '_, 'info, SplitPayment<'info>>,
+        ctx: Context<'_, '_, 'info, 'info, SplitPayment<'info>>,
         total_amount: u64,
         recipients: Vec<SplitRecipient>,
         memo: Option<String>,
+        rounding: SplitRounding,
     ) -> Result<()> {
         require!(
-            recipients.len() >= 2 && recipients.len() <= 10,
+            recipients.len() >= 2 && recipients.len() <= MAX_SPLIT_CONFIG_RECIPIENTS,
             SolclawError::TooManySplitRecipients
         );
 
@@ -332,12 +607,6 @@ pub mod solclaw {
             SolclawError::Unauthorized
         );
 
-        let remaining = &ctx.remaining_accounts;
-        require!(
-            remaining.len() == recipients.len() * 2,
-            SolclawError::InvalidRemainingAccounts
-        );
-
         // v3: Check spending cap
         let clock = Clock::get()?;
         check_and_update_spending_cap(sender_registry, total_amount, &clock)?;
@@ -352,69 +621,32 @@ pub mod solclaw {
         ];
         let signer_seeds = &[&seeds[..]];
 
-        // Calculate amounts, handling dust
-        let mut distributed: u64 = 0;
-        let mut recipient_names: Vec<String> = Vec::new();
-        let mut amounts: Vec<u64> = Vec::new();
-
-        for (i, recipient) in recipients.iter().enumerate() {
-            let amount = if i == recipients.len() - 1 {
-                // Last recipient gets the remainder
-                total_amount.checked_sub(distributed).ok_or(SolclawError::Overflow)?
-            } else {
-                // amount = total * share_bps / 10000
-                ((total_amount as u128)
-                    .checked_mul(recipient.share_bps as u128)
-                    .ok_or(SolclawError::Overflow)?
-                    / 10000) as u64
-            };
-
-            if amount == 0 {
-                distributed = distributed.checked_add(amount).ok_or(SolclawError::Overflow)?;
-                continue;
-            }
-
-            let recipient_registry_info = &remaining[i * 2];
-            let recipient_vault_info = &remaining[i * 2 + 1];
-
-            // Validate recipient registry PDA
-            let (expected_registry_pda, _) = Pubkey::find_program_address(
-                &[b"agent", recipient.name.as_bytes()],
-                ctx.program_id,
-            );
-            require!(
-                recipient_registry_info.key() == expected_registry_pda,
-                SolclawError::NameMismatch
-            );
-
-            // Validate recipient vault PDA
-            let (expected_vault_pda, _) = Pubkey::find_program_address(
-                &[b"vault", recipient.name.as_bytes()],
-                ctx.program_id,
-            );
-            require!(
-                recipient_vault_info.key() == expected_vault_pda,
-                SolclawError::VaultMismatch
-            );
+        // v5: Calculate amounts, allocating dust by the rounding policy
+        let shares: Vec<u16> = recipients.iter().map(|r| r.share_bps).collect();
+        let (amounts, dust) = compute_split_amounts(total_amount, &shares, rounding)?;
+        for (recipient, amount) in recipients.iter().zip(amounts.iter()) {
+            require!(*amount >= recipient.min_amount, SolclawError::SplitBelowMinimum);
+        }
 
-            // Transfer
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.sender_vault.to_account_info(),
-                        to: recipient_vault_info.to_account_info(),
-                        authority: ctx.accounts.sender_vault.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
-                amount,
+        // v5: Pay each recipient, cascading through nested split configs.
+        // Zero shares are skipped but still reported in the event.
+        let mut payout = SplitPayout::new(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.sender_vault.to_account_info(),
+            signer_seeds,
+            ctx.remaining_accounts,
+            sender_registry.key(),
+        );
+        for (recipient, amount) in recipients.iter().zip(amounts.iter()) {
+            payout.pay(
+                SplitTarget::Name(&recipient.name),
+                recipient.cascade,
+                *amount,
+                SplitPayoutNode::ROOT,
+                0,
             )?;
-
-            distributed = distributed.checked_add(amount).ok_or(SolclawError::Overflow)?;
-            recipient_names.push(recipient.name.clone());
-            amounts.push(amount);
         }
+        let payouts = payout.finish()?;
 
         // Update sender stats
         sender_registry.total_sent = sender_registry.total_sent.checked_add(total_amount).ok_or(SolclawError::Overflow)?;
@@ -422,9 +654,12 @@ pub mod solclaw {
         // v3: Emit split payment event with memo
         emit!(SplitPaymentEvent {
             sender: sender_registry.name.clone(),
-            recipients: recipient_names,
+            recipients: recipients.iter().map(|r| r.name.clone()).collect(),
             amounts,
             total: total_amount,
+            rounding,
+            dust,
+            payouts,
             memo: memo.unwrap_or_default(),
             timestamp: clock.unix_timestamp,
         });
@@ -439,14 +674,21 @@ pub mod solclaw {
     // ============================================================
 
     /// Create a new recurring payment subscription
+    /// v5: `subscription_id` is a client-chosen nonce, so a sender can hold several
+    /// subscriptions to the same receiver and re-subscribe after cancelling
+    /// v5: `limits` optionally bounds the subscription to a fixed term, and
+    /// `missed_period_policy` decides what happens to periods cranked late
+    /// v5: `schedule` replaces the fixed interval and may follow the calendar
     pub fn create_subscription(
         ctx: Context<CreateSubscription>,
+        subscription_id: u64,
         receiver_name: String,
         amount: u64,
-        interval_seconds: i64,
+        schedule: Schedule,
+        limits: SubscriptionLimits,
+        missed_period_policy: MissedPeriodPolicy,
     ) -> Result<()> {
-        require!(amount > 0, SolclawError::InvalidAmount);
-        require!(interval_seconds >= 60, SolclawError::InvalidInterval);
+        validate_subscription_terms(amount, &schedule, &limits)?;
 
         let sender_registry = &ctx.accounts.sender_registry;
         let receiver_registry = &ctx.accounts.receiver_registry;
@@ -468,26 +710,26 @@ pub mod solclaw {
         let now = Clock::get()?.unix_timestamp;
 
         let subscription = &mut ctx.accounts.subscription;
+        subscription.id = subscription_id;
         subscription.sender = sender_registry.key();
         subscription.receiver = receiver_registry.key();
         subscription.sender_name = sender_registry.name.clone();
         subscription.receiver_name = receiver_name;
-        subscription.amount = amount;
-        subscription.interval_seconds = interval_seconds;
-        subscription.last_executed = now;
-        subscription.next_due = now + interval_seconds;
-        subscription.is_active = true;
         subscription.authority = ctx.accounts.authority.key();
-        subscription.total_paid = 0;
-        subscription.execution_count = 0;
+        subscription.payer = ctx.accounts.payer.key();
+        subscription.offer = Pubkey::default();
+        subscription.terms_hash = [0u8; 32];
         subscription.bump = ctx.bumps.subscription;
+        subscription.start(amount, schedule, limits, missed_period_policy, now)?;
 
         msg!(
-            "Subscription created: {} -> {}, {} USDC every {} seconds",
+            "Subscription #{} created: {} -> {}, {} USDC on {:?}, first due at {}",
+            subscription_id,
             subscription.sender_name,
             subscription.receiver_name,
             amount,
-            interval_seconds
+            schedule,
+            subscription.next_due
         );
 
         Ok(())
@@ -495,1462 +737,7612 @@ pub mod solclaw {
 
     /// Execute a due subscription payment. ANYONE can call this (permissionless crank).
     /// v3: Added spending cap check and auto-generated memo
+    /// v5: A crank past the subscription's limits pays nothing and marks it completed
+    /// v5: next_due stays anchored to the original schedule; periods missed since
+    /// then are skipped, caught up or accrued as debt per `missed_period_policy`.
+    /// Under Accrue, periods the vault and cap can't cover become debt, so such
+    /// a crank succeeds even when it pays nothing.
     pub fn execute_subscription(ctx: Context<ExecuteSubscription>) -> Result<()> {
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+
         let subscription = &mut ctx.accounts.subscription;
 
         require!(subscription.is_active, SolclawError::SubscriptionNotActive);
+        require!(subscription.paused_at == 0, SolclawError::SubscriptionPaused);
+        require!(subscription.suspended_at == 0, SolclawError::SubscriptionSuspended);
 
-        let now = Clock::get()?.unix_timestamp;
+        // v5: Agreed price or schedule changes apply from their effective date
+        apply_subscription_change(subscription, now)?;
         require!(now >= subscription.next_due, SolclawError::SubscriptionNotDue);
 
-        let sender_registry = &mut ctx.accounts.sender_registry;
+        if subscription.limit_reached() {
+            return complete_subscription(subscription, now);
+        }
 
-        // v3: Check spending cap
-        let clock = Clock::get()?;
-        check_and_update_spending_cap(sender_registry, subscription.amount, &clock)?;
+        let amount = subscription.amount;
+        let (periods_due, due_after) = subscription
+            .periods_due(now)
+            .ok_or(SolclawError::InvalidSchedule)?;
+        let headroom = ctx
+            .accounts
+            .sender_vault
+            .amount
+            .min(remaining_spending_cap(&ctx.accounts.sender_registry, &clock));
+
+        let (periods_paid, periods_accrued, periods_skipped, next_due) = subscription
+            .missed_period_outcome(now, periods_due, due_after, headroom)
+            .ok_or(SolclawError::InvalidSchedule)?;
+
+        let period_payment = amount
+            .checked_mul(periods_paid)
+            .ok_or(SolclawError::Overflow)?;
+        let accrued_debt = amount
+            .checked_mul(periods_accrued)
+            .and_then(|new_debt| new_debt.checked_add(subscription.accrued_debt))
+            .ok_or(SolclawError::Overflow)?;
 
-        // Create signer seeds for the sender vault
-        let name_bytes = sender_registry.name.as_bytes();
-        let seeds = &[
-            b"vault".as_ref(),
-            name_bytes,
-            &[sender_registry.vault_bump],
-        ];
-        let signer_seeds = &[&seeds[..]];
+        // v5: The cranker is tipped only if it passed a token account to receive it
+        let crank_tip = if ctx.accounts.cranker_token_account.is_some() {
+            let protocol_tip = ctx
+                .accounts
+                .protocol_config
+                .as_ref()
+                .map_or(0, |config| config.default_crank_tip);
+            subscription
+                .crank_tip(protocol_tip)
+                .min(headroom.saturating_sub(period_payment))
+        } else {
+            0
+        };
 
-        // Execute the transfer
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.sender_vault.to_account_info(),
-                    to: ctx.accounts.receiver_vault.to_account_info(),
-                    authority: ctx.accounts.sender_vault.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            subscription.amount,
-        )?;
+        // Whatever the vault and cap still allow goes towards outstanding debt
+        let debt_paid = accrued_debt.min(headroom.saturating_sub(period_payment + crank_tip));
+        let payment = period_payment + debt_paid;
+        let crank_delay = (now - subscription.next_due) as u64;
 
-        // Update subscription state
-        subscription.last_executed = now;
-        subscription.next_due = now + subscription.interval_seconds;
-        subscription.total_paid = subscription
-            .total_paid
-            .checked_add(subscription.amount)
-            .ok_or(SolclawError::Overflow)?;
-        subscription.execution_count += 1;
+        // v5: Under Accrue a crank with nothing to spend only records the debt
+        if payment > 0 {
+            pay_subscription(ctx.accounts, payment, &clock)?;
+        }
 
-        // Update sender stats
-        sender_registry.total_sent = sender_registry
-            .total_sent
-            .checked_add(subscription.amount)
-            .ok_or(SolclawError::Overflow)?;
+        if crank_tip > 0 {
+            if let Some(cranker_token_account) = &ctx.accounts.cranker_token_account {
+                let to = cranker_token_account.to_account_info();
+                transfer_from_sender_vault(ctx.accounts, to, crank_tip, &clock)?;
+            }
+        }
 
-        // Update receiver stats
-        let receiver = &mut ctx.accounts.receiver_registry;
-        receiver.total_received = receiver
-            .total_received
-            .checked_add(subscription.amount)
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.accrued_debt = accrued_debt - debt_paid;
+        subscription.last_executed = now;
+        subscription.next_due = next_due;
+        subscription.execution_count += periods_paid + periods_accrued;
+        subscription.consecutive_failures = 0;
+        // v5: Missed-crank statistics
+        subscription.missed_periods += periods_due - 1;
+        subscription.total_crank_delay = subscription
+            .total_crank_delay
+            .saturating_add(crank_delay);
+        subscription.total_crank_tips = subscription
+            .total_crank_tips
+            .checked_add(crank_tip)
             .ok_or(SolclawError::Overflow)?;
 
         // v3: Emit subscription executed event with auto-generated memo
         emit!(SubscriptionExecutedEvent {
             sender: subscription.sender_name.clone(),
             receiver: subscription.receiver_name.clone(),
-            amount: subscription.amount,
+            amount: payment,
             memo: format!("Subscription payment #{}", subscription.execution_count),
             execution_count: subscription.execution_count,
+            periods_paid,
+            periods_skipped,
+            accrued_debt: subscription.accrued_debt,
+            cranker: ctx.accounts.cranker.key(),
+            crank_tip,
             timestamp: now,
         });
 
         msg!(
-            "Subscription executed: {} -> {}, {} USDC (execution #{})",
+            "Subscription executed: {} -> {}, {} USDC for {} period(s) (execution #{}), next due at {}",
             subscription.sender_name,
             subscription.receiver_name,
-            subscription.amount,
-            subscription.execution_count
+            payment,
+            periods_paid,
+            subscription.execution_count,
+            subscription.next_due
         );
 
+        // v5: Complete right away if no further payment fits within the limits
+        if subscription.limit_reached() {
+            complete_subscription(subscription, now)?;
+        }
+
         Ok(())
     }
 
-    /// Cancel a subscription. Only the sender (authority) can cancel.
-    pub fn cancel_subscription(ctx: Context<CancelSubscription>) -> Result<()> {
+    /// v5: Record that a due subscription can't be paid right now because the
+    /// sender vault or spending cap falls short. ANYONE can call this; the
+    /// shortfall is checked on-chain and at most one failure is recorded per
+    /// FAILURE_RETRY_SECONDS. After `max_consecutive_failures` in a row the
+    /// subscription is suspended until the sender resumes it. Under Accrue a
+    /// shortfall is billed as debt by execute_subscription instead.
+    pub fn record_failed_execution(ctx: Context<ExecuteSubscription>) -> Result<()> {
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+
         let subscription = &mut ctx.accounts.subscription;
 
+        require!(subscription.is_active, SolclawError::SubscriptionNotActive);
+        require!(subscription.paused_at == 0, SolclawError::SubscriptionPaused);
+        require!(subscription.suspended_at == 0, SolclawError::SubscriptionSuspended);
         require!(
-            ctx.accounts.authority.key() == subscription.authority,
-            SolclawError::Unauthorized
+            subscription.missed_period_policy != MissedPeriodPolicy::Accrue,
+            SolclawError::SubscriptionAccruesDebt
         );
 
-        subscription.is_active = false;
+        apply_subscription_change(subscription, now)?;
+        require!(now >= subscription.next_due, SolclawError::SubscriptionNotDue);
+        require!(
+            subscription.last_failure_at == 0
+                || now >= subscription.last_failure_at + FAILURE_RETRY_SECONDS,
+            SolclawError::FailureRecordedRecently
+        );
+
+        let reason = if ctx.accounts.sender_vault.amount < subscription.amount {
+            SubscriptionFailureReason::InsufficientFunds
+        } else if remaining_spending_cap(&ctx.accounts.sender_registry, &clock) < subscription.amount {
+            SubscriptionFailureReason::SpendingCapExceeded
+        } else {
+            return err!(SolclawError::SubscriptionPaymentPossible);
+        };
+
+        let suspended = subscription.record_failure(reason, now);
+
+        emit!(SubscriptionPaymentFailedEvent {
+            subscription: subscription.key(),
+            sender: subscription.sender_name.clone(),
+            receiver: subscription.receiver_name.clone(),
+            amount: subscription.amount,
+            reason,
+            consecutive_failures: subscription.consecutive_failures,
+            suspended,
+            timestamp: now,
+        });
 
         msg!(
-            "Subscription cancelled: {} -> {}, total paid: {} USDC over {} executions",
+            "Subscription payment failed: {} -> {}, {:?} ({} in a row){}",
             subscription.sender_name,
             subscription.receiver_name,
-            subscription.total_paid,
-            subscription.execution_count
+            reason,
+            subscription.consecutive_failures,
+            if suspended { ", subscription suspended" } else { "" }
         );
 
         Ok(())
     }
 
-    // ============================================================
-    // v3: SPENDING CAP
-    // ============================================================
-
-    /// Set or remove a daily spending limit for an agent.
-    /// Only the agent's authority can call this.
-    /// Set to 0 to remove the limit.
-    pub fn set_daily_limit(ctx: Context<SetDailyLimit>, limit_usdc: u64) -> Result<()> {
-        let registry = &mut ctx.accounts.registry;
+    /// v5: Set how many consecutive failed executions suspend the
+    /// subscription (0 = never). Only the sender can set it.
+    pub fn set_subscription_max_failures(
+        ctx: Context<UpdateSubscription>,
+        max_consecutive_failures: u8,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.max_consecutive_failures = max_consecutive_failures;
 
-        require!(
-            ctx.accounts.authority.key() == registry.authority,
-            SolclawError::Unauthorized
+        msg!(
+            "Subscription max consecutive failures set: {} -> {}, {}",
+            subscription.sender_name,
+            subscription.receiver_name,
+            max_consecutive_failures
         );
 
-        registry.daily_limit = limit_usdc;
+        Ok(())
+    }
 
-        emit!(DailyLimitSetEvent {
-            agent: registry.name.clone(),
-            limit_usdc,
-            timestamp: Clock::get()?.unix_timestamp,
+    /// v5: Pay down debt accrued under MissedPeriodPolicy::Accrue with whatever
+    /// the sender vault and spending cap allow. Permissionless, like execute.
+    pub fn settle_subscription_debt(ctx: Context<ExecuteSubscription>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        let debt = ctx.accounts.subscription.accrued_debt;
+        let amount = debt
+            .min(ctx.accounts.sender_vault.amount)
+            .min(remaining_spending_cap(&ctx.accounts.sender_registry, &clock));
+        require!(amount > 0, SolclawError::NoSubscriptionDebt);
+
+        pay_subscription(ctx.accounts, amount, &clock)?;
+
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.accrued_debt = debt - amount;
+
+        emit!(SubscriptionDebtSettledEvent {
+            subscription: subscription.key(),
+            sender: subscription.sender_name.clone(),
+            receiver: subscription.receiver_name.clone(),
+            amount,
+            remaining_debt: subscription.accrued_debt,
+            timestamp: clock.unix_timestamp,
         });
 
         msg!(
-            "Daily limit set for {}: {} USDC",
-            registry.name,
-            if limit_usdc == 0 { "unlimited".to_string() } else { format!("{}", limit_usdc as f64 / 1_000_000.0) }
+            "Subscription debt settled: {} -> {}, {} USDC paid, {} USDC outstanding",
+            subscription.sender_name,
+            subscription.receiver_name,
+            amount,
+            subscription.accrued_debt
         );
 
         Ok(())
     }
 
-    // ============================================================
-    // v3: ALLOWANCE (Approve / TransferFrom)
-    // ============================================================
+    /// Cancel a subscription. Only the sender (authority) can cancel.
+    pub fn cancel_subscription(ctx: Context<CancelSubscription>) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
 
-    /// Approve another agent to pull USDC from your vault, up to `amount`.
-    /// If an allowance already exists, this REPLACES the amount (not adds to it).
-    pub fn approve(
-        ctx: Context<Approve>,
-        spender_name: String,
-        amount: u64,
-    ) -> Result<()> {
-        let owner_registry = &ctx.accounts.owner_registry;
-        let spender_registry = &ctx.accounts.spender_registry;
-
-        // Verify caller is the owner
         require!(
-            ctx.accounts.authority.key() == owner_registry.authority,
+            ctx.accounts.authority.key() == subscription.authority,
             SolclawError::Unauthorized
         );
 
-        // Verify spender name matches
-        let mut expected_hash = [0u8; 32];
-        let name_bytes = spender_name.as_bytes();
-        expected_hash[..name_bytes.len().min(32)].copy_from_slice(&name_bytes[..name_bytes.len().min(32)]);
-        require!(
-            spender_registry.name_hash == expected_hash,
-            SolclawError::NameMismatch
-        );
+        subscription.is_active = false;
 
-        // Can't approve yourself
-        require!(
-            owner_registry.key() != spender_registry.key(),
-            SolclawError::CannotApproveSelf
+        msg!(
+            "Subscription cancelled: {} -> {}, total paid: {} USDC over {} executions",
+            subscription.sender_name,
+            subscription.receiver_name,
+            subscription.total_paid,
+            subscription.execution_count
         );
 
-        let allowance = &mut ctx.accounts.allowance;
-        allowance.owner = owner_registry.key();
-        allowance.spender = spender_registry.key();
-        allowance.owner_name = owner_registry.name.clone();
-        allowance.spender_name = spender_name.clone();
-        allowance.amount = amount;
-        allowance.total_pulled = 0;
-        allowance.pull_count = 0;
-        allowance.is_active = true;
-        allowance.authority = ctx.accounts.authority.key();
-        allowance.bump = ctx.bumps.allowance;
+        Ok(())
+    }
 
-        emit!(AllowanceApprovedEvent {
-            owner: owner_registry.name.clone(),
-            spender: spender_name,
-            amount,
-            timestamp: Clock::get()?.unix_timestamp,
+    /// v5: Temporarily suspend an active subscription. Only the sender
+    /// (authority) can pause; nothing can be executed until it is resumed.
+    pub fn pause_subscription(ctx: Context<PauseSubscription>) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+
+        require!(subscription.is_active, SolclawError::SubscriptionNotActive);
+        require!(subscription.paused_at == 0, SolclawError::SubscriptionPaused);
+
+        let now = Clock::get()?.unix_timestamp;
+        subscription.paused_at = now;
+
+        emit!(SubscriptionPausedEvent {
+            subscription: subscription.key(),
+            sender: subscription.sender_name.clone(),
+            receiver: subscription.receiver_name.clone(),
+            next_due: subscription.next_due,
+            timestamp: now,
         });
 
         msg!(
-            "Allowance approved: {} can pull up to {} USDC from {}",
-            allowance.spender_name,
-            amount as f64 / 1_000_000.0,
-            allowance.owner_name
+            "Subscription paused: {} -> {}",
+            subscription.sender_name,
+            subscription.receiver_name
         );
 
         Ok(())
     }
 
-    /// Pull USDC from an owner's vault using an approved allowance.
-    /// The SPENDER calls this (not the owner).
-    pub fn transfer_from(
-        ctx: Context<TransferFrom>,
-        amount: u64,
-        memo: Option<String>,
-    ) -> Result<()> {
-        require!(amount > 0, SolclawError::InvalidAmount);
-
-        if let Some(ref m) = memo {
-            require!(m.len() <= 128, SolclawError::MemoTooLong);
-        }
-
-        let allowance = &mut ctx.accounts.allowance;
-        let owner_registry = &mut ctx.accounts.owner_registry;
-        let spender_registry = &mut ctx.accounts.spender_registry;
+    /// v5: Resume a paused or suspended subscription. The schedule is shifted
+    /// by the paused duration, so the paused period is never charged. A
+    /// suspension doesn't shift it: periods missed while suspended are handled
+    /// by the missed-period policy on the next execution.
+    pub fn resume_subscription(ctx: Context<PauseSubscription>) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
 
-        // Verify the spender is the one calling
+        require!(subscription.is_active, SolclawError::SubscriptionNotActive);
         require!(
-            ctx.accounts.spender_authority.key() == spender_registry.authority,
-            SolclawError::Unauthorized
+            subscription.paused_at != 0 || subscription.suspended_at != 0,
+            SolclawError::SubscriptionNotPaused
         );
 
-        // Verify allowance is active
-        require!(allowance.is_active, SolclawError::AllowanceNotActive);
+        let now = Clock::get()?.unix_timestamp;
+        let paused_seconds = subscription.resume(now).ok_or(SolclawError::InvalidSchedule)?;
 
-        // Verify allowance matches the owner and spender
-        require!(
-            allowance.owner == owner_registry.key(),
-            SolclawError::AllowanceMismatch
-        );
-        require!(
-            allowance.spender == spender_registry.key(),
-            SolclawError::AllowanceMismatch
-        );
+        emit!(SubscriptionResumedEvent {
+            subscription: subscription.key(),
+            sender: subscription.sender_name.clone(),
+            receiver: subscription.receiver_name.clone(),
+            paused_seconds,
+            next_due: subscription.next_due,
+            timestamp: now,
+        });
 
-        // Verify enough allowance remaining
-        require!(
-            amount <= allowance.amount,
-            SolclawError::AllowanceExceeded
+        msg!(
+            "Subscription resumed: {} -> {} after {} seconds, next due at {}",
+            subscription.sender_name,
+            subscription.receiver_name,
+            paused_seconds,
+            subscription.next_due
         );
 
-        // Check owner's spending cap (the owner's daily limit still applies!)
-        let clock = Clock::get()?;
-        check_and_update_spending_cap(owner_registry, amount, &clock)?;
-
-        // Execute the transfer from owner's vault to spender's vault
-        let name_bytes = owner_registry.name.as_bytes();
-        let seeds = &[
-            b"vault",
-            name_bytes,
-            &[owner_registry.vault_bump],
-        ];
-        let signer_seeds = &[&seeds[..]];
-
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.owner_vault.to_account_info(),
-                    to: ctx.accounts.spender_vault.to_account_info(),
-                    authority: ctx.accounts.owner_vault.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            amount,
-        )?;
+        Ok(())
+    }
 
-        // Decrease allowance
-        allowance.amount = allowance.amount
-            .checked_sub(amount)
-            .ok_or(SolclawError::Overflow)?;
-        allowance.total_pulled = allowance.total_pulled
-            .checked_add(amount)
-            .ok_or(SolclawError::Overflow)?;
-        allowance.pull_count += 1;
+    /// v5: Close an inactive subscription and return its rent to the original
+    /// payer. Only the sender (authority) can close, once any accrued debt is
+    /// settled.
+    pub fn close_subscription(ctx: Context<CloseSubscription>) -> Result<()> {
+        let subscription = &ctx.accounts.subscription;
 
-        // Update stats
-        owner_registry.total_sent = owner_registry.total_sent
-            .checked_add(amount)
-            .ok_or(SolclawError::Overflow)?;
-        spender_registry.total_received = spender_registry.total_received
-            .checked_add(amount)
-            .ok_or(SolclawError::Overflow)?;
+        require!(!subscription.is_active, SolclawError::SubscriptionStillActive);
+        // v5: Debt accrued under the Accrue policy must be settled first
+        require!(subscription.accrued_debt == 0, SolclawError::SubscriptionDebtOutstanding);
 
-        emit!(TransferFromEvent {
-            owner: owner_registry.name.clone(),
-            spender: spender_registry.name.clone(),
-            amount,
-            memo: memo.unwrap_or_default(),
-            remaining_allowance: allowance.amount,
-            pull_number: allowance.pull_count,
-            timestamp: clock.unix_timestamp,
+        emit!(SubscriptionClosedEvent {
+            subscription: subscription.key(),
+            sender: subscription.sender_name.clone(),
+            receiver: subscription.receiver_name.clone(),
+            total_paid: subscription.total_paid,
+            execution_count: subscription.execution_count,
+            timestamp: Clock::get()?.unix_timestamp,
         });
 
         msg!(
-            "TransferFrom: {} pulled {} USDC from {} (remaining allowance: {})",
-            spender_registry.name,
-            amount as f64 / 1_000_000.0,
-            owner_registry.name,
-            allowance.amount as f64 / 1_000_000.0
+            "Subscription closed: {} -> {}, total paid: {} USDC over {} executions",
+            subscription.sender_name,
+            subscription.receiver_name,
+            subscription.total_paid as f64 / 1_000_000.0,
+            subscription.execution_count
         );
 
         Ok(())
     }
 
-    /// Revoke an allowance. Only the owner can revoke.
-    pub fn revoke_allowance(ctx: Context<RevokeAllowance>) -> Result<()> {
-        let allowance = &mut ctx.accounts.allowance;
-
+    /// v5: Move a pre-v5 subscription, seeded by ["subscription", sender,
+    /// receiver], to the current layout at ["subscription", sender, receiver,
+    /// subscription_id]. Keeps its amount, interval, due date and totals; the
+    /// legacy account is closed and its rent returned to the authority.
+    /// Legacy accounts can't be used by any other instruction.
+    pub fn migrate_subscription(
+        ctx: Context<MigrateSubscription>,
+        subscription_id: u64,
+    ) -> Result<()> {
+        let legacy = LegacySubscription::load(&ctx.accounts.legacy_subscription)?;
         require!(
-            ctx.accounts.authority.key() == allowance.authority,
+            legacy.authority == ctx.accounts.authority.key(),
             SolclawError::Unauthorized
         );
 
-        allowance.is_active = false;
-        allowance.amount = 0;
+        let now = Clock::get()?.unix_timestamp;
 
-        emit!(AllowanceRevokedEvent {
-            owner: allowance.owner_name.clone(),
-            spender: allowance.spender_name.clone(),
-            total_pulled: allowance.total_pulled,
-            pull_count: allowance.pull_count,
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.id = subscription_id;
+        subscription.sender = legacy.sender;
+        subscription.receiver = legacy.receiver;
+        subscription.sender_name = legacy.sender_name;
+        subscription.receiver_name = legacy.receiver_name;
+        subscription.authority = legacy.authority;
+        subscription.payer = ctx.accounts.authority.key();
+        subscription.offer = Pubkey::default();
+        subscription.terms_hash = [0u8; 32];
+        subscription.bump = ctx.bumps.subscription;
+        // Pre-v5 cranks always restarted the interval, which is the Skip policy
+        subscription.start(
+            legacy.amount,
+            Schedule::Interval { seconds: legacy.interval_seconds },
+            SubscriptionLimits::default(),
+            MissedPeriodPolicy::Skip,
+            now,
+        )?;
+        subscription.last_executed = legacy.last_executed;
+        subscription.next_due = legacy.next_due;
+        subscription.is_active = legacy.is_active;
+        subscription.total_paid = legacy.total_paid;
+        subscription.execution_count = legacy.execution_count;
+
+        // Close the legacy account by hand: it can't be loaded as an Account
+        let legacy_info = ctx.accounts.legacy_subscription.to_account_info();
+        let authority_info = ctx.accounts.authority.to_account_info();
+        let rent = legacy_info.lamports();
+        **authority_info.try_borrow_mut_lamports()? = authority_info
+            .lamports()
+            .checked_add(rent)
+            .ok_or(SolclawError::Overflow)?;
+        **legacy_info.try_borrow_mut_lamports()? = 0;
+        legacy_info.assign(&system_program::ID);
+        legacy_info.resize(0)?;
 
         msg!(
-            "Allowance revoked: {} can no longer pull from {}",
-            allowance.spender_name,
-            allowance.owner_name
+            "Subscription migrated to #{}: {} -> {}, {} USDC every {}s",
+            subscription_id,
+            subscription.sender_name,
+            subscription.receiver_name,
+            subscription.amount,
+            legacy.interval_seconds
         );
 
         Ok(())
     }
 
-    /// Increase an existing allowance by a given amount.
-    pub fn increase_allowance(
-        ctx: Context<ModifyAllowance>,
-        additional_amount: u64,
+    /// v5: Propose new terms, effective from the first payment due at or after
+    /// `effective_at`. Only the receiver can propose. A lower price on the same
+    /// schedule is agreed right away; anything else waits for the sender's
+    /// accept_subscription_change. A new proposal replaces a pending one.
+    pub fn propose_subscription_change(
+        ctx: Context<ProposeSubscriptionChange>,
+        new_amount: u64,
+        new_schedule: Schedule,
+        effective_at: i64,
     ) -> Result<()> {
-        let allowance = &mut ctx.accounts.allowance;
+        let subscription = &mut ctx.accounts.subscription;
+        require!(subscription.is_active, SolclawError::SubscriptionNotActive);
+        validate_subscription_terms(new_amount, &new_schedule, &SubscriptionLimits::default())?;
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(effective_at >= now, SolclawError::InvalidEffectiveDate);
+
+        let accepted = new_amount <= subscription.amount && new_schedule == subscription.schedule;
+        subscription.pending_change = Some(PendingSubscriptionChange {
+            amount: new_amount,
+            schedule: new_schedule,
+            effective_at,
+            proposed_at: now,
+            accepted,
+        });
+
+        emit!(SubscriptionChangeProposedEvent {
+            subscription: subscription.key(),
+            sender: subscription.sender_name.clone(),
+            receiver: subscription.receiver_name.clone(),
+            current_amount: subscription.amount,
+            new_amount,
+            effective_at,
+            needs_consent: !accepted,
+            timestamp: now,
+        });
+
+        msg!(
+            "Subscription change proposed: {} -> {}, {} USDC on {:?} from {}{}",
+            subscription.sender_name,
+            subscription.receiver_name,
+            new_amount,
+            new_schedule,
+            effective_at,
+            if accepted { "" } else { ", awaiting sender consent" }
+        );
 
+        apply_subscription_change(subscription, now)
+    }
+
+    /// v5: Consent to the receiver's pending change. Only the sender can accept.
+    /// The expected terms must match the pending change, so the receiver can't
+    /// swap them between the sender reading the proposal and accepting it.
+    pub fn accept_subscription_change(
+        ctx: Context<UpdateSubscription>,
+        expected_amount: u64,
+        expected_schedule: Schedule,
+        expected_effective_at: i64,
+    ) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        require!(subscription.is_active, SolclawError::SubscriptionNotActive);
+
+        let change = subscription
+            .pending_change
+            .as_mut()
+            .ok_or(SolclawError::NoPendingSubscriptionChange)?;
+        require!(!change.accepted, SolclawError::NoPendingSubscriptionChange);
         require!(
-            ctx.accounts.authority.key() == allowance.authority,
-            SolclawError::Unauthorized
+            change.amount == expected_amount
+                && change.schedule == expected_schedule
+                && change.effective_at == expected_effective_at,
+            SolclawError::SubscriptionChangeMismatch
         );
-        require!(allowance.is_active, SolclawError::AllowanceNotActive);
+        change.accepted = true;
+        let (amount, effective_at) = (change.amount, change.effective_at);
 
-        allowance.amount = allowance.amount
-            .checked_add(additional_amount)
-            .ok_or(SolclawError::Overflow)?;
+        msg!(
+            "Subscription change accepted: {} -> {}, {} USDC from {}",
+            subscription.sender_name,
+            subscription.receiver_name,
+            amount,
+            effective_at
+        );
 
-        emit!(AllowanceModifiedEvent {
-            owner: allowance.owner_name.clone(),
-            spender: allowance.spender_name.clone(),
-            new_amount: allowance.amount,
-            action: "increase".to_string(),
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+        let now = Clock::get()?.unix_timestamp;
+        apply_subscription_change(subscription, now)
+    }
+
+    /// v5: Set the tip paid to whoever cranks this subscription. `crank_tip`
+    /// of 0 falls back to the protocol default; either way the tip never
+    /// exceeds `max_crank_tip` (0 = never tip). Only the sender can set it.
+    pub fn set_subscription_crank_tip(
+        ctx: Context<UpdateSubscription>,
+        crank_tip: u64,
+        max_crank_tip: u64,
+    ) -> Result<()> {
+        require!(crank_tip <= max_crank_tip, SolclawError::InvalidCrankTip);
+
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.crank_tip = crank_tip;
+        subscription.max_crank_tip = max_crank_tip;
 
         msg!(
-            "Allowance increased: {} can now pull up to {} USDC from {}",
-            allowance.spender_name,
-            allowance.amount as f64 / 1_000_000.0,
-            allowance.owner_name
+            "Subscription crank tip set: {} -> {}, tip {} (max {})",
+            subscription.sender_name,
+            subscription.receiver_name,
+            crank_tip,
+            max_crank_tip
         );
 
         Ok(())
     }
 
     // ============================================================
-    // v4: INVOICE SYSTEM
+    // v5: SUBSCRIPTION OFFERS
     // ============================================================
 
-    /// Initialize the global invoice counter. Call once after program deploy.
-    pub fn init_invoice_counter(ctx: Context<InitInvoiceCounter>) -> Result<()> {
-        let counter = &mut ctx.accounts.counter;
-        counter.count = 0;
-        counter.bump = ctx.bumps.counter;
-        msg!("Invoice counter initialized");
-        Ok(())
-    }
-
-    /// Create a payment request (invoice).
-    /// The requester asks the payer for a specific amount.
-    pub fn create_invoice(
-        ctx: Context<CreateInvoice>,
-        payer_name: String,
+    /// Publish a subscription offer that customers accept with accept_offer.
+    /// Only the receiver's authority can create it.
+    pub fn create_subscription_offer(
+        ctx: Context<CreateSubscriptionOffer>,
+        offer_id: u64,
         amount: u64,
-        memo: String,
-        expires_in_seconds: i64,
+        schedule: Schedule,
+        missed_period_policy: MissedPeriodPolicy,
+        terms_hash: [u8; 32],
     ) -> Result<()> {
-        require!(amount > 0, SolclawError::InvalidAmount);
-        require!(memo.len() <= 128, SolclawError::MemoTooLong);
-        require!(expires_in_seconds >= 0, SolclawError::InvalidExpiry);
+        validate_subscription_terms(amount, &schedule, &SubscriptionLimits::default())?;
 
-        let requester_registry = &ctx.accounts.requester_registry;
-        let payer_registry = &ctx.accounts.payer_registry;
+        let now = Clock::get()?.unix_timestamp;
+        let receiver_registry = &ctx.accounts.receiver_registry;
 
-        // Verify caller is the requester
-        require!(
-            ctx.accounts.authority.key() == requester_registry.authority,
-            SolclawError::Unauthorized
-        );
+        let offer = &mut ctx.accounts.offer;
+        offer.id = offer_id;
+        offer.receiver = receiver_registry.key();
+        offer.receiver_name = receiver_registry.name.clone();
+        offer.amount = amount;
+        offer.schedule = schedule;
+        offer.missed_period_policy = missed_period_policy;
+        offer.terms_hash = terms_hash;
+        offer.version = 1;
+        offer.subscriber_count = 0;
+        offer.created_at = now;
+        offer.updated_at = now;
+        offer.authority = ctx.accounts.authority.key();
+        offer.bump = ctx.bumps.offer;
+
+        emit!(SubscriptionOfferUpdatedEvent {
+            offer: offer.key(),
+            receiver: offer.receiver_name.clone(),
+            amount,
+            terms_hash,
+            version: offer.version,
+            timestamp: now,
+        });
 
-        // Verify payer name matches
-        let mut expected_hash = [0u8; 32];
-        let name_bytes = payer_name.as_bytes();
-        expected_hash[..name_bytes.len().min(32)].copy_from_slice(&name_bytes[..name_bytes.len().min(32)]);
-        require!(
-            payer_registry.name_hash == expected_hash,
-            SolclawError::NameMismatch
+        msg!(
+            "Subscription offer #{} created by {}: {} USDC on {:?}",
+            offer_id,
+            offer.receiver_name,
+            amount,
+            schedule
         );
 
-        // Can't invoice yourself
-        require!(
-            requester_registry.key() != payer_registry.key(),
-            SolclawError::CannotInvoiceSelf
-        );
+        Ok(())
+    }
 
-        // Increment global counter
-        let counter = &mut ctx.accounts.counter;
-        let invoice_id = counter.count;
-        counter.count = counter.count
-            .checked_add(1)
-            .ok_or(SolclawError::Overflow)?;
+    /// Change an offer's terms for future subscribers and bump its version.
+    /// Subscriptions already created from it keep the terms they accepted.
+    pub fn update_subscription_offer(
+        ctx: Context<UpdateSubscriptionOffer>,
+        amount: u64,
+        schedule: Schedule,
+        missed_period_policy: MissedPeriodPolicy,
+        terms_hash: [u8; 32],
+    ) -> Result<()> {
+        validate_subscription_terms(amount, &schedule, &SubscriptionLimits::default())?;
 
         let now = Clock::get()?.unix_timestamp;
 
-        let invoice = &mut ctx.accounts.invoice;
-        invoice.id = invoice_id;
-        invoice.requester = requester_registry.key();
-        invoice.payer = payer_registry.key();
-        invoice.requester_name = requester_registry.name.clone();
-        invoice.payer_name = payer_name.clone();
-        invoice.amount = amount;
-        invoice.memo = memo.clone();
-        invoice.status = Invoice::STATUS_PENDING;
-        invoice.created_at = now;
-        invoice.expires_at = if expires_in_seconds > 0 {
-            now + expires_in_seconds
-        } else {
-            0 // Never expires
-        };
-        invoice.paid_at = 0;
-        invoice.authority = ctx.accounts.authority.key();
-        invoice.bump = ctx.bumps.invoice;
-
-        emit!(InvoiceCreatedEvent {
-            invoice_id,
-            requester: requester_registry.name.clone(),
-            payer: payer_name,
+        let offer = &mut ctx.accounts.offer;
+        offer.amount = amount;
+        offer.schedule = schedule;
+        offer.missed_period_policy = missed_period_policy;
+        offer.terms_hash = terms_hash;
+        offer.version = offer.version.checked_add(1).ok_or(SolclawError::Overflow)?;
+        offer.updated_at = now;
+
+        emit!(SubscriptionOfferUpdatedEvent {
+            offer: offer.key(),
+            receiver: offer.receiver_name.clone(),
             amount,
-            memo,
-            expires_at: invoice.expires_at,
+            terms_hash,
+            version: offer.version,
             timestamp: now,
         });
 
         msg!(
-            "Invoice #{} created: {} requesting {} USDC from {}",
-            invoice_id,
-            requester_registry.name,
-            amount as f64 / 1_000_000.0,
-            invoice.payer_name
+            "Subscription offer #{} updated to version {}: {} USDC on {:?}",
+            offer.id,
+            offer.version,
+            amount,
+            schedule
         );
 
         Ok(())
     }
 
-    /// Pay a pending invoice. Only the designated payer can call this.
-    /// Transfers USDC and marks the invoice as paid in one atomic TX.
-    pub fn pay_invoice(ctx: Context<PayInvoice>) -> Result<()> {
-        let invoice = &mut ctx.accounts.invoice;
-        let payer_registry = &mut ctx.accounts.payer_registry;
-        let requester_registry = &mut ctx.accounts.requester_registry;
-
-        // Verify invoice is pending
-        require!(
-            invoice.status == Invoice::STATUS_PENDING,
-            SolclawError::InvoiceNotPending
+    /// Withdraw an offer and return its rent. Existing subscriptions continue.
+    pub fn close_subscription_offer(ctx: Context<CloseSubscriptionOffer>) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        msg!(
+            "Subscription offer #{} closed by {} after {} subscriber(s)",
+            offer.id,
+            offer.receiver_name,
+            offer.subscriber_count
         );
+        Ok(())
+    }
 
-        // Verify caller is the payer
-        require!(
-            ctx.accounts.authority.key() == payer_registry.authority,
-            SolclawError::Unauthorized
-        );
+    /// Subscribe to an offer on its current terms. Signed by the sender;
+    /// `expected_version` must match so the receiver can't swap the terms
+    /// between the customer reading the offer and accepting it. `limits`
+    /// are the sender's own bounds on top of the offer.
+    pub fn accept_offer(
+        ctx: Context<AcceptOffer>,
+        subscription_id: u64,
+        expected_version: u32,
+        limits: SubscriptionLimits,
+    ) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        require!(offer.version == expected_version, SolclawError::OfferVersionMismatch);
+        validate_subscription_terms(offer.amount, &offer.schedule, &limits)?;
 
-        // Check expiry
         let now = Clock::get()?.unix_timestamp;
-        if invoice.expires_at > 0 {
-            require!(
-                now <= invoice.expires_at,
-                SolclawError::InvoiceExpired
-            );
-        }
-
-        // Check spending cap BEFORE transfer
-        let clock = Clock::get()?;
-        check_and_update_spending_cap(payer_registry, invoice.amount, &clock)?;
-
-        // Execute USDC transfer: payer vault → requester vault
-        let name_bytes = payer_registry.name.as_bytes();
-        let seeds = &[
-            b"vault",
-            name_bytes,
-            &[payer_registry.vault_bump],
-        ];
-        let signer_seeds = &[&seeds[..]];
-
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.payer_vault.to_account_info(),
-                    to: ctx.accounts.requester_vault.to_account_info(),
-                    authority: ctx.accounts.payer_vault.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            invoice.amount,
-        )?;
+        let sender_registry = &ctx.accounts.sender_registry;
+        let receiver_registry = &ctx.accounts.receiver_registry;
 
-        // Update invoice status
-        invoice.status = Invoice::STATUS_PAID;
-        invoice.paid_at = now;
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.id = subscription_id;
+        subscription.sender = sender_registry.key();
+        subscription.receiver = receiver_registry.key();
+        subscription.sender_name = sender_registry.name.clone();
+        subscription.receiver_name = receiver_registry.name.clone();
+        subscription.authority = ctx.accounts.authority.key();
+        subscription.payer = ctx.accounts.payer.key();
+        subscription.offer = offer.key();
+        subscription.terms_hash = offer.terms_hash;
+        subscription.bump = ctx.bumps.subscription;
+        subscription.start(offer.amount, offer.schedule, limits, offer.missed_period_policy, now)?;
 
-        // Update stats
-        payer_registry.total_sent = payer_registry.total_sent
-            .checked_add(invoice.amount)
-            .ok_or(SolclawError::Overflow)?;
-        requester_registry.total_received = requester_registry.total_received
-            .checked_add(invoice.amount)
-            .ok_or(SolclawError::Overflow)?;
+        let offer = &mut ctx.accounts.offer;
+        offer.subscriber_count += 1;
 
-        emit!(InvoicePaidEvent {
-            invoice_id: invoice.id,
-            requester: invoice.requester_name.clone(),
-            payer: invoice.payer_name.clone(),
-            amount: invoice.amount,
-            memo: invoice.memo.clone(),
+        emit!(SubscriptionOfferAcceptedEvent {
+            offer: offer.key(),
+            subscription: subscription.key(),
+            sender: subscription.sender_name.clone(),
+            receiver: subscription.receiver_name.clone(),
+            amount: subscription.amount,
+            version: offer.version,
             timestamp: now,
         });
 
         msg!(
-            "Invoice #{} paid: {} USDC from {} to {}",
-            invoice.id,
-            invoice.amount as f64 / 1_000_000.0,
-            invoice.payer_name,
-            invoice.requester_name
+            "Subscription #{} created from offer #{} (v{}): {} -> {}, {} USDC, first due at {}",
+            subscription_id,
+            offer.id,
+            offer.version,
+            subscription.sender_name,
+            subscription.receiver_name,
+            subscription.amount,
+            subscription.next_due
         );
 
         Ok(())
     }
 
-    /// Reject a pending invoice. Only the designated payer can reject.
-    pub fn reject_invoice(ctx: Context<RejectInvoice>) -> Result<()> {
-        let invoice = &mut ctx.accounts.invoice;
+    // ============================================================
+    // v5: PROTOCOL CONFIG
+    // ============================================================
 
-        require!(
-            invoice.status == Invoice::STATUS_PENDING,
-            SolclawError::InvoiceNotPending
-        );
+    /// Initialize the global protocol config. Call once after program deploy.
+    /// Only the program's upgrade authority can call it, and it becomes the
+    /// config authority, so nobody can claim the config first.
+    pub fn init_protocol_config(
+        ctx: Context<InitProtocolConfig>,
+        default_crank_tip: u64,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        config.authority = ctx.accounts.authority.key();
+        config.default_crank_tip = default_crank_tip;
+        config.bump = ctx.bumps.protocol_config;
+        msg!("Protocol config initialized, default crank tip {}", default_crank_tip);
+        Ok(())
+    }
+
+    /// Update the default crank tip used by subscriptions without their own.
+    /// Only the config authority can call this.
+    pub fn set_default_crank_tip(
+        ctx: Context<UpdateProtocolConfig>,
+        default_crank_tip: u64,
+    ) -> Result<()> {
+        ctx.accounts.protocol_config.default_crank_tip = default_crank_tip;
+        msg!("Default crank tip set to {}", default_crank_tip);
+        Ok(())
+    }
+
+    // ============================================================
+    // v3: SPENDING CAP
+    // ============================================================
+
+    /// Set or remove a daily spending limit for an agent.
+    /// Only the agent's authority can call this.
+    /// Set to 0 to remove the limit.
+    pub fn set_daily_limit(ctx: Context<SetDailyLimit>, limit_usdc: u64) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
 
-        // Only the payer can reject
-        let payer_registry = &ctx.accounts.payer_registry;
         require!(
-            ctx.accounts.authority.key() == payer_registry.authority,
+            ctx.accounts.authority.key() == registry.authority,
             SolclawError::Unauthorized
         );
-        require!(
-            invoice.payer == payer_registry.key(),
-            SolclawError::InvoiceMismatch
-        );
 
-        invoice.status = Invoice::STATUS_REJECTED;
+        registry.daily_limit = limit_usdc;
 
-        emit!(InvoiceRejectedEvent {
-            invoice_id: invoice.id,
-            requester: invoice.requester_name.clone(),
-            payer: invoice.payer_name.clone(),
-            amount: invoice.amount,
+        emit!(DailyLimitSetEvent {
+            agent: registry.name.clone(),
+            limit_usdc,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
         msg!(
-            "Invoice #{} rejected by {}",
-            invoice.id,
-            invoice.payer_name
+            "Daily limit set for {}: {} USDC",
+            registry.name,
+            if limit_usdc == 0 { "unlimited".to_string() } else { format!("{}", limit_usdc as f64 / 1_000_000.0) }
         );
 
         Ok(())
     }
 
-    /// Cancel a pending invoice. Only the requester (creator) can cancel.
-    pub fn cancel_invoice(ctx: Context<CancelInvoice>) -> Result<()> {
-        let invoice = &mut ctx.accounts.invoice;
+    // ============================================================
+    // v3: ALLOWANCE (Approve / TransferFrom)
+    // ============================================================
 
-        require!(
-            invoice.status == Invoice::STATUS_PENDING,
-            SolclawError::InvoiceNotPending
-        );
+    /// Approve another agent to pull USDC from your vault, up to `amount`.
+    /// If an allowance already exists, this REPLACES the amount (not adds to it).
+    pub fn approve(
+        ctx: Context<Approve>,
+        spender_name: String,
+        amount: u64,
+    ) -> Result<()> {
+        let owner_registry = &ctx.accounts.owner_registry;
+        let spender_registry = &ctx.accounts.spender_registry;
 
-        // Only the requester can cancel
+        // Verify caller is the owner
         require!(
-            ctx.accounts.authority.key() == invoice.authority,
+            ctx.accounts.authority.key() == owner_registry.authority,
             SolclawError::Unauthorized
         );
 
-        invoice.status = Invoice::STATUS_CANCELLED;
+        // Verify spender name matches
+        let mut expected_hash = [0u8; 32];
+        let name_bytes = spender_name.as_bytes();
+        expected_hash[..name_bytes.len().min(32)].copy_from_slice(&name_bytes[..name_bytes.len().min(32)]);
+        require!(
+            spender_registry.name_hash == expected_hash,
+            SolclawError::NameMismatch
+        );
 
-        emit!(InvoiceCancelledEvent {
-            invoice_id: invoice.id,
-            requester: invoice.requester_name.clone(),
-            payer: invoice.payer_name.clone(),
-            amount: invoice.amount,
+        // Can't approve yourself
+        require!(
+            owner_registry.key() != spender_registry.key(),
+            SolclawError::CannotApproveSelf
+        );
+
+        let allowance = &mut ctx.accounts.allowance;
+        allowance.owner = owner_registry.key();
+        allowance.spender = spender_registry.key();
+        allowance.owner_name = owner_registry.name.clone();
+        allowance.spender_name = spender_name.clone();
+        allowance.amount = amount;
+        allowance.total_pulled = 0;
+        allowance.pull_count = 0;
+        allowance.is_active = true;
+        allowance.authority = ctx.accounts.authority.key();
+        allowance.bump = ctx.bumps.allowance;
+
+        emit!(AllowanceApprovedEvent {
+            owner: owner_registry.name.clone(),
+            spender: spender_name,
+            amount,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
         msg!(
-            "Invoice #{} cancelled by {}",
-            invoice.id,
-            invoice.requester_name
+            "Allowance approved: {} can pull up to {} USDC from {}",
+            allowance.spender_name,
+            amount as f64 / 1_000_000.0,
+            allowance.owner_name
         );
 
         Ok(())
     }
-}
 
-// ============================================================
-// HELPER FUNCTIONS
-// ============================================================
-
-/// Check and update spending cap. Call this BEFORE every outgoing transfer.
-/// Returns Ok(()) if the spend is allowed, Err if it exceeds the daily limit.
-fn check_and_update_spending_cap(
-    registry: &mut Account<AgentRegistry>,
-    amount: u64,
-    clock: &Clock,
-) -> Result<()> {
-    // If no limit set, allow everything
-    if registry.daily_limit == 0 {
-        return Ok(());
-    }
+    /// Pull USDC from an owner's vault using an approved allowance.
+    /// The SPENDER calls this (not the owner).
+    pub fn transfer_from(
+        ctx: Context<TransferFrom>,
+        amount: u64,
+        memo: Option<String>,
+    ) -> Result<()> {
+        require!(amount > 0, SolclawError::InvalidAmount);
 
-    let today = clock.unix_timestamp / 86400;
+        if let Some(ref m) = memo {
+            require!(m.len() <= 128, SolclawError::MemoTooLong);
+        }
 
-    // Reset daily_spent if it's a new day
-    if today != registry.last_spend_day {
-        registry.daily_spent = 0;
-        registry.last_spend_day = today;
-    }
+        let allowance = &mut ctx.accounts.allowance;
+        let owner_registry = &mut ctx.accounts.owner_registry;
+        let spender_registry = &mut ctx.accounts.spender_registry;
 
-    // Check if this spend would exceed the limit
-    let new_total = registry.daily_spent
-        .checked_add(amount)
-        .ok_or(SolclawError::Overflow)?;
+        // Verify the spender is the one calling
+        require!(
+            ctx.accounts.spender_authority.key() == spender_registry.authority,
+            SolclawError::Unauthorized
+        );
 
-    require!(
-        new_total <= registry.daily_limit,
-        SolclawError::SpendingCapExceeded
-    );
+        // Verify allowance is active
+        require!(allowance.is_active, SolclawError::AllowanceNotActive);
 
-    // Update spent amount
-    registry.daily_spent = new_total;
+        // Verify allowance matches the owner and spender
+        require!(
+            allowance.owner == owner_registry.key(),
+            SolclawError::AllowanceMismatch
+        );
+        require!(
+            allowance.spender == spender_registry.key(),
+            SolclawError::AllowanceMismatch
+        );
 
-    Ok(())
-}
+        // Verify enough allowance remaining
+        require!(
+            amount <= allowance.amount,
+            SolclawError::AllowanceExceeded
+        );
 
-// ============================================================
-// ACCOUNT STRUCTS
-// ============================================================
+        // Check owner's spending cap (the owner's daily limit still applies!)
+        let clock = Clock::get()?;
+        check_and_update_spending_cap(owner_registry, amount, &clock)?;
 
-#[derive(Accounts)]
-#[instruction(name: String)]
-pub struct RegisterAgent<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + AgentRegistry::INIT_SPACE,
-        seeds = [b"agent", name.as_bytes()],
-        bump
-    )]
-    pub agent_registry: Account<'info, AgentRegistry>,
+        // Execute the transfer from owner's vault to spender's vault
+        let name_bytes = owner_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[owner_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
 
-    #[account(
-        init,
-        payer = authority,
-        seeds = [b"vault", name.as_bytes()],
-        bump,
-        token::mint = usdc_mint,
-        token::authority = vault,
-    )]
-    pub vault: Account<'info, TokenAccount>,
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.owner_vault.to_account_info(),
+                    to: ctx.accounts.spender_vault.to_account_info(),
+                    authority: ctx.accounts.owner_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
 
-    #[account(
-        constraint = usdc_mint.key().to_string() == USDC_MINT @ SolclawError::InvalidMint
-    )]
-    pub usdc_mint: Account<'info, Mint>,
+        // Decrease allowance
+        allowance.amount = allowance.amount
+            .checked_sub(amount)
+            .ok_or(SolclawError::Overflow)?;
+        allowance.total_pulled = allowance.total_pulled
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+        allowance.pull_count += 1;
 
-    #[account(mut)]
-    pub authority: Signer<'info>,
+        // Update stats
+        owner_registry.total_sent = owner_registry.total_sent
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+        spender_registry.total_received = spender_registry.total_received
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
 
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub rent: Sysvar<'info, Rent>,
-}
+        emit!(TransferFromEvent {
+            owner: owner_registry.name.clone(),
+            spender: spender_registry.name.clone(),
+            amount,
+            memo: memo.unwrap_or_default(),
+            remaining_allowance: allowance.amount,
+            pull_number: allowance.pull_count,
+            timestamp: clock.unix_timestamp,
+        });
 
-#[derive(Accounts)]
-pub struct Deposit<'info> {
-    #[account(
-        seeds = [b"agent", agent_registry.name.as_bytes()],
-        bump = agent_registry.bump,
-    )]
-    pub agent_registry: Account<'info, AgentRegistry>,
+        msg!(
+            "TransferFrom: {} pulled {} USDC from {} (remaining allowance: {})",
+            spender_registry.name,
+            amount as f64 / 1_000_000.0,
+            owner_registry.name,
+            allowance.amount as f64 / 1_000_000.0
+        );
 
-    #[account(
-        mut,
-        seeds = [b"vault", agent_registry.name.as_bytes()],
-        bump = agent_registry.vault_bump,
-    )]
-    pub vault: Account<'info, TokenAccount>,
+        Ok(())
+    }
 
-    #[account(
-        mut,
-        constraint = user_token_account.mint == vault.mint @ SolclawError::InvalidMint
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    /// Revoke an allowance. Only the owner can revoke.
+    pub fn revoke_allowance(ctx: Context<RevokeAllowance>) -> Result<()> {
+        let allowance = &mut ctx.accounts.allowance;
 
-    pub authority: Signer<'info>,
+        require!(
+            ctx.accounts.authority.key() == allowance.authority,
+            SolclawError::Unauthorized
+        );
 
-    pub token_program: Program<'info, Token>,
-}
+        allowance.is_active = false;
+        allowance.amount = 0;
 
-#[derive(Accounts)]
-pub struct TransferByName<'info> {
-    #[account(
-        mut,
-        seeds = [b"agent", sender_registry.name.as_bytes()],
-        bump = sender_registry.bump,
-    )]
-    pub sender_registry: Account<'info, AgentRegistry>,
+        emit!(AllowanceRevokedEvent {
+            owner: allowance.owner_name.clone(),
+            spender: allowance.spender_name.clone(),
+            total_pulled: allowance.total_pulled,
+            pull_count: allowance.pull_count,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-    #[account(
-        mut,
-        seeds = [b"vault", sender_registry.name.as_bytes()],
-        bump = sender_registry.vault_bump,
-    )]
-    pub sender_vault: Account<'info, TokenAccount>,
+        msg!(
+            "Allowance revoked: {} can no longer pull from {}",
+            allowance.spender_name,
+            allowance.owner_name
+        );
 
-    #[account(
-        mut,
-        seeds = [b"agent", receiver_registry.name.as_bytes()],
-        bump = receiver_registry.bump,
-    )]
-    pub receiver_registry: Account<'info, AgentRegistry>,
+        Ok(())
+    }
 
-    #[account(
-        mut,
-        seeds = [b"vault", receiver_registry.name.as_bytes()],
+    /// Increase an existing allowance by a given amount.
+    pub fn increase_allowance(
+        ctx: Context<ModifyAllowance>,
+        additional_amount: u64,
+    ) -> Result<()> {
+        let allowance = &mut ctx.accounts.allowance;
+
+        require!(
+            ctx.accounts.authority.key() == allowance.authority,
+            SolclawError::Unauthorized
+        );
+        require!(allowance.is_active, SolclawError::AllowanceNotActive);
+
+        allowance.amount = allowance.amount
+            .checked_add(additional_amount)
+            .ok_or(SolclawError::Overflow)?;
+
+        emit!(AllowanceModifiedEvent {
+            owner: allowance.owner_name.clone(),
+            spender: allowance.spender_name.clone(),
+            new_amount: allowance.amount,
+            action: "increase".to_string(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Allowance increased: {} can now pull up to {} USDC from {}",
+            allowance.spender_name,
+            allowance.amount as f64 / 1_000_000.0,
+            allowance.owner_name
+        );
+
+        Ok(())
+    }
+
+    // ============================================================
+    // v4: INVOICE SYSTEM
+    // ============================================================
+
+    /// Initialize the global invoice counter. Call once after program deploy.
+    pub fn init_invoice_counter(ctx: Context<InitInvoiceCounter>) -> Result<()> {
+        let counter = &mut ctx.accounts.counter;
+        counter.count = 0;
+        counter.bump = ctx.bumps.counter;
+        msg!("Invoice counter initialized");
+        Ok(())
+    }
+
+    /// Create a payment request (invoice).
+    /// The requester asks the payer for a specific amount.
+    pub fn create_invoice(
+        ctx: Context<CreateInvoice>,
+        payer_name: String,
+        amount: u64,
+        memo: String,
+        expires_in_seconds: i64,
+    ) -> Result<()> {
+        require!(amount > 0, SolclawError::InvalidAmount);
+        require!(memo.len() <= 128, SolclawError::MemoTooLong);
+        require!(expires_in_seconds >= 0, SolclawError::InvalidExpiry);
+
+        let requester_registry = &ctx.accounts.requester_registry;
+        let payer_registry = &ctx.accounts.payer_registry;
+
+        // Verify caller is the requester
+        require!(
+            ctx.accounts.authority.key() == requester_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        // Verify payer name matches
+        let mut expected_hash = [0u8; 32];
+        let name_bytes = payer_name.as_bytes();
+        expected_hash[..name_bytes.len().min(32)].copy_from_slice(&name_bytes[..name_bytes.len().min(32)]);
+        require!(
+            payer_registry.name_hash == expected_hash,
+            SolclawError::NameMismatch
+        );
+
+        // Can't invoice yourself
+        require!(
+            requester_registry.key() != payer_registry.key(),
+            SolclawError::CannotInvoiceSelf
+        );
+
+        // Increment global counter
+        let counter = &mut ctx.accounts.counter;
+        let invoice_id = counter.count;
+        counter.count = counter.count
+            .checked_add(1)
+            .ok_or(SolclawError::Overflow)?;
+
+        let now = Clock::get()?.unix_timestamp;
+
+        let invoice = &mut ctx.accounts.invoice;
+        invoice.id = invoice_id;
+        invoice.requester = requester_registry.key();
+        invoice.payer = payer_registry.key();
+        invoice.requester_name = requester_registry.name.clone();
+        invoice.payer_name = payer_name.clone();
+        invoice.amount = amount;
+        invoice.memo = memo.clone();
+        invoice.status = Invoice::STATUS_PENDING;
+        invoice.created_at = now;
+        invoice.expires_at = if expires_in_seconds > 0 {
+            now + expires_in_seconds
+        } else {
+            0 // Never expires
+        };
+        invoice.paid_at = 0;
+        invoice.refunded_amount = 0;
+        invoice.authority = ctx.accounts.authority.key();
+        invoice.bump = ctx.bumps.invoice;
+
+        emit!(InvoiceCreatedEvent {
+            invoice_id,
+            requester: requester_registry.name.clone(),
+            payer: payer_name,
+            amount,
+            memo,
+            expires_at: invoice.expires_at,
+            timestamp: now,
+        });
+
+        msg!(
+            "Invoice #{} created: {} requesting {} USDC from {}",
+            invoice_id,
+            requester_registry.name,
+            amount as f64 / 1_000_000.0,
+            invoice.payer_name
+        );
+
+        Ok(())
+    }
+
+    /// Pay a pending invoice. Only the designated payer can call this.
+    /// Transfers USDC and marks the invoice as paid in one atomic TX.
+    /// v5: Read-only Solana Pay reference keys may be passed as remaining accounts
+    pub fn pay_invoice(ctx: Context<PayInvoice>) -> Result<()> {
+        // v5: Collect Solana Pay reference keys
+        let references = collect_references(ctx.remaining_accounts)?;
+
+        let invoice = &mut ctx.accounts.invoice;
+        let payer_registry = &mut ctx.accounts.payer_registry;
+        let requester_registry = &mut ctx.accounts.requester_registry;
+
+        // Verify invoice is pending
+        require!(
+            invoice.status == Invoice::STATUS_PENDING,
+            SolclawError::InvoiceNotPending
+        );
+
+        // Verify caller is the payer
+        require!(
+            ctx.accounts.authority.key() == payer_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        // Check expiry
+        let now = Clock::get()?.unix_timestamp;
+        if invoice.expires_at > 0 {
+            require!(
+                now <= invoice.expires_at,
+                SolclawError::InvoiceExpired
+            );
+        }
+
+        // Check spending cap BEFORE transfer
+        let clock = Clock::get()?;
+        check_and_update_spending_cap(payer_registry, invoice.amount, &clock)?;
+
+        // Execute USDC transfer: payer vault → requester vault
+        let name_bytes = payer_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[payer_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.payer_vault.to_account_info(),
+                    to: ctx.accounts.requester_vault.to_account_info(),
+                    authority: ctx.accounts.payer_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            invoice.amount,
+        )?;
+
+        // Update invoice status
+        invoice.status = Invoice::STATUS_PAID;
+        invoice.paid_at = now;
+
+        // Update stats
+        payer_registry.total_sent = payer_registry.total_sent
+            .checked_add(invoice.amount)
+            .ok_or(SolclawError::Overflow)?;
+        requester_registry.total_received = requester_registry.total_received
+            .checked_add(invoice.amount)
+            .ok_or(SolclawError::Overflow)?;
+
+        emit!(InvoicePaidEvent {
+            invoice_id: invoice.id,
+            requester: invoice.requester_name.clone(),
+            payer: invoice.payer_name.clone(),
+            amount: invoice.amount,
+            memo: invoice.memo.clone(),
+            references,
+            timestamp: now,
+        });
+
+        msg!(
+            "Invoice #{} paid: {} USDC from {} to {}",
+            invoice.id,
+            invoice.amount as f64 / 1_000_000.0,
+            invoice.payer_name,
+            invoice.requester_name
+        );
+
+        Ok(())
+    }
+
+    /// Reject a pending invoice. Only the designated payer can reject.
+    pub fn reject_invoice(ctx: Context<RejectInvoice>) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+
+        require!(
+            invoice.status == Invoice::STATUS_PENDING,
+            SolclawError::InvoiceNotPending
+        );
+
+        // Only the payer can reject
+        let payer_registry = &ctx.accounts.payer_registry;
+        require!(
+            ctx.accounts.authority.key() == payer_registry.authority,
+            SolclawError::Unauthorized
+        );
+        require!(
+            invoice.payer == payer_registry.key(),
+            SolclawError::InvoiceMismatch
+        );
+
+        invoice.status = Invoice::STATUS_REJECTED;
+
+        emit!(InvoiceRejectedEvent {
+            invoice_id: invoice.id,
+            requester: invoice.requester_name.clone(),
+            payer: invoice.payer_name.clone(),
+            amount: invoice.amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Invoice #{} rejected by {}",
+            invoice.id,
+            invoice.payer_name
+        );
+
+        Ok(())
+    }
+
+    /// Cancel a pending invoice. Only the requester (creator) can cancel.
+    pub fn cancel_invoice(ctx: Context<CancelInvoice>) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+
+        require!(
+            invoice.status == Invoice::STATUS_PENDING,
+            SolclawError::InvoiceNotPending
+        );
+
+        // Only the requester can cancel
+        require!(
+            ctx.accounts.authority.key() == invoice.authority,
+            SolclawError::Unauthorized
+        );
+
+        invoice.status = Invoice::STATUS_CANCELLED;
+
+        emit!(InvoiceCancelledEvent {
+            invoice_id: invoice.id,
+            requester: invoice.requester_name.clone(),
+            payer: invoice.payer_name.clone(),
+            amount: invoice.amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Invoice #{} cancelled by {}",
+            invoice.id,
+            invoice.requester_name
+        );
+
+        Ok(())
+    }
+
+    // ============================================================
+    // v5: REFUNDS
+    // ============================================================
+
+    /// Refund a paid invoice, fully or partially. Only the requester (the
+    /// original receiver) can refund. Moves USDC back to the payer's vault and
+    /// reverses the payment in both agents' stats.
+    pub fn refund(
+        ctx: Context<Refund>,
+        amount: u64,
+        memo: Option<String>,
+    ) -> Result<()> {
+        require!(amount > 0, SolclawError::InvalidAmount);
+
+        if let Some(ref m) = memo {
+            require!(m.len() <= 128, SolclawError::MemoTooLong);
+        }
+
+        let invoice = &mut ctx.accounts.invoice;
+        let requester_registry = &mut ctx.accounts.requester_registry;
+        let payer_registry = &mut ctx.accounts.payer_registry;
+
+        // Only paid invoices can be refunded
+        require!(
+            invoice.status == Invoice::STATUS_PAID,
+            SolclawError::InvoiceNotPaid
+        );
+
+        // Refunds can never exceed the original payment
+        let refunded_amount = invoice.refunded_amount
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+        require!(
+            refunded_amount <= invoice.amount,
+            SolclawError::RefundExceedsPayment
+        );
+
+        // The refund is an outgoing transfer for the requester
+        let clock = Clock::get()?;
+        check_and_update_spending_cap(requester_registry, amount, &clock)?;
+
+        // Execute USDC transfer: requester vault → payer vault
+        let name_bytes = requester_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[requester_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.requester_vault.to_account_info(),
+                    to: ctx.accounts.payer_vault.to_account_info(),
+                    authority: ctx.accounts.requester_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        // Update invoice
+        invoice.refunded_amount = refunded_amount;
+        if refunded_amount == invoice.amount {
+            invoice.status = Invoice::STATUS_REFUNDED;
+        }
+
+        // Reverse the original payment in stats
+        payer_registry.total_sent = payer_registry.total_sent.saturating_sub(amount);
+        requester_registry.total_received = requester_registry.total_received.saturating_sub(amount);
+
+        emit!(RefundEvent {
+            invoice_id: invoice.id,
+            requester: invoice.requester_name.clone(),
+            payer: invoice.payer_name.clone(),
+            amount,
+            total_refunded: invoice.refunded_amount,
+            memo: memo.unwrap_or_default(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Invoice #{} refunded: {} USDC from {} to {} ({} of {} refunded)",
+            invoice.id,
+            amount as f64 / 1_000_000.0,
+            invoice.requester_name,
+            invoice.payer_name,
+            invoice.refunded_amount as f64 / 1_000_000.0,
+            invoice.amount as f64 / 1_000_000.0
+        );
+
+        Ok(())
+    }
+
+    // ============================================================
+    // v5: ESCROW
+    // ============================================================
+
+    /// Lock USDC from the payer's vault into an escrow until it is released,
+    /// refunded after the deadline, or resolved by an optional arbiter.
+    /// `escrow_id` is a client-chosen nonce, unique per payer.
+    pub fn create_escrow(
+        ctx: Context<CreateEscrow>,
+        escrow_id: u64,
+        payee_name: String,
+        amount: u64,
+        deadline: i64,
+        arbiter: Option<Pubkey>,
+    ) -> Result<()> {
+        require!(amount > 0, SolclawError::InvalidAmount);
+
+        let payer_registry = &mut ctx.accounts.payer_registry;
+        let payee_registry = &ctx.accounts.payee_registry;
+
+        // Verify payee name matches
+        require!(
+            payee_registry.name_hash == name_to_hash(&payee_name),
+            SolclawError::NameMismatch
+        );
+
+        // Can't escrow to yourself
+        require!(
+            payer_registry.key() != payee_registry.key(),
+            SolclawError::CannotEscrowSelf
+        );
+
+        let clock = Clock::get()?;
+        require!(deadline > clock.unix_timestamp, SolclawError::InvalidDeadline);
+
+        check_and_update_spending_cap(payer_registry, amount, &clock)?;
+
+        // Move USDC: payer vault → escrow vault
+        let name_bytes = payer_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[payer_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.payer_vault.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                    authority: ctx.accounts.payer_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.id = escrow_id;
+        escrow.payer = payer_registry.key();
+        escrow.payee = payee_registry.key();
+        escrow.payer_name = payer_registry.name.clone();
+        escrow.payee_name = payee_name;
+        escrow.amount = amount;
+        escrow.deadline = deadline;
+        escrow.arbiter = arbiter;
+        escrow.status = Escrow::STATUS_ACTIVE;
+        escrow.created_at = clock.unix_timestamp;
+        escrow.settled_at = 0;
+        escrow.authority = ctx.accounts.authority.key();
+        escrow.bump = ctx.bumps.escrow;
+        escrow.vault_bump = ctx.bumps.escrow_vault;
+
+        emit!(EscrowCreatedEvent {
+            escrow: escrow.key(),
+            payer: escrow.payer_name.clone(),
+            payee: escrow.payee_name.clone(),
+            amount,
+            deadline,
+            arbiter,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Escrow #{} created: {} USDC from {} to {}, deadline {}",
+            escrow_id,
+            amount as f64 / 1_000_000.0,
+            escrow.payer_name,
+            escrow.payee_name,
+            deadline
+        );
+
+        Ok(())
+    }
+
+    /// Release escrowed USDC to the payee. The payer or the arbiter can release.
+    /// Settling closes the escrow and its vault; the rent goes to the payer's wallet.
+    pub fn release_escrow(ctx: Context<SettleEscrow>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(
+            escrow.status == Escrow::STATUS_ACTIVE,
+            SolclawError::EscrowNotActive
+        );
+
+        let signer = ctx.accounts.authority.key();
+        require!(
+            signer == ctx.accounts.payer_registry.authority || escrow.arbiter == Some(signer),
+            SolclawError::Unauthorized
+        );
+
+        let amount = escrow.amount;
+        transfer_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.payee_vault.to_account_info(),
+            escrow.key(),
+            escrow.vault_bump,
+            amount,
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        escrow.status = Escrow::STATUS_RELEASED;
+        escrow.settled_at = now;
+
+        record_escrow_payment(
+            &mut ctx.accounts.payer_registry,
+            &mut ctx.accounts.payee_registry,
+            amount,
+        )?;
+
+        close_escrow_vault(
+            &ctx.accounts.token_program,
+            &mut ctx.accounts.escrow_vault,
+            ctx.accounts.payer_vault.to_account_info(),
+            ctx.accounts.rent_receiver.to_account_info(),
+            escrow.key(),
+            escrow.vault_bump,
+        )?;
+
+        emit!(EscrowReleasedEvent {
+            escrow: escrow.key(),
+            payer: escrow.payer_name.clone(),
+            payee: escrow.payee_name.clone(),
+            amount,
+            timestamp: now,
+        });
+
+        msg!(
+            "Escrow #{} released: {} USDC to {}",
+            escrow.id,
+            amount as f64 / 1_000_000.0,
+            escrow.payee_name
+        );
+
+        Ok(())
+    }
+
+    /// Return escrowed USDC to the payer. The payer can refund once the
+    /// deadline has passed; the payee can refund voluntarily at any time.
+    pub fn refund_escrow(ctx: Context<SettleEscrow>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(
+            escrow.status == Escrow::STATUS_ACTIVE,
+            SolclawError::EscrowNotActive
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let signer = ctx.accounts.authority.key();
+        if signer != ctx.accounts.payee_registry.authority {
+            require!(
+                signer == ctx.accounts.payer_registry.authority,
+                SolclawError::Unauthorized
+            );
+            require!(now > escrow.deadline, SolclawError::EscrowNotExpired);
+        }
+
+        let amount = escrow.amount;
+        transfer_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.payer_vault.to_account_info(),
+            escrow.key(),
+            escrow.vault_bump,
+            amount,
+        )?;
+
+        escrow.status = Escrow::STATUS_REFUNDED;
+        escrow.settled_at = now;
+
+        close_escrow_vault(
+            &ctx.accounts.token_program,
+            &mut ctx.accounts.escrow_vault,
+            ctx.accounts.payer_vault.to_account_info(),
+            ctx.accounts.rent_receiver.to_account_info(),
+            escrow.key(),
+            escrow.vault_bump,
+        )?;
+
+        emit!(EscrowRefundedEvent {
+            escrow: escrow.key(),
+            payer: escrow.payer_name.clone(),
+            payee: escrow.payee_name.clone(),
+            amount,
+            timestamp: now,
+        });
+
+        msg!(
+            "Escrow #{} refunded: {} USDC back to {}",
+            escrow.id,
+            amount as f64 / 1_000_000.0,
+            escrow.payer_name
+        );
+
+        Ok(())
+    }
+
+    /// Resolve a disputed escrow. Only the arbiter can call this.
+    /// `payee_amount` goes to the payee and the remainder back to the payer.
+    pub fn resolve_escrow(ctx: Context<SettleEscrow>, payee_amount: u64) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+
+        require!(
+            escrow.status == Escrow::STATUS_ACTIVE,
+            SolclawError::EscrowNotActive
+        );
+        require!(escrow.arbiter.is_some(), SolclawError::NoArbiter);
+        require!(
+            escrow.arbiter == Some(ctx.accounts.authority.key()),
+            SolclawError::Unauthorized
+        );
+        require!(
+            payee_amount <= escrow.amount,
+            SolclawError::InvalidEscrowSplit
+        );
+
+        let payer_amount = escrow.amount - payee_amount;
+
+        if payee_amount > 0 {
+            transfer_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow_vault,
+                ctx.accounts.payee_vault.to_account_info(),
+                escrow.key(),
+                escrow.vault_bump,
+                payee_amount,
+            )?;
+        }
+        if payer_amount > 0 {
+            transfer_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow_vault,
+                ctx.accounts.payer_vault.to_account_info(),
+                escrow.key(),
+                escrow.vault_bump,
+                payer_amount,
+            )?;
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        escrow.status = Escrow::STATUS_RESOLVED;
+        escrow.settled_at = now;
+
+        record_escrow_payment(
+            &mut ctx.accounts.payer_registry,
+            &mut ctx.accounts.payee_registry,
+            payee_amount,
+        )?;
+
+        close_escrow_vault(
+            &ctx.accounts.token_program,
+            &mut ctx.accounts.escrow_vault,
+            ctx.accounts.payer_vault.to_account_info(),
+            ctx.accounts.rent_receiver.to_account_info(),
+            escrow.key(),
+            escrow.vault_bump,
+        )?;
+
+        emit!(EscrowResolvedEvent {
+            escrow: escrow.key(),
+            payer: escrow.payer_name.clone(),
+            payee: escrow.payee_name.clone(),
+            payee_amount,
+            payer_amount,
+            arbiter: ctx.accounts.authority.key(),
+            timestamp: now,
+        });
+
+        msg!(
+            "Escrow #{} resolved: {} USDC to {}, {} USDC back to {}",
+            escrow.id,
+            payee_amount as f64 / 1_000_000.0,
+            escrow.payee_name,
+            payer_amount as f64 / 1_000_000.0,
+            escrow.payer_name
+        );
+
+        Ok(())
+    }
+
+    // ============================================================
+    // v5: MILESTONE CONTRACTS
+    // ============================================================
+
+    /// Create a milestone contract and lock the full amount in escrow.
+    /// Each milestone is released separately once the payer approves it.
+    /// An optional arbiter can resolve disputed milestones.
+    /// `contract_id` is a client-chosen nonce, unique per payer.
+    pub fn create_contract(
+        ctx: Context<CreateContract>,
+        contract_id: u64,
+        payee_name: String,
+        milestones: Vec<MilestoneInput>,
+        arbiter: Option<Pubkey>,
+    ) -> Result<()> {
+        require!(
+            !milestones.is_empty() && milestones.len() <= MAX_MILESTONES,
+            SolclawError::InvalidMilestoneCount
+        );
+
+        let payer_registry = &mut ctx.accounts.payer_registry;
+        let payee_registry = &ctx.accounts.payee_registry;
+
+        // Verify payee name matches
+        require!(
+            payee_registry.name_hash == name_to_hash(&payee_name),
+            SolclawError::NameMismatch
+        );
+
+        // Can't contract with yourself
+        require!(
+            payer_registry.key() != payee_registry.key(),
+            SolclawError::CannotEscrowSelf
+        );
+
+        let clock = Clock::get()?;
+        let mut total: u64 = 0;
+        for milestone in milestones.iter() {
+            require!(milestone.amount > 0, SolclawError::InvalidAmount);
+            require!(
+                milestone.deadline > clock.unix_timestamp,
+                SolclawError::InvalidDeadline
+            );
+            total = total.checked_add(milestone.amount).ok_or(SolclawError::Overflow)?;
+        }
+
+        check_and_update_spending_cap(payer_registry, total, &clock)?;
+
+        // Move USDC: payer vault → contract escrow vault
+        let name_bytes = payer_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[payer_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.payer_vault.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                    authority: ctx.accounts.payer_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            total,
+        )?;
+
+        let contract = &mut ctx.accounts.contract;
+        contract.id = contract_id;
+        contract.payer = payer_registry.key();
+        contract.payee = payee_registry.key();
+        contract.payer_name = payer_registry.name.clone();
+        contract.payee_name = payee_name;
+        contract.total_amount = total;
+        contract.released_amount = 0;
+        contract.refunded_amount = 0;
+        contract.arbiter = arbiter;
+        contract.status = Contract::STATUS_ACTIVE;
+        contract.milestones = milestones
+            .iter()
+            .map(|m| Milestone {
+                amount: m.amount,
+                description_hash: m.description_hash,
+                deadline: m.deadline,
+                status: Milestone::STATUS_PENDING,
+                submitted_at: 0,
+            })
+            .collect();
+        contract.created_at = clock.unix_timestamp;
+        contract.authority = ctx.accounts.authority.key();
+        contract.bump = ctx.bumps.contract;
+        contract.vault_bump = ctx.bumps.escrow_vault;
+
+        emit!(ContractCreatedEvent {
+            contract: contract.key(),
+            payer: contract.payer_name.clone(),
+            payee: contract.payee_name.clone(),
+            total_amount: total,
+            milestone_count: contract.milestones.len() as u8,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Contract #{} created: {} USDC over {} milestones from {} to {}",
+            contract_id,
+            total as f64 / 1_000_000.0,
+            contract.milestones.len(),
+            contract.payer_name,
+            contract.payee_name
+        );
+
+        Ok(())
+    }
+
+    /// Submit a milestone for approval. Only the payee can submit, before the
+    /// milestone deadline. A disputed milestone can be resubmitted at any time.
+    /// Submitting starts the payer's MILESTONE_REVIEW_SECONDS review window.
+    pub fn submit_milestone(ctx: Context<UpdateMilestone>, index: u8) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+
+        require!(
+            contract.status == Contract::STATUS_ACTIVE,
+            SolclawError::ContractNotActive
+        );
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.payee_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let milestone = contract
+            .milestones
+            .get_mut(index as usize)
+            .ok_or(SolclawError::InvalidMilestone)?;
+        match milestone.status {
+            Milestone::STATUS_PENDING => {
+                require!(now <= milestone.deadline, SolclawError::MilestoneDeadlinePassed);
+            }
+            Milestone::STATUS_DISPUTED => {}
+            _ => return err!(SolclawError::InvalidMilestoneStatus),
+        }
+
+        milestone.status = Milestone::STATUS_SUBMITTED;
+        milestone.submitted_at = now;
+
+        emit!(MilestoneSubmittedEvent {
+            contract: contract.key(),
+            index,
+            payee: contract.payee_name.clone(),
+            timestamp: now,
+        });
+
+        msg!("Contract #{} milestone {} submitted by {}", contract.id, index, contract.payee_name);
+
+        Ok(())
+    }
+
+    /// Approve a milestone and release its amount to the payee.
+    /// Only the payer can approve. Approving the last milestone closes the
+    /// contract and its vault; the rent goes to the payer's wallet.
+    pub fn approve_milestone(ctx: Context<SettleContract>, index: u8) -> Result<()> {
+        require!(
+            ctx.accounts.contract.status == Contract::STATUS_ACTIVE,
+            SolclawError::ContractNotActive
+        );
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.payer_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        let milestone = ctx.accounts.contract
+            .milestones
+            .get(index as usize)
+            .ok_or(SolclawError::InvalidMilestone)?;
+        require!(
+            milestone.status != Milestone::STATUS_RELEASED
+                && milestone.status != Milestone::STATUS_RESOLVED,
+            SolclawError::InvalidMilestoneStatus
+        );
+        let amount = milestone.amount;
+
+        release_milestone(ctx.accounts, index, amount, Milestone::STATUS_RELEASED)
+    }
+
+    /// Release a submitted milestone the payer neither approved nor disputed
+    /// within MILESTONE_REVIEW_SECONDS. Only the payee can claim.
+    pub fn claim_milestone(ctx: Context<SettleContract>, index: u8) -> Result<()> {
+        require!(
+            ctx.accounts.contract.status == Contract::STATUS_ACTIVE,
+            SolclawError::ContractNotActive
+        );
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.payee_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let milestone = ctx.accounts.contract
+            .milestones
+            .get(index as usize)
+            .ok_or(SolclawError::InvalidMilestone)?;
+        require!(
+            milestone.status == Milestone::STATUS_SUBMITTED,
+            SolclawError::InvalidMilestoneStatus
+        );
+        require!(
+            now >= milestone.submitted_at + MILESTONE_REVIEW_SECONDS,
+            SolclawError::MilestoneUnderReview
+        );
+        let amount = milestone.amount;
+
+        release_milestone(ctx.accounts, index, amount, Milestone::STATUS_RELEASED)
+    }
+
+    /// Resolve a disputed milestone. Only the contract's arbiter can call this.
+    /// `payee_amount` goes to the payee and the rest of the milestone back to
+    /// the payer.
+    pub fn resolve_milestone(
+        ctx: Context<SettleContract>,
+        index: u8,
+        payee_amount: u64,
+    ) -> Result<()> {
+        let contract = &ctx.accounts.contract;
+
+        require!(
+            contract.status == Contract::STATUS_ACTIVE,
+            SolclawError::ContractNotActive
+        );
+        require!(contract.arbiter.is_some(), SolclawError::NoArbiter);
+        require!(
+            contract.arbiter == Some(ctx.accounts.authority.key()),
+            SolclawError::Unauthorized
+        );
+
+        let milestone = contract
+            .milestones
+            .get(index as usize)
+            .ok_or(SolclawError::InvalidMilestone)?;
+        require!(
+            milestone.status == Milestone::STATUS_DISPUTED,
+            SolclawError::InvalidMilestoneStatus
+        );
+        require!(
+            payee_amount <= milestone.amount,
+            SolclawError::InvalidEscrowSplit
+        );
+
+        release_milestone(ctx.accounts, index, payee_amount, Milestone::STATUS_RESOLVED)
+    }
+
+    /// Dispute a milestone. Either party can dispute a pending milestone, or a
+    /// submitted one while it is still under review. The payee can then
+    /// resubmit it, or the arbiter (if any) can resolve it.
+    pub fn dispute_milestone(
+        ctx: Context<UpdateMilestone>,
+        index: u8,
+        reason_hash: [u8; 32],
+    ) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+
+        require!(
+            contract.status == Contract::STATUS_ACTIVE,
+            SolclawError::ContractNotActive
+        );
+
+        let signer = ctx.accounts.authority.key();
+        require!(
+            signer == ctx.accounts.payer_registry.authority
+                || signer == ctx.accounts.payee_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let milestone = contract
+            .milestones
+            .get_mut(index as usize)
+            .ok_or(SolclawError::InvalidMilestone)?;
+        match milestone.status {
+            Milestone::STATUS_PENDING => {}
+            Milestone::STATUS_SUBMITTED => {
+                require!(
+                    now < milestone.submitted_at + MILESTONE_REVIEW_SECONDS,
+                    SolclawError::MilestoneReviewEnded
+                );
+            }
+            _ => return err!(SolclawError::InvalidMilestoneStatus),
+        }
+
+        milestone.status = Milestone::STATUS_DISPUTED;
+
+        emit!(MilestoneDisputedEvent {
+            contract: contract.key(),
+            index,
+            disputed_by: signer,
+            reason_hash,
+            timestamp: now,
+        });
+
+        msg!("Contract #{} milestone {} disputed", contract.id, index);
+
+        Ok(())
+    }
+
+    /// Cancel a contract and refund the unpaid remainder to the payer.
+    /// The payee can cancel at any time; the payer cannot while a milestone is
+    /// submitted or disputed. Cancelling closes the contract and its vault.
+    pub fn cancel_contract(ctx: Context<SettleContract>) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+
+        require!(
+            contract.status == Contract::STATUS_ACTIVE,
+            SolclawError::ContractNotActive
+        );
+
+        let signer = ctx.accounts.authority.key();
+        if signer != ctx.accounts.payee_registry.authority {
+            require!(
+                signer == ctx.accounts.payer_registry.authority,
+                SolclawError::Unauthorized
+            );
+            require!(
+                !contract.milestones.iter().any(|m| {
+                    m.status == Milestone::STATUS_SUBMITTED
+                        || m.status == Milestone::STATUS_DISPUTED
+                }),
+                SolclawError::MilestoneAwaitingApproval
+            );
+        }
+
+        let refund_amount = contract.unpaid_amount()?;
+
+        if refund_amount > 0 {
+            transfer_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow_vault,
+                ctx.accounts.payer_vault.to_account_info(),
+                contract.key(),
+                contract.vault_bump,
+                refund_amount,
+            )?;
+        }
+
+        contract.status = Contract::STATUS_CANCELLED;
+
+        emit!(ContractCancelledEvent {
+            contract: contract.key(),
+            payer: contract.payer_name.clone(),
+            payee: contract.payee_name.clone(),
+            released_amount: contract.released_amount,
+            refunded_amount: contract.refunded_amount + refund_amount,
+            cancelled_by: signer,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Contract #{} cancelled: {} USDC refunded to {}",
+            contract.id,
+            refund_amount as f64 / 1_000_000.0,
+            contract.payer_name
+        );
+
+        close_settled_contract(ctx.accounts)
+    }
+
+    // ============================================================
+    // v5: HASH TIME-LOCKED TRANSFERS (HTLC)
+    // ============================================================
+
+    /// Lock USDC for a receiver until they reveal the preimage of `hashlock`
+    /// or the timelock expires. `htlc_id` is a client-chosen nonce, unique per
+    /// sender. `hash_algorithm` selects sha256 (0) or keccak256 (1) so the
+    /// same hashlock can be used on the counterparty chain.
+    pub fn create_htlc(
+        ctx: Context<CreateHtlc>,
+        htlc_id: u64,
+        receiver_name: String,
+        amount: u64,
+        hashlock: [u8; 32],
+        hash_algorithm: u8,
+        timelock: i64,
+    ) -> Result<()> {
+        require!(amount > 0, SolclawError::InvalidAmount);
+        require!(
+            hash_algorithm == Htlc::HASH_SHA256 || hash_algorithm == Htlc::HASH_KECCAK256,
+            SolclawError::InvalidHashAlgorithm
+        );
+
+        let sender_registry = &mut ctx.accounts.sender_registry;
+        let receiver_registry = &ctx.accounts.receiver_registry;
+
+        // Verify receiver name matches
+        require!(
+            receiver_registry.name_hash == name_to_hash(&receiver_name),
+            SolclawError::NameMismatch
+        );
+
+        // Can't lock funds for yourself
+        require!(
+            sender_registry.key() != receiver_registry.key(),
+            SolclawError::CannotEscrowSelf
+        );
+
+        let clock = Clock::get()?;
+        require!(timelock > clock.unix_timestamp, SolclawError::InvalidDeadline);
+
+        check_and_update_spending_cap(sender_registry, amount, &clock)?;
+
+        // Move USDC: sender vault → HTLC escrow vault
+        let name_bytes = sender_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[sender_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.sender_vault.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                    authority: ctx.accounts.sender_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        let htlc = &mut ctx.accounts.htlc;
+        htlc.id = htlc_id;
+        htlc.sender = sender_registry.key();
+        htlc.receiver = receiver_registry.key();
+        htlc.sender_name = sender_registry.name.clone();
+        htlc.receiver_name = receiver_name;
+        htlc.amount = amount;
+        htlc.hashlock = hashlock;
+        htlc.hash_algorithm = hash_algorithm;
+        htlc.timelock = timelock;
+        htlc.status = Htlc::STATUS_ACTIVE;
+        htlc.created_at = clock.unix_timestamp;
+        htlc.authority = ctx.accounts.authority.key();
+        htlc.bump = ctx.bumps.htlc;
+        htlc.vault_bump = ctx.bumps.escrow_vault;
+
+        emit!(HtlcCreatedEvent {
+            htlc: htlc.key(),
+            sender: htlc.sender_name.clone(),
+            receiver: htlc.receiver_name.clone(),
+            amount,
+            hashlock,
+            hash_algorithm,
+            timelock,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "HTLC #{} created: {} USDC from {} to {}, timelock {}",
+            htlc_id,
+            amount as f64 / 1_000_000.0,
+            htlc.sender_name,
+            htlc.receiver_name,
+            timelock
+        );
+
+        Ok(())
+    }
+
+    /// Claim a HTLC by revealing the preimage. Only the receiver can claim,
+    /// and only before the timelock. The preimage is published in the event
+    /// so the counterparty can complete the other leg of the swap.
+    pub fn claim_htlc(ctx: Context<SettleHtlc>, preimage: Vec<u8>) -> Result<()> {
+        let htlc = &mut ctx.accounts.htlc;
+
+        require!(htlc.status == Htlc::STATUS_ACTIVE, SolclawError::HtlcNotActive);
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.receiver_registry.authority,
+            SolclawError::Unauthorized
+        );
+        require!(preimage.len() <= 64, SolclawError::InvalidPreimage);
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(now < htlc.timelock, SolclawError::HtlcExpired);
+
+        let digest = if htlc.hash_algorithm == Htlc::HASH_KECCAK256 {
+            solana_keccak_hasher::hash(&preimage).to_bytes()
+        } else {
+            solana_sha256_hasher::hash(&preimage).to_bytes()
+        };
+        require!(digest == htlc.hashlock, SolclawError::InvalidPreimage);
+
+        let amount = htlc.amount;
+        transfer_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.receiver_vault.to_account_info(),
+            htlc.key(),
+            htlc.vault_bump,
+            amount,
+        )?;
+
+        htlc.status = Htlc::STATUS_CLAIMED;
+
+        record_escrow_payment(
+            &mut ctx.accounts.sender_registry,
+            &mut ctx.accounts.receiver_registry,
+            amount,
+        )?;
+
+        close_escrow_vault(
+            &ctx.accounts.token_program,
+            &mut ctx.accounts.escrow_vault,
+            ctx.accounts.sender_vault.to_account_info(),
+            ctx.accounts.rent_receiver.to_account_info(),
+            htlc.key(),
+            htlc.vault_bump,
+        )?;
+
+        emit!(HtlcClaimedEvent {
+            htlc: htlc.key(),
+            sender: htlc.sender_name.clone(),
+            receiver: htlc.receiver_name.clone(),
+            amount,
+            preimage,
+            timestamp: now,
+        });
+
+        msg!(
+            "HTLC #{} claimed: {} USDC to {}",
+            htlc.id,
+            amount as f64 / 1_000_000.0,
+            htlc.receiver_name
+        );
+
+        Ok(())
+    }
+
+    /// Refund a HTLC to the sender once the timelock has passed.
+    /// Only the sender can refund.
+    pub fn refund_htlc(ctx: Context<SettleHtlc>) -> Result<()> {
+        let htlc = &mut ctx.accounts.htlc;
+
+        require!(htlc.status == Htlc::STATUS_ACTIVE, SolclawError::HtlcNotActive);
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.sender_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= htlc.timelock, SolclawError::HtlcNotExpired);
+
+        let amount = htlc.amount;
+        transfer_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.sender_vault.to_account_info(),
+            htlc.key(),
+            htlc.vault_bump,
+            amount,
+        )?;
+
+        htlc.status = Htlc::STATUS_REFUNDED;
+
+        close_escrow_vault(
+            &ctx.accounts.token_program,
+            &mut ctx.accounts.escrow_vault,
+            ctx.accounts.sender_vault.to_account_info(),
+            ctx.accounts.rent_receiver.to_account_info(),
+            htlc.key(),
+            htlc.vault_bump,
+        )?;
+
+        emit!(HtlcRefundedEvent {
+            htlc: htlc.key(),
+            sender: htlc.sender_name.clone(),
+            receiver: htlc.receiver_name.clone(),
+            amount,
+            timestamp: now,
+        });
+
+        msg!(
+            "HTLC #{} refunded: {} USDC back to {}",
+            htlc.id,
+            amount as f64 / 1_000_000.0,
+            htlc.sender_name
+        );
+
+        Ok(())
+    }
+
+    // ============================================================
+    // v5: PAYMENT STREAMS
+    // ============================================================
+
+    /// Open a payment stream that vests `rate_per_second` to the receiver,
+    /// funded by `deposit` locked in escrow. `stream_id` is a client-chosen
+    /// nonce, unique per sender.
+    pub fn create_stream(
+        ctx: Context<CreateStream>,
+        stream_id: u64,
+        receiver_name: String,
+        rate_per_second: u64,
+        deposit: u64,
+    ) -> Result<()> {
+        require!(rate_per_second > 0, SolclawError::InvalidStreamRate);
+        require!(deposit > 0, SolclawError::InvalidAmount);
+
+        let sender_registry = &mut ctx.accounts.sender_registry;
+        let receiver_registry = &ctx.accounts.receiver_registry;
+
+        // Verify receiver name matches
+        require!(
+            receiver_registry.name_hash == name_to_hash(&receiver_name),
+            SolclawError::NameMismatch
+        );
+
+        // Can't stream to yourself
+        require!(
+            sender_registry.key() != receiver_registry.key(),
+            SolclawError::CannotEscrowSelf
+        );
+
+        let clock = Clock::get()?;
+        check_and_update_spending_cap(sender_registry, deposit, &clock)?;
+
+        // Move USDC: sender vault → stream escrow vault
+        let name_bytes = sender_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[sender_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.sender_vault.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                    authority: ctx.accounts.sender_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            deposit,
+        )?;
+
+        let stream = &mut ctx.accounts.stream;
+        stream.id = stream_id;
+        stream.sender = sender_registry.key();
+        stream.receiver = receiver_registry.key();
+        stream.sender_name = sender_registry.name.clone();
+        stream.receiver_name = receiver_name;
+        stream.rate_per_second = rate_per_second;
+        stream.deposited = deposit;
+        stream.withdrawn = 0;
+        stream.vested_at_checkpoint = 0;
+        stream.checkpoint_time = clock.unix_timestamp;
+        stream.is_active = true;
+        stream.created_at = clock.unix_timestamp;
+        stream.authority = ctx.accounts.authority.key();
+        stream.bump = ctx.bumps.stream;
+        stream.vault_bump = ctx.bumps.escrow_vault;
+
+        emit!(StreamCreatedEvent {
+            stream: stream.key(),
+            sender: stream.sender_name.clone(),
+            receiver: stream.receiver_name.clone(),
+            rate_per_second,
+            deposit,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Stream #{} created: {} -> {}, {} units/sec, {} USDC deposited",
+            stream_id,
+            stream.sender_name,
+            stream.receiver_name,
+            rate_per_second,
+            deposit as f64 / 1_000_000.0
+        );
+
+        Ok(())
+    }
+
+    /// Withdraw everything vested so far. Only the receiver can withdraw.
+    pub fn withdraw_stream(ctx: Context<SettleStream>) -> Result<()> {
+        let stream = &mut ctx.accounts.stream;
+
+        require!(stream.is_active, SolclawError::StreamNotActive);
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.receiver_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let amount = stream.vested(now)?
+            .checked_sub(stream.withdrawn)
+            .ok_or(SolclawError::Overflow)?;
+        require!(amount > 0, SolclawError::NothingToWithdraw);
+
+        transfer_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.receiver_vault.to_account_info(),
+            stream.key(),
+            stream.vault_bump,
+            amount,
+        )?;
+
+        stream.withdrawn = stream.withdrawn
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+
+        record_escrow_payment(
+            &mut ctx.accounts.sender_registry,
+            &mut ctx.accounts.receiver_registry,
+            amount,
+        )?;
+
+        emit!(StreamWithdrawnEvent {
+            stream: stream.key(),
+            sender: stream.sender_name.clone(),
+            receiver: stream.receiver_name.clone(),
+            amount,
+            total_withdrawn: stream.withdrawn,
+            timestamp: now,
+        });
+
+        msg!(
+            "Stream #{} withdrawal: {} USDC to {}",
+            stream.id,
+            amount as f64 / 1_000_000.0,
+            stream.receiver_name
+        );
+
+        Ok(())
+    }
+
+    /// Add funds to an active stream. Only the sender can top up.
+    /// Vesting is checkpointed first so a stream that ran dry does not
+    /// retroactively vest for the time it was unfunded.
+    pub fn top_up_stream(ctx: Context<TopUpStream>, amount: u64) -> Result<()> {
+        require!(amount > 0, SolclawError::InvalidAmount);
+
+        let stream = &mut ctx.accounts.stream;
+        let sender_registry = &mut ctx.accounts.sender_registry;
+
+        require!(stream.is_active, SolclawError::StreamNotActive);
+
+        let clock = Clock::get()?;
+        check_and_update_spending_cap(sender_registry, amount, &clock)?;
+
+        // Move USDC: sender vault → stream escrow vault
+        let name_bytes = sender_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[sender_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.sender_vault.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                    authority: ctx.accounts.sender_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        stream.vested_at_checkpoint = stream.vested(clock.unix_timestamp)?;
+        stream.checkpoint_time = clock.unix_timestamp;
+        stream.deposited = stream.deposited
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+
+        emit!(StreamToppedUpEvent {
+            stream: stream.key(),
+            sender: stream.sender_name.clone(),
+            amount,
+            total_deposited: stream.deposited,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Stream #{} topped up: {} USDC (total deposited: {})",
+            stream.id,
+            amount as f64 / 1_000_000.0,
+            stream.deposited as f64 / 1_000_000.0
+        );
+
+        Ok(())
+    }
+
+    /// Cancel a stream. Pays the receiver whatever has vested and refunds the
+    /// rest to the sender. Only the sender can cancel. The stream and its
+    /// vault are closed and their rent returned to the sender's wallet.
+    pub fn cancel_stream(ctx: Context<SettleStream>) -> Result<()> {
+        let stream = &mut ctx.accounts.stream;
+
+        require!(stream.is_active, SolclawError::StreamNotActive);
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.sender_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let vested = stream.vested(now)?;
+        let receiver_amount = vested
+            .checked_sub(stream.withdrawn)
+            .ok_or(SolclawError::Overflow)?;
+        let sender_amount = stream.deposited
+            .checked_sub(vested)
+            .ok_or(SolclawError::Overflow)?;
+
+        if receiver_amount > 0 {
+            transfer_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow_vault,
+                ctx.accounts.receiver_vault.to_account_info(),
+                stream.key(),
+                stream.vault_bump,
+                receiver_amount,
+            )?;
+        }
+        if sender_amount > 0 {
+            transfer_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow_vault,
+                ctx.accounts.sender_vault.to_account_info(),
+                stream.key(),
+                stream.vault_bump,
+                sender_amount,
+            )?;
+        }
+
+        stream.withdrawn = vested;
+        stream.is_active = false;
+
+        // Only the vested part counts as a payment
+        let sender_registry = &mut ctx.accounts.sender_registry;
+        sender_registry.total_sent = sender_registry.total_sent
+            .checked_add(receiver_amount)
+            .ok_or(SolclawError::Overflow)?;
+        let receiver_registry = &mut ctx.accounts.receiver_registry;
+        receiver_registry.total_received = receiver_registry.total_received
+            .checked_add(receiver_amount)
+            .ok_or(SolclawError::Overflow)?;
+
+        emit!(StreamCancelledEvent {
+            stream: stream.key(),
+            sender: stream.sender_name.clone(),
+            receiver: stream.receiver_name.clone(),
+            receiver_amount,
+            sender_refund: sender_amount,
+            total_streamed: vested,
+            timestamp: now,
+        });
+
+        msg!(
+            "Stream #{} cancelled: {} USDC to {}, {} USDC refunded to {}",
+            stream.id,
+            receiver_amount as f64 / 1_000_000.0,
+            stream.receiver_name,
+            sender_amount as f64 / 1_000_000.0,
+            stream.sender_name
+        );
+
+        // Nothing is left to withdraw: reclaim the stream and vault rent
+        let accounts = ctx.accounts;
+        close_escrow_vault(
+            &accounts.token_program,
+            &mut accounts.escrow_vault,
+            accounts.sender_vault.to_account_info(),
+            accounts.rent_receiver.to_account_info(),
+            accounts.stream.key(),
+            accounts.stream.vault_bump,
+        )?;
+        accounts.stream.close(accounts.rent_receiver.to_account_info())
+    }
+
+    // ============================================================
+    // v5: PAYMENT CHANNELS
+    // ============================================================
+
+    /// Open a unidirectional payment channel. The payer locks `deposit` in
+    /// escrow and then signs cumulative vouchers off-chain with their wallet
+    /// key; the payee settles the latest voucher on-chain.
+    /// `channel_id` is a client-chosen nonce, unique per payer.
+    pub fn open_channel(
+        ctx: Context<OpenChannel>,
+        channel_id: u64,
+        payee_name: String,
+        deposit: u64,
+        challenge_period: i64,
+    ) -> Result<()> {
+        require!(deposit > 0, SolclawError::InvalidAmount);
+        require!(challenge_period > 0, SolclawError::InvalidChallengePeriod);
+
+        let payer_registry = &mut ctx.accounts.payer_registry;
+        let payee_registry = &ctx.accounts.payee_registry;
+
+        // Verify payee name matches
+        require!(
+            payee_registry.name_hash == name_to_hash(&payee_name),
+            SolclawError::NameMismatch
+        );
+
+        // Can't open a channel to yourself
+        require!(
+            payer_registry.key() != payee_registry.key(),
+            SolclawError::CannotEscrowSelf
+        );
+
+        let clock = Clock::get()?;
+        check_and_update_spending_cap(payer_registry, deposit, &clock)?;
+
+        // Move USDC: payer vault → channel escrow vault
+        let name_bytes = payer_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[payer_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.payer_vault.to_account_info(),
+                    to: ctx.accounts.escrow_vault.to_account_info(),
+                    authority: ctx.accounts.payer_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            deposit,
+        )?;
+
+        let channel = &mut ctx.accounts.channel;
+        channel.id = channel_id;
+        channel.payer = payer_registry.key();
+        channel.payee = payee_registry.key();
+        channel.payer_name = payer_registry.name.clone();
+        channel.payee_name = payee_name;
+        channel.voucher_signer = ctx.accounts.authority.key();
+        channel.deposit = deposit;
+        channel.settled = 0;
+        channel.challenge_period = challenge_period;
+        channel.close_requested_at = 0;
+        channel.status = PaymentChannel::STATUS_OPEN;
+        channel.created_at = clock.unix_timestamp;
+        channel.bump = ctx.bumps.channel;
+        channel.vault_bump = ctx.bumps.escrow_vault;
+
+        emit!(ChannelOpenedEvent {
+            channel: channel.key(),
+            payer: channel.payer_name.clone(),
+            payee: channel.payee_name.clone(),
+            deposit,
+            challenge_period,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Channel #{} opened: {} -> {}, {} USDC deposited",
+            channel_id,
+            channel.payer_name,
+            channel.payee_name,
+            deposit as f64 / 1_000_000.0
+        );
+
+        Ok(())
+    }
+
+    /// Settle a signed voucher. Only the payee can settle. The transaction must
+    /// include an Ed25519 program instruction verifying the payer's signature
+    /// over `voucher_message(channel, cumulative_amount)`. Only the increase
+    /// over the previously settled amount is paid out.
+    pub fn settle_channel(ctx: Context<SettleChannel>, cumulative_amount: u64) -> Result<()> {
+        let channel = &mut ctx.accounts.channel;
+
+        require!(
+            channel.status != PaymentChannel::STATUS_CLOSED,
+            SolclawError::ChannelClosed
+        );
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.payee_registry.authority,
+            SolclawError::Unauthorized
+        );
+        require!(
+            cumulative_amount > channel.settled,
+            SolclawError::StaleVoucher
+        );
+        require!(
+            cumulative_amount <= channel.deposit,
+            SolclawError::VoucherExceedsDeposit
+        );
+
+        verify_ed25519_signature(
+            &ctx.accounts.instructions,
+            &channel.voucher_signer,
+            &voucher_message(&channel.key(), cumulative_amount),
+        )?;
+
+        let amount = cumulative_amount - channel.settled;
+        transfer_from_escrow(
+            &ctx.accounts.token_program,
+            &ctx.accounts.escrow_vault,
+            ctx.accounts.payee_vault.to_account_info(),
+            channel.key(),
+            channel.vault_bump,
+            amount,
+        )?;
+
+        channel.settled = cumulative_amount;
+
+        record_escrow_payment(
+            &mut ctx.accounts.payer_registry,
+            &mut ctx.accounts.payee_registry,
+            amount,
+        )?;
+
+        emit!(ChannelSettledEvent {
+            channel: channel.key(),
+            payer: channel.payer_name.clone(),
+            payee: channel.payee_name.clone(),
+            amount,
+            cumulative_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Channel #{} settled: {} USDC to {} (cumulative {})",
+            channel.id,
+            amount as f64 / 1_000_000.0,
+            channel.payee_name,
+            cumulative_amount as f64 / 1_000_000.0
+        );
+
+        Ok(())
+    }
+
+    /// Start the challenge period before closing. Only the payer can request
+    /// a close; the payee can still settle vouchers until it ends.
+    pub fn request_close_channel(ctx: Context<RequestCloseChannel>) -> Result<()> {
+        let channel = &mut ctx.accounts.channel;
+
+        require!(
+            channel.status == PaymentChannel::STATUS_OPEN,
+            SolclawError::ChannelNotOpen
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        channel.status = PaymentChannel::STATUS_CLOSING;
+        channel.close_requested_at = now;
+
+        emit!(ChannelCloseRequestedEvent {
+            channel: channel.key(),
+            payer: channel.payer_name.clone(),
+            payee: channel.payee_name.clone(),
+            closes_at: now + channel.challenge_period,
+            timestamp: now,
+        });
+
+        msg!(
+            "Channel #{} closing: challenge period ends at {}",
+            channel.id,
+            now + channel.challenge_period
+        );
+
+        Ok(())
+    }
+
+    /// Close a channel and refund the unsettled deposit to the payer.
+    /// The payer can close once the challenge period has elapsed; the payee
+    /// can close cooperatively at any time. The channel and its vault are
+    /// closed and their rent returned to the payer's wallet.
+    pub fn close_channel(ctx: Context<SettleChannel>) -> Result<()> {
+        let channel = &mut ctx.accounts.channel;
+
+        require!(
+            channel.status != PaymentChannel::STATUS_CLOSED,
+            SolclawError::ChannelClosed
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let signer = ctx.accounts.authority.key();
+        if signer != ctx.accounts.payee_registry.authority {
+            require!(
+                signer == ctx.accounts.payer_registry.authority,
+                SolclawError::Unauthorized
+            );
+            require!(
+                channel.status == PaymentChannel::STATUS_CLOSING
+                    && now >= channel.close_requested_at + channel.challenge_period,
+                SolclawError::ChallengePeriodActive
+            );
+        }
+
+        let refund_amount = channel.deposit
+            .checked_sub(channel.settled)
+            .ok_or(SolclawError::Overflow)?;
+
+        if refund_amount > 0 {
+            transfer_from_escrow(
+                &ctx.accounts.token_program,
+                &ctx.accounts.escrow_vault,
+                ctx.accounts.payer_vault.to_account_info(),
+                channel.key(),
+                channel.vault_bump,
+                refund_amount,
+            )?;
+        }
+
+        channel.status = PaymentChannel::STATUS_CLOSED;
+
+        emit!(ChannelClosedEvent {
+            channel: channel.key(),
+            payer: channel.payer_name.clone(),
+            payee: channel.payee_name.clone(),
+            settled: channel.settled,
+            refunded: refund_amount,
+            timestamp: now,
+        });
+
+        msg!(
+            "Channel #{} closed: {} USDC settled, {} USDC refunded to {}",
+            channel.id,
+            channel.settled as f64 / 1_000_000.0,
+            refund_amount as f64 / 1_000_000.0,
+            channel.payer_name
+        );
+
+        let accounts = ctx.accounts;
+        close_escrow_vault(
+            &accounts.token_program,
+            &mut accounts.escrow_vault,
+            accounts.payer_vault.to_account_info(),
+            accounts.rent_receiver.to_account_info(),
+            accounts.channel.key(),
+            accounts.channel.vault_bump,
+        )?;
+        accounts.channel.close(accounts.rent_receiver.to_account_info())
+    }
+
+    // ============================================================
+    // v5: GASLESS PAYMENT INTENTS
+    // ============================================================
+
+    /// Execute a payment intent signed off-chain by the sender's authority.
+    /// Anyone can relay it: the relayer pays the SOL fees and is reimbursed
+    /// with `relayer_fee` USDC from the sender's vault, capped by the signed
+    /// `max_relayer_fee`. The transaction must include an Ed25519 program
+    /// instruction verifying the signature over `intent_message(...)`.
+    /// Nonces are sequential per sender, so each intent executes at most once.
+    pub fn execute_intent(
+        ctx: Context<ExecuteIntent>,
+        amount: u64,
+        nonce: u64,
+        expiry: i64,
+        max_relayer_fee: u64,
+        relayer_fee: u64,
+    ) -> Result<()> {
+        require!(amount > 0, SolclawError::InvalidAmount);
+        require!(
+            relayer_fee <= max_relayer_fee,
+            SolclawError::RelayerFeeTooHigh
+        );
+
+        let clock = Clock::get()?;
+        require!(clock.unix_timestamp <= expiry, SolclawError::IntentExpired);
+
+        let sender_registry = &mut ctx.accounts.sender_registry;
+        let receiver_registry = &mut ctx.accounts.receiver_registry;
+
+        // Replay protection
+        let intent_nonce = &mut ctx.accounts.intent_nonce;
+        require!(nonce == intent_nonce.next_nonce, SolclawError::InvalidNonce);
+
+        verify_ed25519_signature(
+            &ctx.accounts.instructions,
+            &sender_registry.authority,
+            &intent_message(
+                &sender_registry.key(),
+                &receiver_registry.key(),
+                amount,
+                nonce,
+                expiry,
+                max_relayer_fee,
+            ),
+        )?;
+
+        intent_nonce.sender = sender_registry.key();
+        intent_nonce.next_nonce = nonce.checked_add(1).ok_or(SolclawError::Overflow)?;
+        intent_nonce.bump = ctx.bumps.intent_nonce;
+
+        // Both the payment and the relayer fee leave the sender's vault
+        let total = amount.checked_add(relayer_fee).ok_or(SolclawError::Overflow)?;
+        check_and_update_spending_cap(sender_registry, total, &clock)?;
+
+        let name_bytes = sender_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[sender_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.sender_vault.to_account_info(),
+                    to: ctx.accounts.receiver_vault.to_account_info(),
+                    authority: ctx.accounts.sender_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        if relayer_fee > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.sender_vault.to_account_info(),
+                        to: ctx.accounts.relayer_token_account.to_account_info(),
+                        authority: ctx.accounts.sender_vault.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                relayer_fee,
+            )?;
+        }
+
+        // Update stats (the relayer fee is not an agent-to-agent payment)
+        sender_registry.total_sent = sender_registry.total_sent
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+        receiver_registry.total_received = receiver_registry.total_received
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+
+        emit!(IntentExecutedEvent {
+            sender: sender_registry.name.clone(),
+            receiver: receiver_registry.name.clone(),
+            amount,
+            nonce,
+            relayer: ctx.accounts.relayer.key(),
+            relayer_fee,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Intent #{} executed: {} USDC from {} to {} (relayer fee {})",
+            nonce,
+            amount as f64 / 1_000_000.0,
+            sender_registry.name,
+            receiver_registry.name,
+            relayer_fee as f64 / 1_000_000.0
+        );
+
+        Ok(())
+    }
+
+    // ============================================================
+    // v5: METERED BILLING
+    // ============================================================
+
+    /// Propose a usage-metered plan to a consumer. Only the provider can
+    /// create it; it is inactive until the consumer accepts the price.
+    /// `plan_id` is a client-chosen nonce, unique per provider/consumer pair.
+    pub fn create_metered_plan(
+        ctx: Context<CreateMeteredPlan>,
+        plan_id: u64,
+        consumer_name: String,
+        price_per_unit: u64,
+    ) -> Result<()> {
+        require!(price_per_unit > 0, SolclawError::InvalidAmount);
+
+        let provider_registry = &ctx.accounts.provider_registry;
+        let consumer_registry = &ctx.accounts.consumer_registry;
+
+        // Verify consumer name matches
+        require!(
+            consumer_registry.name_hash == name_to_hash(&consumer_name),
+            SolclawError::NameMismatch
+        );
+
+        // Can't bill yourself
+        require!(
+            provider_registry.key() != consumer_registry.key(),
+            SolclawError::CannotInvoiceSelf
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+
+        let plan = &mut ctx.accounts.plan;
+        plan.id = plan_id;
+        plan.provider = provider_registry.key();
+        plan.consumer = consumer_registry.key();
+        plan.provider_name = provider_registry.name.clone();
+        plan.consumer_name = consumer_name;
+        plan.price_per_unit = price_per_unit;
+        plan.settled_units = 0;
+        plan.total_billed = 0;
+        plan.status = MeteredPlan::STATUS_PROPOSED;
+        plan.created_at = now;
+        plan.accepted_at = 0;
+        plan.cancelled_at = 0;
+        plan.payer = ctx.accounts.payer.key();
+        plan.bump = ctx.bumps.plan;
+
+        emit!(MeteredPlanCreatedEvent {
+            plan: plan.key(),
+            provider: plan.provider_name.clone(),
+            consumer: plan.consumer_name.clone(),
+            price_per_unit,
+            timestamp: now,
+        });
+
+        msg!(
+            "Metered plan #{} proposed: {} bills {} {} USDC per unit",
+            plan_id,
+            plan.provider_name,
+            plan.consumer_name,
+            price_per_unit as f64 / 1_000_000.0
+        );
+
+        Ok(())
+    }
+
+    /// Accept a proposed metered plan. Only the consumer can accept.
+    /// Settlement also needs an allowance from the consumer to the provider.
+    pub fn accept_metered_plan(ctx: Context<UpdateMeteredPlan>) -> Result<()> {
+        let plan = &mut ctx.accounts.plan;
+
+        require!(
+            plan.status == MeteredPlan::STATUS_PROPOSED,
+            SolclawError::MeteredPlanNotProposed
+        );
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.consumer_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        plan.status = MeteredPlan::STATUS_ACTIVE;
+        plan.accepted_at = now;
+
+        emit!(MeteredPlanAcceptedEvent {
+            plan: plan.key(),
+            provider: plan.provider_name.clone(),
+            consumer: plan.consumer_name.clone(),
+            price_per_unit: plan.price_per_unit,
+            timestamp: now,
+        });
+
+        msg!("Metered plan accepted by {}", plan.consumer_name);
+
+        Ok(())
+    }
+
+    /// Cancel a metered plan. Either the provider or the consumer can cancel.
+    /// Usage the consumer signed before cancelling can still be settled for
+    /// METERED_SETTLEMENT_GRACE_SECONDS.
+    pub fn cancel_metered_plan(ctx: Context<UpdateMeteredPlan>) -> Result<()> {
+        let plan = &mut ctx.accounts.plan;
+
+        require!(
+            plan.status != MeteredPlan::STATUS_CANCELLED,
+            SolclawError::MeteredPlanNotActive
+        );
+
+        let signer = ctx.accounts.authority.key();
+        require!(
+            signer == ctx.accounts.provider_registry.authority
+                || signer == ctx.accounts.consumer_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        plan.status = MeteredPlan::STATUS_CANCELLED;
+        plan.cancelled_at = now;
+
+        emit!(MeteredPlanCancelledEvent {
+            plan: plan.key(),
+            provider: plan.provider_name.clone(),
+            consumer: plan.consumer_name.clone(),
+            settled_units: plan.settled_units,
+            total_billed: plan.total_billed,
+            settle_until: plan.settlement_deadline(),
+            cancelled_by: signer,
+            timestamp: now,
+        });
+
+        msg!(
+            "Metered plan cancelled: {} -> {}, {} units billed",
+            plan.consumer_name,
+            plan.provider_name,
+            plan.settled_units
+        );
+
+        Ok(())
+    }
+
+    /// Settle usage against the consumer's allowance. Only the provider can
+    /// settle. The consumer signs cumulative usage checkpoints off-chain; the
+    /// transaction must include an Ed25519 program instruction verifying the
+    /// consumer's signature over `usage_message(plan, cumulative_units)`, so
+    /// the provider cannot inflate usage. All units since the last settlement
+    /// are billed in one pull. A cancelled plan can still be settled until its
+    /// grace period ends.
+    pub fn settle_usage(ctx: Context<SettleUsage>, cumulative_units: u64) -> Result<()> {
+        let plan = &mut ctx.accounts.plan;
+        let allowance = &mut ctx.accounts.allowance;
+        let consumer_registry = &mut ctx.accounts.consumer_registry;
+        let provider_registry = &mut ctx.accounts.provider_registry;
+
+        let clock = Clock::get()?;
+        require!(
+            plan.is_settleable(clock.unix_timestamp),
+            SolclawError::MeteredPlanNotActive
+        );
+        require!(
+            cumulative_units > plan.settled_units,
+            SolclawError::StaleUsageReport
+        );
+        require!(allowance.is_active, SolclawError::AllowanceNotActive);
+
+        verify_ed25519_signature(
+            &ctx.accounts.instructions,
+            &consumer_registry.authority,
+            &usage_message(&plan.key(), cumulative_units),
+        )?;
+
+        let units = cumulative_units - plan.settled_units;
+        let amount = units
+            .checked_mul(plan.price_per_unit)
+            .ok_or(SolclawError::Overflow)?;
+
+        require!(
+            amount <= allowance.amount,
+            SolclawError::AllowanceExceeded
+        );
+
+        // The consumer's daily limit still applies
+        check_and_update_spending_cap(consumer_registry, amount, &clock)?;
+
+        // Pull USDC: consumer vault → provider vault
+        let name_bytes = consumer_registry.name.as_bytes();
+        let seeds = &[
+            b"vault",
+            name_bytes,
+            &[consumer_registry.vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.consumer_vault.to_account_info(),
+                    to: ctx.accounts.provider_vault.to_account_info(),
+                    authority: ctx.accounts.consumer_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        // Decrease allowance
+        allowance.amount = allowance.amount
+            .checked_sub(amount)
+            .ok_or(SolclawError::Overflow)?;
+        allowance.total_pulled = allowance.total_pulled
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+        allowance.pull_count += 1;
+
+        // Update plan
+        plan.settled_units = cumulative_units;
+        plan.total_billed = plan.total_billed
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+
+        // Update stats
+        consumer_registry.total_sent = consumer_registry.total_sent
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+        provider_registry.total_received = provider_registry.total_received
+            .checked_add(amount)
+            .ok_or(SolclawError::Overflow)?;
+
+        emit!(UsageSettledEvent {
+            plan: plan.key(),
+            provider: plan.provider_name.clone(),
+            consumer: plan.consumer_name.clone(),
+            units,
+            cumulative_units,
+            amount,
+            remaining_allowance: allowance.amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Usage settled: {} units, {} USDC from {} to {}",
+            units,
+            amount as f64 / 1_000_000.0,
+            plan.consumer_name,
+            plan.provider_name
+        );
+
+        Ok(())
+    }
+
+    /// Close a cancelled metered plan once nothing can be settled any more
+    /// and return its rent to the original payer. Either party can close.
+    pub fn close_metered_plan(ctx: Context<CloseMeteredPlan>) -> Result<()> {
+        let plan = &ctx.accounts.plan;
+
+        require!(
+            plan.status == MeteredPlan::STATUS_CANCELLED,
+            SolclawError::MeteredPlanStillOpen
+        );
+        require!(
+            !plan.is_settleable(Clock::get()?.unix_timestamp),
+            SolclawError::MeteredPlanStillOpen
+        );
+
+        let signer = ctx.accounts.authority.key();
+        require!(
+            signer == ctx.accounts.provider_registry.authority
+                || signer == ctx.accounts.consumer_registry.authority,
+            SolclawError::Unauthorized
+        );
+
+        msg!(
+            "Metered plan #{} closed: {} -> {}, {} USDC billed",
+            plan.id,
+            plan.consumer_name,
+            plan.provider_name,
+            plan.total_billed as f64 / 1_000_000.0
+        );
+
+        Ok(())
+    }
+
+    // ============================================================
+    // v5: SPLIT CONFIGS
+    // ============================================================
+
+    /// Create a reusable revenue-share configuration for an agent.
+    /// Remaining accounts: the AgentRegistry of each recipient, in order.
+    /// With `locked = true` the configuration can never be changed or closed.
+    /// `reserve` is the part of the owner's vault balance distributions leave
+    /// untouched, so the vault can keep funds that are not meant to be split.
+    pub fn create_split_config<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CreateSplitConfig<'info>>,
+        recipients: Vec<SplitRecipient>,
+        rounding: SplitRounding,
+        reserve: u64,
+        locked: bool,
+    ) -> Result<()> {
+        let owner_registry = &ctx.accounts.owner_registry;
+        let entries = build_split_config_entries(
+            &recipients,
+            rounding,
+            ctx.remaining_accounts,
+            owner_registry.key(),
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+
+        let config = &mut ctx.accounts.split_config;
+        config.owner = owner_registry.key();
+        config.owner_name = owner_registry.name.clone();
+        config.recipients = entries;
+        config.rounding = rounding;
+        config.reserve = reserve;
+        config.locked = locked;
+        config.total_distributed = 0;
+        config.distribution_count = 0;
+        config.created_at = now;
+        config.updated_at = now;
+        config.bump = ctx.bumps.split_config;
+
+        emit!(SplitConfigUpdatedEvent {
+            split_config: config.key(),
+            owner: config.owner_name.clone(),
+            recipients: config.recipients.iter().map(|r| r.registry).collect(),
+            shares_bps: config.recipients.iter().map(|r| r.share_bps).collect(),
+            reserve,
+            locked,
+            timestamp: now,
+        });
+
+        msg!(
+            "Split config created for {} with {} recipients{}",
+            config.owner_name,
+            config.recipients.len(),
+            if locked { " (locked)" } else { "" }
+        );
+
+        Ok(())
+    }
+
+    /// Replace the recipients of an unlocked split configuration.
+    /// Remaining accounts: the AgentRegistry of each recipient, in order.
+    /// Passing `lock = true` makes the new configuration permanent.
+    pub fn update_split_config<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UpdateSplitConfig<'info>>,
+        recipients: Vec<SplitRecipient>,
+        rounding: SplitRounding,
+        reserve: u64,
+        lock: bool,
+    ) -> Result<()> {
+        require!(!ctx.accounts.split_config.locked, SolclawError::SplitConfigLocked);
+
+        let entries = build_split_config_entries(
+            &recipients,
+            rounding,
+            ctx.remaining_accounts,
+            ctx.accounts.owner_registry.key(),
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+
+        let config = &mut ctx.accounts.split_config;
+        config.recipients = entries;
+        config.rounding = rounding;
+        config.reserve = reserve;
+        config.locked = lock;
+        config.updated_at = now;
+
+        emit!(SplitConfigUpdatedEvent {
+            split_config: config.key(),
+            owner: config.owner_name.clone(),
+            recipients: config.recipients.iter().map(|r| r.registry).collect(),
+            shares_bps: config.recipients.iter().map(|r| r.share_bps).collect(),
+            reserve,
+            locked: lock,
+            timestamp: now,
+        });
+
+        msg!(
+            "Split config updated for {} with {} recipients{}",
+            config.owner_name,
+            config.recipients.len(),
+            if lock { " (locked)" } else { "" }
+        );
+
+        Ok(())
+    }
+
+    /// Close an unlocked split configuration and reclaim its rent.
+    pub fn close_split_config(ctx: Context<CloseSplitConfig>) -> Result<()> {
+        require!(!ctx.accounts.split_config.locked, SolclawError::SplitConfigLocked);
+
+        msg!("Split config closed for {}", ctx.accounts.split_config.owner_name);
+
+        Ok(())
+    }
+
+    /// Pay out the owner's vault balance above the configured reserve
+    /// according to its split configuration. Permissionless: anyone can
+    /// crank it, so every deposit above the reserve is distributed.
+    /// Remaining accounts, in pre-order: [registry, vault] for each direct
+    /// recipient, [registry, split_config] followed by that config's
+    /// recipients for each cascading one.
+    pub fn distribute_split<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DistributeSplit<'info>>,
+    ) -> Result<()> {
+        let total_amount = ctx
+            .accounts
+            .owner_vault
+            .amount
+            .saturating_sub(ctx.accounts.split_config.reserve);
+        require!(total_amount > 0, SolclawError::NothingToDistribute);
+
+        let owner_registry = &mut ctx.accounts.owner_registry;
+        let clock = Clock::get()?;
+
+        // v3: The owner's spending cap still applies to cranked payouts
+        check_and_update_spending_cap(owner_registry, total_amount, &clock)?;
+
+        let name_bytes = owner_registry.name.as_bytes();
+        let vault_bump = owner_registry.vault_bump;
+        let seeds = &[
+            b"vault".as_ref(),
+            name_bytes,
+            &[vault_bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        // Pay out by the configured shares, cascading through nested configs
+        let mut payout = SplitPayout::new(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.owner_vault.to_account_info(),
+            signer_seeds,
+            ctx.remaining_accounts,
+            owner_registry.key(),
+        );
+        let amounts = payout.pay_config(
+            &ctx.accounts.split_config,
+            total_amount,
+            SplitPayoutNode::ROOT,
+            0,
+        )?;
+        let payouts = payout.finish()?;
+
+        owner_registry.total_sent = owner_registry.total_sent
+            .checked_add(total_amount)
+            .ok_or(SolclawError::Overflow)?;
+
+        let config = &mut ctx.accounts.split_config;
+        config.total_distributed = config.total_distributed
+            .checked_add(total_amount)
+            .ok_or(SolclawError::Overflow)?;
+        config.distribution_count = config.distribution_count.saturating_add(1);
+
+        emit!(SplitDistributedEvent {
+            split_config: config.key(),
+            owner: config.owner_name.clone(),
+            recipients: config.recipients.iter().map(|r| r.registry).collect(),
+            amounts,
+            total: total_amount,
+            payouts,
+            cranker: ctx.accounts.cranker.key(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Split distributed: {} USDC from {} to {} recipients",
+            total_amount as f64 / 1_000_000.0,
+            config.owner_name,
+            config.recipients.len()
+        );
+
+        Ok(())
+    }
+}
+
+// ============================================================
+// HELPER FUNCTIONS
+// ============================================================
+
+/// Check and update spending cap. Call this BEFORE every outgoing transfer.
+/// Returns Ok(()) if the spend is allowed, Err if it exceeds the daily limit.
+fn check_and_update_spending_cap(
+    registry: &mut Account<AgentRegistry>,
+    amount: u64,
+    clock: &Clock,
+) -> Result<()> {
+    // If no limit set, allow everything
+    if registry.daily_limit == 0 {
+        return Ok(());
+    }
+
+    let today = clock.unix_timestamp / 86400;
+
+    // Reset daily_spent if it's a new day
+    if today != registry.last_spend_day {
+        registry.daily_spent = 0;
+        registry.last_spend_day = today;
+    }
+
+    // Check if this spend would exceed the limit
+    let new_total = registry.daily_spent
+        .checked_add(amount)
+        .ok_or(SolclawError::Overflow)?;
+
+    require!(
+        new_total <= registry.daily_limit,
+        SolclawError::SpendingCapExceeded
+    );
+
+    // Update spent amount
+    registry.daily_spent = new_total;
+
+    Ok(())
+}
+
+/// v5: How much more the agent may spend today under its daily cap,
+/// without updating it. Returns u64::MAX when no cap is set.
+fn remaining_spending_cap(registry: &AgentRegistry, clock: &Clock) -> u64 {
+    if registry.daily_limit == 0 {
+        return u64::MAX;
+    }
+
+    let today = clock.unix_timestamp / 86400;
+    let spent = if today != registry.last_spend_day {
+        0
+    } else {
+        registry.daily_spent
+    };
+
+    registry.daily_limit.saturating_sub(spent)
+}
+
+/// v5: Load and validate a recipient (registry, vault) pair passed in
+/// remaining accounts. The registry must be writable, owned by this program
+/// and carry the AgentRegistry discriminator. Both PDAs are re-derived with
+/// `create_program_address` from the bumps stored on the registry, which is
+/// far cheaper than `find_program_address`. The sender can't be its own
+/// recipient: its registry is serialized from the instruction's accounts on
+/// exit and would overwrite the credit.
+fn load_recipient<'info>(
+    registry_info: &'info AccountInfo<'info>,
+    vault_info: &AccountInfo<'info>,
+    expected_name: Option<&str>,
+    sender: Pubkey,
+) -> Result<Account<'info, AgentRegistry>> {
+    require!(registry_info.is_writable, SolclawError::RecipientNotWritable);
+    require!(registry_info.key() != sender, SolclawError::CannotPaySelf);
+
+    let registry = Account::<AgentRegistry>::try_from(registry_info)?;
+    if let Some(name) = expected_name {
+        require!(registry.name == name, SolclawError::NameMismatch);
+    }
+
+    let name_bytes = registry.name.as_bytes();
+    let expected_registry_pda = Pubkey::create_program_address(
+        &[b"agent", name_bytes, &[registry.bump]],
+        &crate::ID,
+    )
+    .map_err(|_| error!(SolclawError::NameMismatch))?;
+    require!(
+        registry_info.key() == expected_registry_pda,
+        SolclawError::NameMismatch
+    );
+
+    let expected_vault_pda = Pubkey::create_program_address(
+        &[b"vault", name_bytes, &[registry.vault_bump]],
+        &crate::ID,
+    )
+    .map_err(|_| error!(SolclawError::VaultMismatch))?;
+    require!(
+        vault_info.key() == expected_vault_pda,
+        SolclawError::VaultMismatch
+    );
+
+    Ok(registry)
+}
+
+/// v5: Add `amount` to a recipient's total_received and write it back
+/// immediately, so a recipient loaded again later sees the update.
+fn credit_recipient(registry: &mut Account<AgentRegistry>, amount: u64) -> Result<()> {
+    registry.total_received = registry.total_received
+        .checked_add(amount)
+        .ok_or(SolclawError::Overflow)?;
+    registry.exit(&crate::ID)
+}
+
+/// v5: Count funds released from an escrow-style account (escrow, contract,
+/// HTLC, stream or channel) in both agents' stats. Locking funds up front is
+/// charged against the sender's spending cap but only counts as sent once it
+/// reaches the recipient, and only the part that does (refunds don't count).
+fn record_escrow_payment(
+    sender: &mut AgentRegistry,
+    recipient: &mut AgentRegistry,
+    amount: u64,
+) -> Result<()> {
+    sender.total_sent = sender.total_sent
+        .checked_add(amount)
+        .ok_or(SolclawError::Overflow)?;
+    recipient.total_received = recipient.total_received
+        .checked_add(amount)
+        .ok_or(SolclawError::Overflow)?;
+    Ok(())
+}
+
+/// v5: Move `amount` from the sender vault to the receiver vault for a
+/// subscription, enforcing the sender's spending cap and updating stats.
+fn pay_subscription(
+    accounts: &mut ExecuteSubscription,
+    amount: u64,
+    clock: &Clock,
+) -> Result<()> {
+    let receiver_vault = accounts.receiver_vault.to_account_info();
+    transfer_from_sender_vault(accounts, receiver_vault, amount, clock)?;
+
+    let sender_registry = &mut accounts.sender_registry;
+    let subscription = &mut accounts.subscription;
+    subscription.total_paid = subscription
+        .total_paid
+        .checked_add(amount)
+        .ok_or(SolclawError::Overflow)?;
+
+    // Update sender stats
+    sender_registry.total_sent = sender_registry
+        .total_sent
+        .checked_add(amount)
+        .ok_or(SolclawError::Overflow)?;
+
+    // Update receiver stats
+    credit_recipient(&mut accounts.receiver_registry, amount)
+}
+
+/// v5: Checks shared by subscriptions and subscription offers.
+fn validate_subscription_terms(
+    amount: u64,
+    schedule: &Schedule,
+    limits: &SubscriptionLimits,
+) -> Result<()> {
+    require!(amount > 0, SolclawError::InvalidAmount);
+    if let Schedule::Interval { seconds } = *schedule {
+        require!(seconds >= 60, SolclawError::InvalidInterval);
+    }
+    require!(schedule.is_valid(), SolclawError::InvalidSchedule);
+    require!(
+        limits.max_total == 0 || limits.max_total >= amount,
+        SolclawError::InvalidSubscriptionLimits
+    );
+    Ok(())
+}
+
+/// v5: Transfer out of a subscription's sender vault, enforcing the sender's
+/// spending cap.
+fn transfer_from_sender_vault<'info>(
+    accounts: &mut ExecuteSubscription<'info>,
+    to: AccountInfo<'info>,
+    amount: u64,
+    clock: &Clock,
+) -> Result<()> {
+    let sender_registry = &mut accounts.sender_registry;
+    check_and_update_spending_cap(sender_registry, amount, clock)?;
+
+    // Create signer seeds for the sender vault
+    let name_bytes = sender_registry.name.as_bytes();
+    let seeds = &[
+        b"vault".as_ref(),
+        name_bytes,
+        &[sender_registry.vault_bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            accounts.token_program.to_account_info(),
+            Transfer {
+                from: accounts.sender_vault.to_account_info(),
+                to,
+                authority: accounts.sender_vault.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+    )
+}
+
+/// v5: Apply an agreed pending change once the next payment falls due on or
+/// after its effective date. A new schedule starts from its first
+/// occurrence at or after the current next_due.
+fn apply_subscription_change(subscription: &mut Account<Subscription>, now: i64) -> Result<()> {
+    let Some(previous_amount) = subscription.apply_pending_change()? else {
+        return Ok(());
+    };
+
+    emit!(SubscriptionChangeAppliedEvent {
+        subscription: subscription.key(),
+        sender: subscription.sender_name.clone(),
+        receiver: subscription.receiver_name.clone(),
+        previous_amount,
+        new_amount: subscription.amount,
+        next_due: subscription.next_due,
+        timestamp: now,
+    });
+
+    msg!(
+        "Subscription change applied: {} -> {}, now {} USDC, next due at {}",
+        subscription.sender_name,
+        subscription.receiver_name,
+        subscription.amount,
+        subscription.next_due
+    );
+
+    Ok(())
+}
+
+/// v5: Deactivate a subscription whose limits have been reached.
+fn complete_subscription(subscription: &mut Account<Subscription>, now: i64) -> Result<()> {
+    subscription.is_active = false;
+    subscription.completed_at = now;
+
+    emit!(SubscriptionCompletedEvent {
+        subscription: subscription.key(),
+        sender: subscription.sender_name.clone(),
+        receiver: subscription.receiver_name.clone(),
+        total_paid: subscription.total_paid,
+        execution_count: subscription.execution_count,
+        timestamp: now,
+    });
+
+    msg!(
+        "Subscription completed: {} -> {}, total paid: {} USDC over {} executions",
+        subscription.sender_name,
+        subscription.receiver_name,
+        subscription.total_paid as f64 / 1_000_000.0,
+        subscription.execution_count
+    );
+
+    Ok(())
+}
+
+/// v5: Collect Solana Pay reference keys from the remaining accounts.
+/// References must be read-only non-signers; they only need to appear in the
+/// transaction so `getSignaturesForAddress(reference)` finds the payment.
+fn collect_references(accounts: &[AccountInfo]) -> Result<Vec<Pubkey>> {
+    require!(
+        accounts.len() <= MAX_REFERENCES,
+        SolclawError::TooManyReferences
+    );
+
+    accounts
+        .iter()
+        .map(|account| {
+            require!(
+                !account.is_writable && !account.is_signer,
+                SolclawError::InvalidReference
+            );
+            Ok(account.key())
+        })
+        .collect()
+}
+
+/// v5: Build the fixed-size name hash stored on AgentRegistry.
+fn name_to_hash(name: &str) -> [u8; 32] {
+    let name_bytes = name.as_bytes();
+    let mut name_hash = [0u8; 32];
+    name_hash[..name_bytes.len().min(32)].copy_from_slice(&name_bytes[..name_bytes.len().min(32)]);
+    name_hash
+}
+
+/// v5: Pay out of an escrow token account. The token account is its own
+/// authority, seeded by ["escrow_vault", owner], where `owner` is the escrow
+/// (or contract) account holding the funds.
+fn transfer_from_escrow<'info>(
+    token_program: &Program<'info, Token>,
+    escrow_vault: &Account<'info, TokenAccount>,
+    to: AccountInfo<'info>,
+    owner: Pubkey,
+    vault_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[
+        b"escrow_vault".as_ref(),
+        owner.as_ref(),
+        &[vault_bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: escrow_vault.to_account_info(),
+                to,
+                authority: escrow_vault.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        amount,
+    )
+}
+
+/// v5: Pay out milestone `index`: `payee_amount` to the payee and the rest of
+/// the milestone back to the payer. Closes the contract once every milestone
+/// has been paid out.
+fn release_milestone(
+    accounts: &mut SettleContract,
+    index: u8,
+    payee_amount: u64,
+    status: u8,
+) -> Result<()> {
+    let contract = &mut accounts.contract;
+    let milestone = &mut contract.milestones[index as usize];
+    let payer_amount = milestone.amount
+        .checked_sub(payee_amount)
+        .ok_or(SolclawError::Overflow)?;
+    milestone.status = status;
+
+    if payee_amount > 0 {
+        transfer_from_escrow(
+            &accounts.token_program,
+            &accounts.escrow_vault,
+            accounts.payee_vault.to_account_info(),
+            contract.key(),
+            contract.vault_bump,
+            payee_amount,
+        )?;
+    }
+    if payer_amount > 0 {
+        transfer_from_escrow(
+            &accounts.token_program,
+            &accounts.escrow_vault,
+            accounts.payer_vault.to_account_info(),
+            contract.key(),
+            contract.vault_bump,
+            payer_amount,
+        )?;
+    }
+
+    contract.released_amount = contract.released_amount
+        .checked_add(payee_amount)
+        .ok_or(SolclawError::Overflow)?;
+    contract.refunded_amount = contract.refunded_amount
+        .checked_add(payer_amount)
+        .ok_or(SolclawError::Overflow)?;
+    let completed = contract.unpaid_amount()? == 0;
+    if completed {
+        contract.status = Contract::STATUS_COMPLETED;
+    }
+
+    record_escrow_payment(
+        &mut accounts.payer_registry,
+        &mut accounts.payee_registry,
+        payee_amount,
+    )?;
+
+    emit!(MilestoneApprovedEvent {
+        contract: contract.key(),
+        index,
+        payer: contract.payer_name.clone(),
+        payee: contract.payee_name.clone(),
+        amount: payee_amount,
+        refunded: payer_amount,
+        released_amount: contract.released_amount,
+        resolved: status == Milestone::STATUS_RESOLVED,
+        completed,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!(
+        "Contract #{} milestone {} paid out: {} USDC to {}, {} USDC back to {}",
+        contract.id,
+        index,
+        payee_amount as f64 / 1_000_000.0,
+        contract.payee_name,
+        payer_amount as f64 / 1_000_000.0,
+        contract.payer_name
+    );
+
+    if completed {
+        close_settled_contract(accounts)?;
+    }
+
+    Ok(())
+}
+
+/// v5: Close a completed or cancelled contract together with its vault.
+fn close_settled_contract(accounts: &mut SettleContract) -> Result<()> {
+    close_escrow_vault(
+        &accounts.token_program,
+        &mut accounts.escrow_vault,
+        accounts.payer_vault.to_account_info(),
+        accounts.rent_receiver.to_account_info(),
+        accounts.contract.key(),
+        accounts.contract.vault_bump,
+    )?;
+    accounts.contract.close(accounts.rent_receiver.to_account_info())
+}
+
+/// v5: Close a ["escrow_vault", owner] token account once the owning account
+/// is fully settled and return its rent to `destination`. Tokens sent to the
+/// vault from outside (which would otherwise block the close) go to `sweep_to`.
+fn close_escrow_vault<'info>(
+    token_program: &Program<'info, Token>,
+    escrow_vault: &mut Account<'info, TokenAccount>,
+    sweep_to: AccountInfo<'info>,
+    destination: AccountInfo<'info>,
+    owner: Pubkey,
+    vault_bump: u8,
+) -> Result<()> {
+    escrow_vault.reload()?;
+    if escrow_vault.amount > 0 {
+        transfer_from_escrow(
+            token_program,
+            escrow_vault,
+            sweep_to,
+            owner,
+            vault_bump,
+            escrow_vault.amount,
+        )?;
+    }
+
+    let seeds = &[
+        b"escrow_vault".as_ref(),
+        owner.as_ref(),
+        &[vault_bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    token::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: escrow_vault.to_account_info(),
+            destination,
+            authority: escrow_vault.to_account_info(),
+        },
+        signer_seeds,
+    ))
+}
+
+/// v5: Message signed off-chain by the payer for a payment channel voucher:
+/// "solclaw:voucher:v1" || channel || cumulative_amount (u64 LE).
+pub fn voucher_message(channel: &Pubkey, cumulative_amount: u64) -> Vec<u8> {
+    let mut message = Vec::with_capacity(VOUCHER_DOMAIN.len() + 32 + 8);
+    message.extend_from_slice(VOUCHER_DOMAIN);
+    message.extend_from_slice(channel.as_ref());
+    message.extend_from_slice(&cumulative_amount.to_le_bytes());
+    message
+}
+
+/// v5: Require that an Ed25519 program instruction earlier in this transaction
+/// verified `signer`'s signature over `message`. The Ed25519 precompile
+/// rejects the whole transaction on a bad signature, so finding a matching
+/// entry is enough.
+fn verify_ed25519_signature(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> Result<()> {
+    let current_index = solana_instructions_sysvar::load_current_index_checked(instructions)?;
+
+    for index in 0..current_index {
+        let ix = solana_instructions_sysvar::load_instruction_at_checked(index as usize, instructions)?;
+        if ix.program_id == solana_sdk_ids::ed25519_program::ID
+            && ed25519_instruction_has_signature(&ix.data, signer, message)
+        {
+            return Ok(());
+        }
+    }
+
+    err!(SolclawError::MissingSignatureVerification)
+}
+
+/// v5: Whether Ed25519 program instruction data contains a signature by
+/// `signer` over exactly `message`. Only entries whose signature, key and
+/// message live in the same instruction are considered.
+pub fn ed25519_instruction_has_signature(data: &[u8], signer: &Pubkey, message: &[u8]) -> bool {
+    const OFFSETS_START: usize = 2;
+    const OFFSETS_SIZE: usize = 14;
+    const SAME_INSTRUCTION: u16 = u16::MAX;
+
+    let read_u16 = |at: usize| -> Option<u16> {
+        data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    };
+
+    let count = match data.first() {
+        Some(count) => *count as usize,
+        None => return false,
+    };
+
+    (0..count).any(|i| {
+        let start = OFFSETS_START + i * OFFSETS_SIZE;
+        let entry = (|| {
+            let signature_ix = read_u16(start + 2)?;
+            let pubkey_offset = read_u16(start + 4)? as usize;
+            let pubkey_ix = read_u16(start + 6)?;
+            let message_offset = read_u16(start + 8)? as usize;
+            let message_size = read_u16(start + 10)? as usize;
+            let message_ix = read_u16(start + 12)?;
+
+            if signature_ix != SAME_INSTRUCTION
+                || pubkey_ix != SAME_INSTRUCTION
+                || message_ix != SAME_INSTRUCTION
+            {
+                return None;
+            }
+
+            let pubkey = data.get(pubkey_offset..pubkey_offset + 32)?;
+            let signed = data.get(message_offset..message_offset + message_size)?;
+            Some(pubkey == signer.as_ref() && signed == message)
+        })();
+        entry.unwrap_or(false)
+    })
+}
+
+/// v5: Message signed off-chain by the sender's authority for a payment intent:
+/// "solclaw:intent:v1" || sender registry || receiver registry || amount
+/// || nonce || expiry || max_relayer_fee (integers little-endian).
+pub fn intent_message(
+    sender: &Pubkey,
+    receiver: &Pubkey,
+    amount: u64,
+    nonce: u64,
+    expiry: i64,
+    max_relayer_fee: u64,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(INTENT_DOMAIN.len() + 32 + 32 + 8 * 4);
+    message.extend_from_slice(INTENT_DOMAIN);
+    message.extend_from_slice(sender.as_ref());
+    message.extend_from_slice(receiver.as_ref());
+    message.extend_from_slice(&amount.to_le_bytes());
+    message.extend_from_slice(&nonce.to_le_bytes());
+    message.extend_from_slice(&expiry.to_le_bytes());
+    message.extend_from_slice(&max_relayer_fee.to_le_bytes());
+    message
+}
+
+/// v5: Message signed off-chain by the consumer for a usage checkpoint:
+/// "solclaw:usage:v1" || plan || cumulative_units (u64 LE).
+pub fn usage_message(plan: &Pubkey, cumulative_units: u64) -> Vec<u8> {
+    let mut message = Vec::with_capacity(USAGE_DOMAIN.len() + 32 + 8);
+    message.extend_from_slice(USAGE_DOMAIN);
+    message.extend_from_slice(plan.as_ref());
+    message.extend_from_slice(&cumulative_units.to_le_bytes());
+    message
+}
+
+/// v5: Split `total` by basis-point shares (which must sum to 10000) and
+/// allocate the rounding dust by `rounding`. Returns the per-recipient
+/// amounts, which always sum to `total`, and the dust allocated.
+fn compute_split_amounts(
+    total: u64,
+    shares: &[u16],
+    rounding: SplitRounding,
+) -> Result<(Vec<u64>, u64)> {
+    let mut amounts = Vec::with_capacity(shares.len());
+    let mut remainders = Vec::with_capacity(shares.len());
+    let mut distributed: u64 = 0;
+
+    for bps in shares {
+        // amount = total * share_bps / 10000
+        let product = (total as u128) * (*bps as u128);
+        let amount = (product / 10000) as u64;
+        amounts.push(amount);
+        remainders.push((product % 10000) as u64);
+        distributed = distributed.checked_add(amount).ok_or(SolclawError::Overflow)?;
+    }
+
+    let dust = total.checked_sub(distributed).ok_or(SolclawError::Overflow)?;
+
+    let mut order: Vec<usize> = (0..shares.len()).collect();
+    match rounding {
+        SplitRounding::RemainderTo(index) => {
+            let index = index as usize;
+            require!(index < shares.len(), SolclawError::InvalidSplitRounding);
+            amounts[index] = amounts[index].checked_add(dust).ok_or(SolclawError::Overflow)?;
+            return Ok((amounts, dust));
+        }
+        // Stable sorts keep list order among ties
+        SplitRounding::LargestRemainder => order.sort_by(|a, b| remainders[*b].cmp(&remainders[*a])),
+        SplitRounding::LargestShareFirst => order.sort_by(|a, b| shares[*b].cmp(&shares[*a])),
+    }
+
+    // dust < number of recipients with a non-zero share, so one unit each suffices
+    for index in order.into_iter().take(dust as usize) {
+        amounts[index] += 1;
+    }
+
+    Ok((amounts, dust))
+}
+
+/// v5: Who a split share is for: a name given by the payer (split_payment)
+/// or a registry stored in a SplitConfig.
+enum SplitTarget<'a> {
+    Name(&'a str),
+    Registry(Pubkey),
+}
+
+/// v5: Walks a possibly nested split, paying every leaf directly from one
+/// vault. Remaining accounts are consumed in pre-order: a direct recipient
+/// takes [registry, vault], a cascading one takes [registry, split_config]
+/// followed by the accounts of that config's recipients. Cascading agents
+/// never hold the funds, so only leaf registries are credited. The payer
+/// can't appear anywhere in the tree.
+struct SplitPayout<'a, 'info> {
+    token_program: AccountInfo<'info>,
+    from_vault: AccountInfo<'info>,
+    signer_seeds: &'a [&'a [&'a [u8]]],
+    remaining: &'info [AccountInfo<'info>],
+    cursor: usize,
+    path: Vec<Pubkey>,
+    nodes: Vec<SplitPayoutNode>,
+}
+
+impl<'a, 'info> SplitPayout<'a, 'info> {
+    fn new(
+        token_program: AccountInfo<'info>,
+        from_vault: AccountInfo<'info>,
+        signer_seeds: &'a [&'a [&'a [u8]]],
+        remaining: &'info [AccountInfo<'info>],
+        payer: Pubkey,
+    ) -> Self {
+        Self {
+            token_program,
+            from_vault,
+            signer_seeds,
+            remaining,
+            cursor: 0,
+            path: vec![payer],
+            nodes: Vec::new(),
+        }
+    }
+
+    fn next_account(&mut self) -> Result<&'info AccountInfo<'info>> {
+        let info = self
+            .remaining
+            .get(self.cursor)
+            .ok_or(SolclawError::InvalidRemainingAccounts)?;
+        self.cursor += 1;
+        Ok(info)
+    }
+
+    fn push_node(&mut self, node: SplitPayoutNode) -> Result<u8> {
+        require!(
+            self.nodes.len() < MAX_SPLIT_PAYOUT_NODES,
+            SolclawError::SplitTreeTooLarge
+        );
+        self.nodes.push(node);
+        Ok((self.nodes.len() - 1) as u8)
+    }
+
+    /// Pay one share, either into the recipient's vault or through its config.
+    fn pay(
+        &mut self,
+        target: SplitTarget,
+        cascade: bool,
+        amount: u64,
+        parent: u8,
+        depth: u8,
+    ) -> Result<()> {
+        let registry_info = self.next_account()?;
+        let next_info = self.next_account()?;
+        if let SplitTarget::Registry(expected) = target {
+            require!(
+                registry_info.key() == expected,
+                SolclawError::SplitRecipientMismatch
+            );
+        }
+        // The payer can't receive any share, directly or through a nested config
+        require!(
+            registry_info.key() != self.path[0],
+            SolclawError::CannotSplitToSelf
+        );
+
+        if cascade {
+            require!(
+                (depth as usize) < MAX_SPLIT_DEPTH,
+                SolclawError::SplitTooDeep
+            );
+            let registry = Account::<AgentRegistry>::try_from(registry_info)?;
+            if let SplitTarget::Name(name) = target {
+                require!(registry.name == name, SolclawError::NameMismatch);
+            }
+            require!(
+                !self.path.contains(&registry_info.key()),
+                SolclawError::SplitCycle
+            );
+
+            // Only create_split_config writes a SplitConfig, at ["split_config", owner]
+            let config = Account::<SplitConfig>::try_from(next_info)?;
+            require!(
+                config.owner == registry_info.key(),
+                SolclawError::SplitRecipientMismatch
+            );
+
+            let index = self.push_node(SplitPayoutNode {
+                registry: registry_info.key(),
+                parent,
+                depth,
+                amount,
+                cascaded: true,
+            })?;
+            self.pay_config(&config, amount, index, depth + 1)?;
+            return Ok(());
+        }
+
+        self.push_node(SplitPayoutNode {
+            registry: registry_info.key(),
+            parent,
+            depth,
+            amount,
+            cascaded: false,
+        })?;
+        if amount == 0 {
+            return Ok(());
+        }
+
+        // v5: Validate recipient registry and vault PDAs with stored bumps
+        let expected_name = match target {
+            SplitTarget::Name(name) => Some(name),
+            SplitTarget::Registry(_) => None,
+        };
+        let mut recipient_registry =
+            load_recipient(registry_info, next_info, expected_name, self.path[0])?;
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                self.token_program.clone(),
+                Transfer {
+                    from: self.from_vault.clone(),
+                    to: next_info.clone(),
+                    authority: self.from_vault.clone(),
+                },
+                self.signer_seeds,
+            ),
+            amount,
+        )?;
+
+        credit_recipient(&mut recipient_registry, amount)
+    }
+
+    /// Split `amount` across a config's recipients. Returns their amounts.
+    fn pay_config(
+        &mut self,
+        config: &SplitConfig,
+        amount: u64,
+        parent: u8,
+        depth: u8,
+    ) -> Result<Vec<u64>> {
+        let shares: Vec<u16> = config.recipients.iter().map(|r| r.share_bps).collect();
+        let (amounts, _) = compute_split_amounts(amount, &shares, config.rounding)?;
+        // Minimums only apply when something actually flows through this config
+        if amount > 0 {
+            for (entry, share) in config.recipients.iter().zip(amounts.iter()) {
+                require!(*share >= entry.min_amount, SolclawError::SplitBelowMinimum);
+            }
+        }
+
+        self.path.push(config.owner);
+        for (entry, share) in config.recipients.iter().zip(amounts.iter()) {
+            self.pay(
+                SplitTarget::Registry(entry.registry),
+                entry.cascade,
+                *share,
+                parent,
+                depth,
+            )?;
+        }
+        self.path.pop();
+
+        Ok(amounts)
+    }
+
+    /// Check every remaining account was used and return the payout tree.
+    fn finish(self) -> Result<Vec<SplitPayoutNode>> {
+        require!(
+            self.cursor == self.remaining.len(),
+            SolclawError::InvalidRemainingAccounts
+        );
+        Ok(self.nodes)
+    }
+}
+
+/// v5: Validate split configuration recipients against the registries passed
+/// in `registries` (one per recipient, in order) and resolve them to
+/// AgentRegistry keys. Shares must sum to 10000 and the owner cannot be a
+/// recipient of its own split.
+fn build_split_config_entries<'info>(
+    recipients: &[SplitRecipient],
+    rounding: SplitRounding,
+    registries: &'info [AccountInfo<'info>],
+    owner: Pubkey,
+) -> Result<Vec<SplitConfigEntry>> {
+    require!(
+        !recipients.is_empty() && recipients.len() <= MAX_SPLIT_CONFIG_RECIPIENTS,
+        SolclawError::TooManySplitRecipients
+    );
+    require!(
+        registries.len() == recipients.len(),
+        SolclawError::InvalidRemainingAccounts
+    );
+
+    let total_bps: u64 = recipients.iter().map(|r| r.share_bps as u64).sum();
+    require!(total_bps == 10000, SolclawError::InvalidSplitShares);
+    if let SplitRounding::RemainderTo(index) = rounding {
+        require!((index as usize) < recipients.len(), SolclawError::InvalidSplitRounding);
+    }
+
+    let mut entries = Vec::with_capacity(recipients.len());
+    for (recipient, info) in recipients.iter().zip(registries.iter()) {
+        let registry = Account::<AgentRegistry>::try_from(info)?;
+        require!(
+            registry.name_hash == name_to_hash(&recipient.name),
+            SolclawError::NameMismatch
+        );
+        let expected_registry_pda = Pubkey::create_program_address(
+            &[b"agent", registry.name.as_bytes(), &[registry.bump]],
+            &crate::ID,
+        )
+        .map_err(|_| error!(SolclawError::NameMismatch))?;
+        require!(info.key() == expected_registry_pda, SolclawError::NameMismatch);
+        require!(info.key() != owner, SolclawError::CannotSplitToSelf);
+
+        entries.push(SplitConfigEntry {
+            registry: info.key(),
+            share_bps: recipient.share_bps,
+            min_amount: recipient.min_amount,
+            cascade: recipient.cascade,
+        });
+    }
+
+    Ok(entries)
+}
+
+// ============================================================
+// ACCOUNT STRUCTS
+// ============================================================
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct RegisterAgent<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + AgentRegistry::INIT_SPACE,
+        seeds = [b"agent", name.as_bytes()],
+        bump
+    )]
+    pub agent_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"vault", name.as_bytes()],
+        bump,
+        token::mint = usdc_mint,
+        token::authority = vault,
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key().to_string() == USDC_MINT @ SolclawError::InvalidMint
+    )]
+    pub usdc_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(
+        seeds = [b"agent", agent_registry.name.as_bytes()],
+        bump = agent_registry.bump,
+    )]
+    pub agent_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", agent_registry.name.as_bytes()],
+        bump = agent_registry.vault_bump,
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_account.mint == vault.mint @ SolclawError::InvalidMint
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct TransferByName<'info> {
+    #[account(
+        mut,
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", sender_registry.name.as_bytes()],
+        bump = sender_registry.vault_bump,
+    )]
+    pub sender_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.vault_bump,
+    )]
+    pub receiver_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(
+        seeds = [b"agent", agent_registry.name.as_bytes()],
+        bump = agent_registry.bump,
+    )]
+    pub agent_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", agent_registry.name.as_bytes()],
+        bump = agent_registry.vault_bump,
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = destination.mint == vault.mint @ SolclawError::InvalidMint
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = agent_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct BatchPayment<'info> {
+    #[account(
+        mut,
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", sender_registry.name.as_bytes()],
+        bump = sender_registry.vault_bump,
+    )]
+    pub sender_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SplitPayment<'info> {
+    #[account(
+        mut,
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", sender_registry.name.as_bytes()],
+        bump = sender_registry.vault_bump,
+    )]
+    pub sender_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(subscription_id: u64, receiver_name: String)]
+pub struct CreateSubscription<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Subscription::INIT_SPACE,
+        seeds = [
+            b"subscription",
+            sender_registry.key().as_ref(),
+            receiver_registry.key().as_ref(),
+            subscription_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteSubscription<'info> {
+    #[account(
+        mut,
+        constraint = subscription.sender == sender_registry.key() @ SolclawError::InvalidSubscription,
+        constraint = subscription.receiver == receiver_registry.key() @ SolclawError::InvalidSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", sender_registry.name.as_bytes()],
+        bump = sender_registry.vault_bump,
+    )]
+    pub sender_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.vault_bump,
+    )]
+    pub receiver_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// Anyone can crank — no authority constraint
+    pub cranker: Signer<'info>,
+
+    /// v5: USDC account receiving the crank tip; omit it to forgo the tip
+    #[account(
+        mut,
+        constraint = cranker_token_account.mint == sender_vault.mint @ SolclawError::InvalidMint
+    )]
+    pub cranker_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// v5: Supplies the default crank tip when the subscription sets none
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+    )]
+    pub protocol_config: Option<Account<'info, ProtocolConfig>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSubscription<'info> {
+    #[account(mut)]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        constraint = subscription.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+}
+
+/// v5: Shared by pause_subscription and resume_subscription.
+#[derive(Accounts)]
+pub struct PauseSubscription<'info> {
+    #[account(mut)]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        constraint = subscription.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+}
+
+/// v5: Receiver-side proposal to change a subscription's terms.
+#[derive(Accounts)]
+pub struct ProposeSubscriptionChange<'info> {
+    #[account(
+        mut,
+        constraint = subscription.receiver == receiver_registry.key() @ SolclawError::InvalidSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        constraint = receiver_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+}
+
+/// v5: Sender-only changes to a subscription's settings.
+#[derive(Accounts)]
+pub struct UpdateSubscription<'info> {
+    #[account(mut)]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        constraint = subscription.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSubscription<'info> {
+    #[account(
+        mut,
+        close = payer,
+        constraint = subscription.payer == payer.key() @ SolclawError::InvalidSubscription,
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        constraint = subscription.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Original rent payer, receives the lamports
+    #[account(mut)]
+    pub payer: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(subscription_id: u64)]
+pub struct MigrateSubscription<'info> {
+    /// CHECK: Pre-v5 layout, decoded and closed by hand in the handler
+    #[account(
+        mut,
+        seeds = [
+            b"subscription",
+            sender_registry.key().as_ref(),
+            receiver_registry.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub legacy_subscription: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Subscription::INIT_SPACE,
+        seeds = [
+            b"subscription",
+            sender_registry.key().as_ref(),
+            receiver_registry.key().as_ref(),
+            subscription_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    /// Legacy authority; pays for the new account and gets the old rent back
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// v5: Subscription Offer Accounts
+
+#[derive(Accounts)]
+#[instruction(offer_id: u64)]
+pub struct CreateSubscriptionOffer<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SubscriptionOffer::INIT_SPACE,
+        seeds = [
+            b"subscription_offer",
+            receiver_registry.key().as_ref(),
+            offer_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub offer: Account<'info, SubscriptionOffer>,
+
+    #[account(
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        constraint = receiver_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSubscriptionOffer<'info> {
+    #[account(
+        mut,
+        has_one = authority @ SolclawError::Unauthorized,
+    )]
+    pub offer: Account<'info, SubscriptionOffer>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSubscriptionOffer<'info> {
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority @ SolclawError::Unauthorized,
+    )]
+    pub offer: Account<'info, SubscriptionOffer>,
+
+    /// The receiver's wallet, receives the rent
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(subscription_id: u64)]
+pub struct AcceptOffer<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"subscription_offer",
+            receiver_registry.key().as_ref(),
+            offer.id.to_le_bytes().as_ref(),
+        ],
+        bump = offer.bump,
+    )]
+    pub offer: Account<'info, SubscriptionOffer>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Subscription::INIT_SPACE,
+        seeds = [
+            b"subscription",
+            sender_registry.key().as_ref(),
+            receiver_registry.key().as_ref(),
+            subscription_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// v5: Protocol Config Accounts
+
+#[derive(Accounts)]
+pub struct InitProtocolConfig<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ProtocolConfig::INIT_SPACE,
+        seeds = [b"protocol_config"],
+        bump,
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key())
+            @ SolclawError::Unauthorized,
+    )]
+    pub program: Program<'info, crate::program::Solclaw>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ SolclawError::Unauthorized,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    /// Must be the program's upgrade authority
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProtocolConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        has_one = authority @ SolclawError::Unauthorized,
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    pub authority: Signer<'info>,
+}
+
+// v3: Spending Cap Accounts
+#[derive(Accounts)]
+pub struct SetDailyLimit<'info> {
+    #[account(
+        mut,
+        seeds = [b"agent", registry.name.as_bytes()],
+        bump = registry.bump,
+        constraint = registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub registry: Account<'info, AgentRegistry>,
+
+    pub authority: Signer<'info>,
+}
+
+// v3: Allowance Accounts
+#[derive(Accounts)]
+#[instruction(spender_name: String)]
+pub struct Approve<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + Allowance::INIT_SPACE,
+        seeds = [
+            b"allowance",
+            owner_registry.key().as_ref(),
+            spender_registry.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(
+        seeds = [b"agent", owner_registry.name.as_bytes()],
+        bump = owner_registry.bump,
+    )]
+    pub owner_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        seeds = [b"agent", spender_registry.name.as_bytes()],
+        bump = spender_registry.bump,
+    )]
+    pub spender_registry: Account<'info, AgentRegistry>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TransferFrom<'info> {
+    #[account(
+        mut,
+        constraint = allowance.owner == owner_registry.key() @ SolclawError::AllowanceMismatch,
+        constraint = allowance.spender == spender_registry.key() @ SolclawError::AllowanceMismatch,
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", owner_registry.name.as_bytes()],
+        bump = owner_registry.bump,
+    )]
+    pub owner_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", spender_registry.name.as_bytes()],
+        bump = spender_registry.bump,
+    )]
+    pub spender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", owner_registry.name.as_bytes()],
+        bump = owner_registry.vault_bump,
+    )]
+    pub owner_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", spender_registry.name.as_bytes()],
+        bump = spender_registry.vault_bump,
+    )]
+    pub spender_vault: Account<'info, TokenAccount>,
+
+    /// The spender's wallet — they initiate the pull
+    pub spender_authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeAllowance<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"allowance",
+            allowance.owner.as_ref(),
+            allowance.spender.as_ref(),
+        ],
+        bump = allowance.bump,
+        constraint = allowance.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ModifyAllowance<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"allowance",
+            allowance.owner.as_ref(),
+            allowance.spender.as_ref(),
+        ],
+        bump = allowance.bump,
+        constraint = allowance.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    pub authority: Signer<'info>,
+}
+
+// v4: Invoice Accounts
+
+#[derive(Accounts)]
+pub struct InitInvoiceCounter<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = InvoiceCounter::SIZE,
+        seeds = [b"invoice_counter"],
+        bump,
+    )]
+    pub counter: Account<'info, InvoiceCounter>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(payer_name: String, amount: u64, memo: String, expires_in_seconds: i64)]
+pub struct CreateInvoice<'info> {
+    #[account(
+        init,
+        payer = fee_payer,
+        space = Invoice::SIZE,
+        seeds = [b"invoice", counter.count.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [b"invoice_counter"],
+        bump = counter.bump,
+    )]
+    pub counter: Account<'info, InvoiceCounter>,
+
+    pub requester_registry: Account<'info, AgentRegistry>,
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PayInvoice<'info> {
+    #[account(
+        mut,
+        constraint = invoice.payer == payer_registry.key() @ SolclawError::InvoiceMismatch,
+        constraint = invoice.requester == requester_registry.key() @ SolclawError::InvoiceMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(mut)]
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    #[account(mut)]
+    pub requester_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        constraint = payer_vault.key() == payer_registry.vault @ SolclawError::VaultMismatch
+    )]
+    pub payer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = requester_vault.key() == requester_registry.vault @ SolclawError::VaultMismatch
+    )]
+    pub requester_vault: Account<'info, TokenAccount>,
+
+    /// The payer's wallet
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RejectInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [b"invoice", invoice.id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        constraint = invoice.payer == payer_registry.key() @ SolclawError::InvoiceMismatch
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        seeds = [b"agent", payer_registry.name.as_bytes()],
+        bump = payer_registry.bump,
+        constraint = payer_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [b"invoice", invoice.id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        constraint = invoice.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    pub authority: Signer<'info>,
+}
+
+// v5: Refund Accounts
+#[derive(Accounts)]
+pub struct Refund<'info> {
+    #[account(
+        mut,
+        seeds = [b"invoice", invoice.id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        constraint = invoice.requester == requester_registry.key() @ SolclawError::InvoiceMismatch,
+        constraint = invoice.payer == payer_registry.key() @ SolclawError::InvoiceMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", requester_registry.name.as_bytes()],
+        bump = requester_registry.bump,
+        constraint = requester_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub requester_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", payer_registry.name.as_bytes()],
+        bump = payer_registry.bump,
+    )]
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", requester_registry.name.as_bytes()],
+        bump = requester_registry.vault_bump,
+    )]
+    pub requester_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", payer_registry.name.as_bytes()],
+        bump = payer_registry.vault_bump,
+    )]
+    pub payer_vault: Account<'info, TokenAccount>,
+
+    /// The requester's wallet — the original receiver issues the refund
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// v5: Escrow Accounts
+#[derive(Accounts)]
+#[instruction(escrow_id: u64)]
+pub struct CreateEscrow<'info> {
+    #[account(
+        init,
+        payer = fee_payer,
+        space = 8 + Escrow::INIT_SPACE,
+        seeds = [
+            b"escrow",
+            payer_registry.key().as_ref(),
+            escrow_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        init,
+        payer = fee_payer,
+        seeds = [b"escrow_vault", escrow.key().as_ref()],
+        bump,
+        token::mint = usdc_mint,
+        token::authority = escrow_vault,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key().to_string() == USDC_MINT @ SolclawError::InvalidMint
+    )]
+    pub usdc_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", payer_registry.name.as_bytes()],
+        bump = payer_registry.bump,
+        constraint = payer_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", payer_registry.name.as_bytes()],
+        bump = payer_registry.vault_bump,
+    )]
+    pub payer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"agent", payee_registry.name.as_bytes()],
+        bump = payee_registry.bump,
+    )]
+    pub payee_registry: Account<'info, AgentRegistry>,
+
+    /// The payer's wallet
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Shared by release_escrow, refund_escrow and resolve_escrow.
+/// Who may sign depends on the instruction and is checked in the handler.
+#[derive(Accounts)]
+pub struct SettleEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"escrow",
+            escrow.payer.as_ref(),
+            escrow.id.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.payer == payer_registry.key() @ SolclawError::EscrowMismatch,
+        constraint = escrow.payee == payee_registry.key() @ SolclawError::EscrowMismatch,
+        close = rent_receiver,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", escrow.key().as_ref()],
+        bump = escrow.vault_bump,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", payer_registry.name.as_bytes()],
+        bump = payer_registry.bump,
+    )]
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", payer_registry.name.as_bytes()],
+        bump = payer_registry.vault_bump,
+    )]
+    pub payer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", payee_registry.name.as_bytes()],
+        bump = payee_registry.bump,
+    )]
+    pub payee_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", payee_registry.name.as_bytes()],
+        bump = payee_registry.vault_bump,
+    )]
+    pub payee_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: The payer's wallet, receives the escrow and vault rent
+    #[account(mut, address = escrow.authority @ SolclawError::Unauthorized)]
+    pub rent_receiver: UncheckedAccount<'info>,
+
+    /// Payer, payee or arbiter wallet, depending on the instruction
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// v5: Milestone Contract Accounts
+#[derive(Accounts)]
+#[instruction(contract_id: u64)]
+pub struct CreateContract<'info> {
+    #[account(
+        init,
+        payer = fee_payer,
+        space = 8 + Contract::INIT_SPACE,
+        seeds = [
+            b"contract",
+            payer_registry.key().as_ref(),
+            contract_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub contract: Account<'info, Contract>,
+
+    #[account(
+        init,
+        payer = fee_payer,
+        seeds = [b"escrow_vault", contract.key().as_ref()],
+        bump,
+        token::mint = usdc_mint,
+        token::authority = escrow_vault,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key().to_string() == USDC_MINT @ SolclawError::InvalidMint
+    )]
+    pub usdc_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", payer_registry.name.as_bytes()],
+        bump = payer_registry.bump,
+        constraint = payer_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", payer_registry.name.as_bytes()],
+        bump = payer_registry.vault_bump,
+    )]
+    pub payer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"agent", payee_registry.name.as_bytes()],
+        bump = payee_registry.bump,
+    )]
+    pub payee_registry: Account<'info, AgentRegistry>,
+
+    /// The payer's wallet
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Shared by submit_milestone and dispute_milestone (no funds move).
+#[derive(Accounts)]
+pub struct UpdateMilestone<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.payer.as_ref(),
+            contract.id.to_le_bytes().as_ref(),
+        ],
+        bump = contract.bump,
+        constraint = contract.payer == payer_registry.key() @ SolclawError::ContractMismatch,
+        constraint = contract.payee == payee_registry.key() @ SolclawError::ContractMismatch,
+    )]
+    pub contract: Account<'info, Contract>,
+
+    pub payer_registry: Account<'info, AgentRegistry>,
+    pub payee_registry: Account<'info, AgentRegistry>,
+
+    /// Payer or payee wallet, depending on the instruction
+    pub authority: Signer<'info>,
+}
+
+/// Shared by approve_milestone and cancel_contract.
+#[derive(Accounts)]
+pub struct SettleContract<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.payer.as_ref(),
+            contract.id.to_le_bytes().as_ref(),
+        ],
+        bump = contract.bump,
+        constraint = contract.payer == payer_registry.key() @ SolclawError::ContractMismatch,
+        constraint = contract.payee == payee_registry.key() @ SolclawError::ContractMismatch,
+    )]
+    pub contract: Account<'info, Contract>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", contract.key().as_ref()],
+        bump = contract.vault_bump,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", payer_registry.name.as_bytes()],
+        bump = payer_registry.bump,
+    )]
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", payer_registry.name.as_bytes()],
+        bump = payer_registry.vault_bump,
+    )]
+    pub payer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", payee_registry.name.as_bytes()],
+        bump = payee_registry.bump,
+    )]
+    pub payee_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", payee_registry.name.as_bytes()],
+        bump = payee_registry.vault_bump,
+    )]
+    pub payee_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: The payer's wallet, receives the contract and vault rent
+    #[account(mut, address = contract.authority @ SolclawError::Unauthorized)]
+    pub rent_receiver: UncheckedAccount<'info>,
+
+    /// Payer or payee wallet, depending on the instruction
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// v5: HTLC Accounts
+#[derive(Accounts)]
+#[instruction(htlc_id: u64)]
+pub struct CreateHtlc<'info> {
+    #[account(
+        init,
+        payer = fee_payer,
+        space = 8 + Htlc::INIT_SPACE,
+        seeds = [
+            b"htlc",
+            sender_registry.key().as_ref(),
+            htlc_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub htlc: Account<'info, Htlc>,
+
+    #[account(
+        init,
+        payer = fee_payer,
+        seeds = [b"escrow_vault", htlc.key().as_ref()],
+        bump,
+        token::mint = usdc_mint,
+        token::authority = escrow_vault,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key().to_string() == USDC_MINT @ SolclawError::InvalidMint
+    )]
+    pub usdc_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", sender_registry.name.as_bytes()],
+        bump = sender_registry.vault_bump,
+    )]
+    pub sender_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    /// The sender's wallet
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Shared by claim_htlc and refund_htlc.
+#[derive(Accounts)]
+pub struct SettleHtlc<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"htlc",
+            htlc.sender.as_ref(),
+            htlc.id.to_le_bytes().as_ref(),
+        ],
+        bump = htlc.bump,
+        constraint = htlc.sender == sender_registry.key() @ SolclawError::HtlcMismatch,
+        constraint = htlc.receiver == receiver_registry.key() @ SolclawError::HtlcMismatch,
+        close = rent_receiver,
+    )]
+    pub htlc: Account<'info, Htlc>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", htlc.key().as_ref()],
+        bump = htlc.vault_bump,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", sender_registry.name.as_bytes()],
+        bump = sender_registry.vault_bump,
+    )]
+    pub sender_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.vault_bump,
+    )]
+    pub receiver_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: The sender's wallet, receives the HTLC and vault rent
+    #[account(mut, address = htlc.authority @ SolclawError::Unauthorized)]
+    pub rent_receiver: UncheckedAccount<'info>,
+
+    /// Receiver wallet (claim) or sender wallet (refund)
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// v5: Stream Accounts
+#[derive(Accounts)]
+#[instruction(stream_id: u64)]
+pub struct CreateStream<'info> {
+    #[account(
+        init,
+        payer = fee_payer,
+        space = 8 + Stream::INIT_SPACE,
+        seeds = [
+            b"stream",
+            sender_registry.key().as_ref(),
+            stream_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub stream: Account<'info, Stream>,
+
+    #[account(
+        init,
+        payer = fee_payer,
+        seeds = [b"escrow_vault", stream.key().as_ref()],
+        bump,
+        token::mint = usdc_mint,
+        token::authority = escrow_vault,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key().to_string() == USDC_MINT @ SolclawError::InvalidMint
+    )]
+    pub usdc_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", sender_registry.name.as_bytes()],
+        bump = sender_registry.vault_bump,
+    )]
+    pub sender_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    /// The sender's wallet
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct TopUpStream<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"stream",
+            stream.sender.as_ref(),
+            stream.id.to_le_bytes().as_ref(),
+        ],
+        bump = stream.bump,
+        constraint = stream.sender == sender_registry.key() @ SolclawError::StreamMismatch,
+    )]
+    pub stream: Account<'info, Stream>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", stream.key().as_ref()],
+        bump = stream.vault_bump,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", sender_registry.name.as_bytes()],
+        bump = sender_registry.vault_bump,
+    )]
+    pub sender_vault: Account<'info, TokenAccount>,
+
+    /// The sender's wallet
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Shared by withdraw_stream and cancel_stream.
+#[derive(Accounts)]
+pub struct SettleStream<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"stream",
+            stream.sender.as_ref(),
+            stream.id.to_le_bytes().as_ref(),
+        ],
+        bump = stream.bump,
+        constraint = stream.sender == sender_registry.key() @ SolclawError::StreamMismatch,
+        constraint = stream.receiver == receiver_registry.key() @ SolclawError::StreamMismatch,
+    )]
+    pub stream: Account<'info, Stream>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", stream.key().as_ref()],
+        bump = stream.vault_bump,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", sender_registry.name.as_bytes()],
+        bump = sender_registry.vault_bump,
+    )]
+    pub sender_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.vault_bump,
+    )]
+    pub receiver_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: The sender's wallet, receives the stream and vault rent on cancel
+    #[account(mut, address = stream.authority @ SolclawError::Unauthorized)]
+    pub rent_receiver: UncheckedAccount<'info>,
+
+    /// Receiver wallet (withdraw) or sender wallet (cancel)
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// v5: Payment Channel Accounts
+#[derive(Accounts)]
+#[instruction(channel_id: u64)]
+pub struct OpenChannel<'info> {
+    #[account(
+        init,
+        payer = fee_payer,
+        space = 8 + PaymentChannel::INIT_SPACE,
+        seeds = [
+            b"channel",
+            payer_registry.key().as_ref(),
+            channel_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+    )]
+    pub channel: Account<'info, PaymentChannel>,
+
+    #[account(
+        init,
+        payer = fee_payer,
+        seeds = [b"escrow_vault", channel.key().as_ref()],
+        bump,
+        token::mint = usdc_mint,
+        token::authority = escrow_vault,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        constraint = usdc_mint.key().to_string() == USDC_MINT @ SolclawError::InvalidMint
+    )]
+    pub usdc_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", payer_registry.name.as_bytes()],
+        bump = payer_registry.bump,
+        constraint = payer_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", payer_registry.name.as_bytes()],
+        bump = payer_registry.vault_bump,
+    )]
+    pub payer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"agent", payee_registry.name.as_bytes()],
+        bump = payee_registry.bump,
+    )]
+    pub payee_registry: Account<'info, AgentRegistry>,
+
+    /// The payer's wallet — also the key that signs vouchers
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub fee_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Shared by settle_channel and close_channel.
+#[derive(Accounts)]
+pub struct SettleChannel<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"channel",
+            channel.payer.as_ref(),
+            channel.id.to_le_bytes().as_ref(),
+        ],
+        bump = channel.bump,
+        constraint = channel.payer == payer_registry.key() @ SolclawError::ChannelMismatch,
+        constraint = channel.payee == payee_registry.key() @ SolclawError::ChannelMismatch,
+    )]
+    pub channel: Account<'info, PaymentChannel>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow_vault", channel.key().as_ref()],
+        bump = channel.vault_bump,
+    )]
+    pub escrow_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", payer_registry.name.as_bytes()],
+        bump = payer_registry.bump,
+    )]
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", payer_registry.name.as_bytes()],
+        bump = payer_registry.vault_bump,
+    )]
+    pub payer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", payee_registry.name.as_bytes()],
+        bump = payee_registry.bump,
+    )]
+    pub payee_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", payee_registry.name.as_bytes()],
+        bump = payee_registry.vault_bump,
+    )]
+    pub payee_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: Instructions sysvar, used to find the Ed25519 verification
+    #[account(address = solana_sdk_ids::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    /// CHECK: The payer's wallet, receives the channel and vault rent on close
+    #[account(mut, address = channel.voucher_signer @ SolclawError::Unauthorized)]
+    pub rent_receiver: UncheckedAccount<'info>,
+
+    /// Payee wallet (settle) or payer/payee wallet (close)
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RequestCloseChannel<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"channel",
+            channel.payer.as_ref(),
+            channel.id.to_le_bytes().as_ref(),
+        ],
+        bump = channel.bump,
+        constraint = channel.payer == payer_registry.key() @ SolclawError::ChannelMismatch,
+    )]
+    pub channel: Account<'info, PaymentChannel>,
+
+    #[account(
+        constraint = payer_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub payer_registry: Account<'info, AgentRegistry>,
+
+    /// The payer's wallet
+    pub authority: Signer<'info>,
+}
+
+// v5: Payment Intent Accounts
+#[derive(Accounts)]
+pub struct ExecuteIntent<'info> {
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = 8 + IntentNonce::INIT_SPACE,
+        seeds = [b"intent_nonce", sender_registry.key().as_ref()],
+        bump,
+    )]
+    pub intent_nonce: Account<'info, IntentNonce>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", sender_registry.name.as_bytes()],
+        bump = sender_registry.bump,
+    )]
+    pub sender_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", sender_registry.name.as_bytes()],
+        bump = sender_registry.vault_bump,
+    )]
+    pub sender_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", receiver_registry.name.as_bytes()],
+        bump = receiver_registry.bump,
+    )]
+    pub receiver_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", receiver_registry.name.as_bytes()],
         bump = receiver_registry.vault_bump,
     )]
     pub receiver_vault: Account<'info, TokenAccount>,
 
+    /// Where the relayer receives its USDC fee
     #[account(
-        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
+        mut,
+        constraint = relayer_token_account.mint == sender_vault.mint @ SolclawError::InvalidMint
     )]
-    pub authority: Signer<'info>,
+    pub relayer_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Instructions sysvar, used to find the Ed25519 verification
+    #[account(address = solana_sdk_ids::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    /// Anyone can relay — pays SOL fees and rent for the nonce account
+    #[account(mut)]
+    pub relayer: Signer<'info>,
 
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
+// v5: Metered Billing Accounts
 #[derive(Accounts)]
-pub struct Withdraw<'info> {
+#[instruction(plan_id: u64)]
+pub struct CreateMeteredPlan<'info> {
     #[account(
-        seeds = [b"agent", agent_registry.name.as_bytes()],
-        bump = agent_registry.bump,
+        init,
+        payer = payer,
+        space = 8 + MeteredPlan::INIT_SPACE,
+        seeds = [
+            b"metered_plan",
+            provider_registry.key().as_ref(),
+            consumer_registry.key().as_ref(),
+            plan_id.to_le_bytes().as_ref(),
+        ],
+        bump,
     )]
-    pub agent_registry: Account<'info, AgentRegistry>,
+    pub plan: Account<'info, MeteredPlan>,
+
+    #[account(
+        seeds = [b"agent", provider_registry.name.as_bytes()],
+        bump = provider_registry.bump,
+        constraint = provider_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub provider_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        seeds = [b"agent", consumer_registry.name.as_bytes()],
+        bump = consumer_registry.bump,
+    )]
+    pub consumer_registry: Account<'info, AgentRegistry>,
+
+    /// The provider's wallet
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
 
+    pub system_program: Program<'info, System>,
+}
+
+/// Shared by accept_metered_plan and cancel_metered_plan.
+#[derive(Accounts)]
+pub struct UpdateMeteredPlan<'info> {
     #[account(
         mut,
-        seeds = [b"vault", agent_registry.name.as_bytes()],
-        bump = agent_registry.vault_bump,
+        seeds = [
+            b"metered_plan",
+            plan.provider.as_ref(),
+            plan.consumer.as_ref(),
+            plan.id.to_le_bytes().as_ref(),
+        ],
+        bump = plan.bump,
+        constraint = plan.provider == provider_registry.key() @ SolclawError::MeteredPlanMismatch,
+        constraint = plan.consumer == consumer_registry.key() @ SolclawError::MeteredPlanMismatch,
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub plan: Account<'info, MeteredPlan>,
+
+    pub provider_registry: Account<'info, AgentRegistry>,
+    pub consumer_registry: Account<'info, AgentRegistry>,
+
+    /// Consumer wallet (accept) or either party's wallet (cancel)
+    pub authority: Signer<'info>,
+}
 
+#[derive(Accounts)]
+pub struct CloseMeteredPlan<'info> {
     #[account(
         mut,
-        constraint = destination.mint == vault.mint @ SolclawError::InvalidMint
+        seeds = [
+            b"metered_plan",
+            plan.provider.as_ref(),
+            plan.consumer.as_ref(),
+            plan.id.to_le_bytes().as_ref(),
+        ],
+        bump = plan.bump,
+        close = payer,
+        constraint = plan.provider == provider_registry.key() @ SolclawError::MeteredPlanMismatch,
+        constraint = plan.consumer == consumer_registry.key() @ SolclawError::MeteredPlanMismatch,
+        constraint = plan.payer == payer.key() @ SolclawError::MeteredPlanMismatch,
     )]
-    pub destination: Account<'info, TokenAccount>,
+    pub plan: Account<'info, MeteredPlan>,
+
+    pub provider_registry: Account<'info, AgentRegistry>,
+    pub consumer_registry: Account<'info, AgentRegistry>,
+
+    /// Either party's wallet
+    pub authority: Signer<'info>,
+
+    /// Original rent payer, receives the lamports
+    #[account(mut)]
+    pub payer: SystemAccount<'info>,
+}
 
+#[derive(Accounts)]
+pub struct SettleUsage<'info> {
     #[account(
-        constraint = agent_registry.authority == authority.key() @ SolclawError::Unauthorized
+        mut,
+        seeds = [
+            b"metered_plan",
+            plan.provider.as_ref(),
+            plan.consumer.as_ref(),
+            plan.id.to_le_bytes().as_ref(),
+        ],
+        bump = plan.bump,
+        constraint = plan.provider == provider_registry.key() @ SolclawError::MeteredPlanMismatch,
+        constraint = plan.consumer == consumer_registry.key() @ SolclawError::MeteredPlanMismatch,
+    )]
+    pub plan: Account<'info, MeteredPlan>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"allowance",
+            consumer_registry.key().as_ref(),
+            provider_registry.key().as_ref(),
+        ],
+        bump = allowance.bump,
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", consumer_registry.name.as_bytes()],
+        bump = consumer_registry.bump,
+    )]
+    pub consumer_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", provider_registry.name.as_bytes()],
+        bump = provider_registry.bump,
+        constraint = provider_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub provider_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", consumer_registry.name.as_bytes()],
+        bump = consumer_registry.vault_bump,
+    )]
+    pub consumer_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", provider_registry.name.as_bytes()],
+        bump = provider_registry.vault_bump,
     )]
+    pub provider_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: Instructions sysvar, used to find the Ed25519 verification
+    #[account(address = solana_sdk_ids::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    /// The provider's wallet — they initiate the pull
     pub authority: Signer<'info>,
 
     pub token_program: Program<'info, Token>,
 }
 
+// v5: Split Config Accounts
 #[derive(Accounts)]
-pub struct BatchPayment<'info> {
+pub struct CreateSplitConfig<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + SplitConfig::INIT_SPACE,
+        seeds = [b"split_config", owner_registry.key().as_ref()],
+        bump,
+    )]
+    pub split_config: Account<'info, SplitConfig>,
+
+    #[account(
+        seeds = [b"agent", owner_registry.name.as_bytes()],
+        bump = owner_registry.bump,
+        constraint = owner_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub owner_registry: Account<'info, AgentRegistry>,
+
+    /// The owner's wallet
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSplitConfig<'info> {
     #[account(
         mut,
-        seeds = [b"agent", sender_registry.name.as_bytes()],
-        bump = sender_registry.bump,
+        seeds = [b"split_config", owner_registry.key().as_ref()],
+        bump = split_config.bump,
     )]
-    pub sender_registry: Account<'info, AgentRegistry>,
+    pub split_config: Account<'info, SplitConfig>,
+
+    #[account(
+        seeds = [b"agent", owner_registry.name.as_bytes()],
+        bump = owner_registry.bump,
+        constraint = owner_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub owner_registry: Account<'info, AgentRegistry>,
+
+    /// The owner's wallet
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSplitConfig<'info> {
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"split_config", owner_registry.key().as_ref()],
+        bump = split_config.bump,
+    )]
+    pub split_config: Account<'info, SplitConfig>,
+
+    #[account(
+        seeds = [b"agent", owner_registry.name.as_bytes()],
+        bump = owner_registry.bump,
+        constraint = owner_registry.authority == authority.key() @ SolclawError::Unauthorized
+    )]
+    pub owner_registry: Account<'info, AgentRegistry>,
+
+    /// The owner's wallet, receives the rent
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DistributeSplit<'info> {
+    #[account(
+        mut,
+        seeds = [b"split_config", owner_registry.key().as_ref()],
+        bump = split_config.bump,
+    )]
+    pub split_config: Account<'info, SplitConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"agent", owner_registry.name.as_bytes()],
+        bump = owner_registry.bump,
+    )]
+    pub owner_registry: Account<'info, AgentRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", owner_registry.name.as_bytes()],
+        bump = owner_registry.vault_bump,
+    )]
+    pub owner_vault: Account<'info, TokenAccount>,
+
+    /// Anyone can crank a distribution
+    pub cranker: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// ============================================================
+// DATA TYPES
+// ============================================================
+
+#[account]
+#[derive(InitSpace)]
+pub struct AgentRegistry {
+    pub name_hash: [u8; 32],        // Hash of name for indexing
+    #[max_len(32)]
+    pub name: String,               // Human-readable name (max 32 chars)
+    pub authority: Pubkey,          // Wallet that controls this vault
+    pub vault: Pubkey,              // Token account for USDC
+    pub created_at: i64,            // Unix timestamp
+    pub total_sent: u64,            // Total USDC sent (for leaderboard)
+    pub total_received: u64,        // Total USDC received (for leaderboard)
+    pub bump: u8,                   // PDA bump for agent registry
+    pub vault_bump: u8,             // PDA bump for vault
+    // v3: Spending Cap fields
+    pub daily_limit: u64,           // Daily spending limit in USDC units (0 = no limit)
+    pub daily_spent: u64,           // Amount spent today
+    pub last_spend_day: i64,        // Day number of last spend (unix_timestamp / 86400)
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Subscription {
+    pub id: u64,                    // v5: Client-chosen nonce, unique per sender/receiver pair
+    pub sender: Pubkey,             // AgentRegistry PDA of sender
+    pub receiver: Pubkey,           // AgentRegistry PDA of receiver
+    #[max_len(32)]
+    pub sender_name: String,        // Sender name (for display)
+    #[max_len(32)]
+    pub receiver_name: String,      // Receiver name (for display)
+    pub amount: u64,                // Amount per payment in USDC units
+    pub schedule: Schedule,         // v5: When payments fall due (was interval_seconds)
+    pub last_executed: i64,         // Timestamp of last execution
+    pub next_due: i64,              // Timestamp of next due payment
+    pub is_active: bool,            // Whether subscription is active
+    pub authority: Pubkey,          // Who can cancel (sender's wallet)
+    pub total_paid: u64,            // Total amount paid so far
+    pub execution_count: u64,       // Number of payments executed
+    pub payer: Pubkey,              // v5: Paid the rent, refunded on close
+    pub paused_at: i64,             // v5: When the subscription was paused (0 = running)
+    pub max_executions: u64,        // v5: Stop after this many payments (0 = unlimited)
+    pub end_at: i64,                // v5: No payment falls due after this (0 = no end date)
+    pub max_total: u64,             // v5: Never pay more than this in total (0 = no cap)
+    pub completed_at: i64,          // v5: When the limits were reached (0 = not completed)
+    pub missed_period_policy: MissedPeriodPolicy, // v5: Handling of periods cranked late
+    pub accrued_debt: u64,          // v5: Billed but unpaid amount (Accrue policy)
+    pub crank_tip: u64,             // v5: Tip per crank (0 = protocol default)
+    pub max_crank_tip: u64,         // v5: Sender's bound on any tip (0 = never tip)
+    pub missed_periods: u64,        // v5: Periods that fell due before the crank that handled them
+    pub total_crank_delay: u64,     // v5: Sum of seconds between next_due and each crank
+    pub total_crank_tips: u64,      // v5: Total tips paid to crankers
+    pub offer: Pubkey,              // v5: SubscriptionOffer accepted (default = none)
+    pub terms_hash: [u8; 32],       // v5: Hash of the off-chain terms agreed to
+    pub pending_change: Option<PendingSubscriptionChange>, // v5: Proposed new terms
+    pub consecutive_failures: u32,  // v5: Failed executions since the last payment
+    pub max_consecutive_failures: u8, // v5: Failures that suspend it (0 = never)
+    pub last_failure_reason: SubscriptionFailureReason, // v5: Why the last failure happened
+    pub last_failure_at: i64,       // v5: When the last failure was recorded (0 = never)
+    pub suspended_at: i64,          // v5: When repeated failures suspended it (0 = not suspended)
+    pub bump: u8,                   // PDA bump
+    pub version: u8,                // v5: Account layout (LAYOUT_VERSION)
+}
+
+/// v5: Pre-v5 Subscription layout (layout 1), seeded by ["subscription",
+/// sender, receiver]. Only read by migrate_subscription.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace)]
+pub struct LegacySubscription {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    #[max_len(32)]
+    pub sender_name: String,
+    #[max_len(32)]
+    pub receiver_name: String,
+    pub amount: u64,
+    pub interval_seconds: i64,
+    pub last_executed: i64,
+    pub next_due: i64,
+    pub is_active: bool,
+    pub authority: Pubkey,
+    pub total_paid: u64,
+    pub execution_count: u64,
+    pub bump: u8,
+}
+
+impl LegacySubscription {
+    /// Decode a legacy account, checking its owner, discriminator and size.
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        require!(info.owner == &crate::ID, SolclawError::NotLegacySubscription);
+        let data = info.try_borrow_data()?;
+        require!(
+            data.len() == 8 + Self::INIT_SPACE && data[..8] == *Subscription::DISCRIMINATOR,
+            SolclawError::NotLegacySubscription
+        );
+        Self::deserialize(&mut &data[8..]).map_err(|_| SolclawError::NotLegacySubscription.into())
+    }
+}
+
+/// v5: Why a due subscription payment could not be made
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub enum SubscriptionFailureReason {
+    /// No failure recorded
+    None,
+    /// The sender vault holds less than one payment
+    InsufficientFunds,
+    /// The sender's daily spending cap leaves less than one payment
+    SpendingCapExceeded,
+}
+
+/// v5: New terms proposed by the receiver
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct PendingSubscriptionChange {
+    /// New amount per payment in USDC units
+    pub amount: u64,
+    /// New payment schedule
+    pub schedule: Schedule,
+    /// Applies from the first payment due at or after this
+    pub effective_at: i64,
+    /// When the receiver proposed it
+    pub proposed_at: i64,
+    /// Agreed by the sender, or needed no consent
+    pub accepted: bool,
+}
+
+impl Subscription {
+    /// Layout written by v5. Pre-v5 accounts have no version field and are
+    /// layout 1 (see LegacySubscription).
+    pub const LAYOUT_VERSION: u8 = 2;
+
+    /// Set the payment terms and reset all execution state. The first
+    /// payment falls due on the schedule's first occurrence after `now`.
+    pub fn start(
+        &mut self,
+        amount: u64,
+        schedule: Schedule,
+        limits: SubscriptionLimits,
+        missed_period_policy: MissedPeriodPolicy,
+        now: i64,
+    ) -> Result<()> {
+        let next_due = schedule
+            .next_after(now, now)
+            .ok_or(SolclawError::InvalidSchedule)?;
+        require!(
+            limits.end_at == 0 || limits.end_at >= next_due,
+            SolclawError::InvalidSubscriptionLimits
+        );
+
+        self.amount = amount;
+        self.schedule = schedule;
+        self.last_executed = now;
+        self.next_due = next_due;
+        self.is_active = true;
+        self.total_paid = 0;
+        self.execution_count = 0;
+        self.paused_at = 0;
+        self.max_executions = limits.max_executions;
+        self.end_at = limits.end_at;
+        self.max_total = limits.max_total;
+        self.completed_at = 0;
+        self.missed_period_policy = missed_period_policy;
+        self.accrued_debt = 0;
+        self.crank_tip = 0;
+        self.max_crank_tip = 0;
+        self.missed_periods = 0;
+        self.total_crank_delay = 0;
+        self.total_crank_tips = 0;
+        self.pending_change = None;
+        self.consecutive_failures = 0;
+        self.max_consecutive_failures = DEFAULT_MAX_CONSECUTIVE_FAILURES;
+        self.last_failure_reason = SubscriptionFailureReason::None;
+        self.last_failure_at = 0;
+        self.suspended_at = 0;
+        self.version = Self::LAYOUT_VERSION;
+        Ok(())
+    }
+
+    /// Periods due at `now`, counting the one at next_due, and the occurrence
+    /// after them. Calendar schedules count at most MAX_SCHEDULE_STEPS.
+    pub fn periods_due(&self, now: i64) -> Option<(u64, i64)> {
+        let cap = match self.schedule {
+            Schedule::Interval { .. } => u64::MAX,
+            _ => MAX_SCHEDULE_STEPS,
+        };
+        self.schedule.count(self.next_due, now, cap)
+    }
+
+    /// Number of periods, starting at next_due, that can still be billed
+    /// within the limits, at most `cap`. Billed includes accrued debt.
+    pub fn periods_remaining(&self, cap: u64) -> u64 {
+        let mut remaining = cap;
+        if self.max_executions != 0 {
+            remaining = remaining.min(self.max_executions.saturating_sub(self.execution_count));
+        }
+        if self.end_at != 0 {
+            let periods = self
+                .schedule
+                .count(self.next_due, self.end_at, remaining)
+                .map_or(0, |(periods, _)| periods);
+            remaining = remaining.min(periods);
+        }
+        if self.max_total != 0 {
+            let billed = self.total_paid.saturating_add(self.accrued_debt);
+            remaining = remaining.min(self.max_total.saturating_sub(billed) / self.amount);
+        }
+        remaining
+    }
+
+    /// How a crank at `now` handles `periods_due` periods (the next one due at
+    /// `due_after`) under missed_period_policy, with `headroom` spendable:
+    /// (periods paid now, periods added to the debt, periods dropped, new
+    /// next_due). Only called when limit_reached() is false.
+    pub fn missed_period_outcome(
+        &self,
+        now: i64,
+        periods_due: u64,
+        due_after: i64,
+        headroom: u64,
+    ) -> Option<(u64, u64, u64, i64)> {
+        let billable = self.periods_remaining(periods_due);
+        match self.missed_period_policy {
+            MissedPeriodPolicy::Skip => {
+                let next_due = self.schedule.next_after(self.next_due, now)?;
+                Some((1, 0, periods_due - 1, next_due))
+            }
+            MissedPeriodPolicy::CatchUp => {
+                let periods = billable
+                    .min(MAX_CATCH_UP_PERIODS)
+                    .min(headroom / self.amount)
+                    .max(1);
+                let (_, next_due) = self.schedule.count(self.next_due, now, periods)?;
+                Some((periods, 0, 0, next_due))
+            }
+            MissedPeriodPolicy::Accrue => {
+                // Pay what the headroom covers, possibly nothing; bill the rest
+                let paid = billable.min(headroom / self.amount);
+                Some((paid, billable - paid, periods_due - billable, due_after))
+            }
+        }
+    }
+
+    /// Switch to the pending change's terms if it is agreed and the next
+    /// payment falls due on or after its effective date. Returns the previous
+    /// amount when it was applied.
+    pub fn apply_pending_change(&mut self) -> Result<Option<u64>> {
+        let Some(change) = self.pending_change.clone() else {
+            return Ok(None);
+        };
+        if !change.accepted || self.next_due < change.effective_at {
+            return Ok(None);
+        }
+
+        let previous_amount = self.amount;
+        self.amount = change.amount;
+        self.schedule = change.schedule;
+        self.next_due = change
+            .schedule
+            .next_after(self.next_due, self.next_due - 1)
+            .ok_or(SolclawError::InvalidSchedule)?;
+        self.pending_change = None;
+        Ok(Some(previous_amount))
+    }
+
+    /// Count a failed execution and suspend the subscription once
+    /// max_consecutive_failures are reached in a row. Returns whether it was
+    /// suspended.
+    pub fn record_failure(&mut self, reason: SubscriptionFailureReason, now: i64) -> bool {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        self.last_failure_reason = reason;
+        self.last_failure_at = now;
+
+        let suspended = self.max_consecutive_failures != 0
+            && self.consecutive_failures >= self.max_consecutive_failures as u32;
+        if suspended {
+            self.suspended_at = now;
+        }
+        suspended
+    }
 
-    #[account(
-        mut,
-        seeds = [b"vault", sender_registry.name.as_bytes()],
-        bump = sender_registry.vault_bump,
-    )]
-    pub sender_vault: Account<'info, TokenAccount>,
+    /// Whether the next payment would exceed any of the limits.
+    pub fn limit_reached(&self) -> bool {
+        self.periods_remaining(1) == 0
+    }
 
-    #[account(
-        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
-    )]
-    pub authority: Signer<'info>,
+    /// next_due after a pause of `paused_seconds`: the schedule is shifted by
+    /// the pause and calendar schedules resume on their first occurrence from
+    /// there.
+    pub fn resumed_next_due(&self, paused_seconds: i64) -> Option<i64> {
+        let shifted = self.next_due.checked_add(paused_seconds)?;
+        self.schedule.next_after(shifted, shifted - 1)
+    }
 
-    pub token_program: Program<'info, Token>,
+    /// Lift a pause and/or suspension at `now`. Only the paused time shifts
+    /// next_due; time spent suspended stays billable. Returns the paused
+    /// seconds.
+    pub fn resume(&mut self, now: i64) -> Option<i64> {
+        let paused_seconds = if self.paused_at != 0 {
+            now.saturating_sub(self.paused_at)
+        } else {
+            0
+        };
+        self.next_due = self.resumed_next_due(paused_seconds)?;
+        self.paused_at = 0;
+        self.suspended_at = 0;
+        // A resumed (or unsuspended) subscription gets a fresh set of retries
+        self.consecutive_failures = 0;
+        Some(paused_seconds)
+    }
+
+    /// Tip owed to a cranker, given the protocol's default tip.
+    pub fn crank_tip(&self, protocol_tip: u64) -> u64 {
+        let tip = if self.crank_tip != 0 { self.crank_tip } else { protocol_tip };
+        tip.min(self.max_crank_tip)
+    }
 }
 
-#[derive(Accounts)]
-pub struct SplitPayment<'info> {
-    #[account(
-        mut,
-        seeds = [b"agent", sender_registry.name.as_bytes()],
-        bump = sender_registry.bump,
-    )]
-    pub sender_registry: Account<'info, AgentRegistry>,
+/// v5: Subscription terms published by a receiver.
+/// Seeded by ["subscription_offer", receiver registry, offer_id]. Accepting
+/// copies the terms onto the new Subscription, so later updates only affect
+/// future subscribers.
+#[account]
+#[derive(InitSpace)]
+pub struct SubscriptionOffer {
+    pub id: u64,                    // Client-chosen nonce, unique per receiver
+    pub receiver: Pubkey,           // AgentRegistry PDA of the receiver
+    #[max_len(32)]
+    pub receiver_name: String,      // Receiver name (for display/events)
+    pub amount: u64,                // Amount per payment in USDC units
+    pub schedule: Schedule,         // When payments fall due
+    pub missed_period_policy: MissedPeriodPolicy, // Handling of periods cranked late
+    pub terms_hash: [u8; 32],       // Hash of the off-chain service terms
+    pub version: u32,               // Incremented on every update
+    pub subscriber_count: u64,      // Subscriptions created from this offer
+    pub created_at: i64,            // Creation timestamp
+    pub updated_at: i64,            // Last update timestamp
+    pub authority: Pubkey,          // Receiver's wallet
+    pub bump: u8,                   // PDA bump
+}
 
-    #[account(
-        mut,
-        seeds = [b"vault", sender_registry.name.as_bytes()],
-        bump = sender_registry.vault_bump,
-    )]
-    pub sender_vault: Account<'info, TokenAccount>,
+/// v5: Global protocol settings.
+/// Single PDA for the entire program, seeded by ["protocol_config"].
+#[account]
+#[derive(InitSpace)]
+pub struct ProtocolConfig {
+    pub authority: Pubkey,          // Wallet allowed to update the config
+    pub default_crank_tip: u64,     // Tip for subscriptions without their own
+    pub bump: u8,                   // PDA bump
+}
 
-    #[account(
-        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
-    )]
-    pub authority: Signer<'info>,
+/// v5: What execute_subscription does with periods that fell due while
+/// nobody cranked, typically because the sender vault was empty
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum MissedPeriodPolicy {
+    /// Pay the current period only; earlier missed periods are dropped
+    Skip,
+    /// Pay back missed periods, up to MAX_CATCH_UP_PERIODS per crank
+    CatchUp,
+    /// Pay every due period the vault and cap cover; record the rest as accrued_debt
+    Accrue,
+}
 
-    pub token_program: Program<'info, Token>,
+/// v5: Optional fixed-term bounds passed to create_subscription; 0 disables each
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct SubscriptionLimits {
+    /// Maximum number of payments
+    pub max_executions: u64,
+    /// Last timestamp at which a payment may fall due
+    pub end_at: i64,
+    /// Maximum total paid over the subscription's lifetime, in USDC units
+    pub max_total: u64,
 }
 
-#[derive(Accounts)]
-#[instruction(receiver_name: String)]
-pub struct CreateSubscription<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = 8 + Subscription::INIT_SPACE,
-        seeds = [
-            b"subscription",
-            sender_registry.key().as_ref(),
-            receiver_registry.key().as_ref(),
-        ],
-        bump,
-    )]
-    pub subscription: Account<'info, Subscription>,
+/// v3: Allowance account for approve/transferFrom pattern
+#[account]
+#[derive(InitSpace)]
+pub struct Allowance {
+    pub owner: Pubkey,              // AgentRegistry PDA of the owner
+    pub spender: Pubkey,            // AgentRegistry PDA of the spender
+    #[max_len(32)]
+    pub owner_name: String,         // Owner's name (for display/events)
+    #[max_len(32)]
+    pub spender_name: String,       // Spender's name (for display/events)
+    pub amount: u64,                // Remaining allowance in USDC units
+    pub total_pulled: u64,          // Total amount ever pulled
+    pub pull_count: u64,            // Number of times transferFrom was called
+    pub is_active: bool,            // Whether this allowance is active
+    pub authority: Pubkey,          // The authority who can modify/revoke (owner's wallet)
+    pub bump: u8,                   // PDA bump
+}
 
-    #[account(
-        seeds = [b"agent", sender_registry.name.as_bytes()],
-        bump = sender_registry.bump,
-    )]
-    pub sender_registry: Account<'info, AgentRegistry>,
+/// v4: Global counter for generating unique invoice IDs.
+/// Single PDA for the entire program, seeded by ["invoice_counter"].
+#[account]
+pub struct InvoiceCounter {
+    pub count: u64,
+    pub bump: u8,
+}
 
-    #[account(
-        seeds = [b"agent", receiver_registry.name.as_bytes()],
-        bump = receiver_registry.bump,
-    )]
-    pub receiver_registry: Account<'info, AgentRegistry>,
+impl InvoiceCounter {
+    pub const SIZE: usize = 8 + 8 + 1; // discriminator + count + bump
+}
 
-    #[account(
-        constraint = sender_registry.authority == authority.key() @ SolclawError::Unauthorized
-    )]
-    pub authority: Signer<'info>,
+/// v4: On-chain payment request.
+/// Created by the requester, paid by the payer.
+#[account]
+pub struct Invoice {
+    /// Unique invoice ID (derived from global counter)
+    pub id: u64,
+    /// The agent requesting payment (AgentRegistry PDA)
+    pub requester: Pubkey,
+    /// The agent who should pay (AgentRegistry PDA)
+    pub payer: Pubkey,
+    /// Requester's readable name (max 32)
+    pub requester_name: String,
+    /// Payer's readable name (max 32)
+    pub payer_name: String,
+    /// Amount requested in USDC smallest units
+    pub amount: u64,
+    /// Description / reason for the invoice (max 128)
+    pub memo: String,
+    /// Current status: 0=Pending, 1=Paid, 2=Rejected, 3=Cancelled, 4=Expired, 5=Refunded
+    pub status: u8,
+    /// Creation timestamp
+    pub created_at: i64,
+    /// Expiry timestamp. 0 = never expires.
+    pub expires_at: i64,
+    /// Timestamp when paid (0 if not yet paid)
+    pub paid_at: i64,
+    /// The authority who created this (requester's wallet)
+    pub authority: Pubkey,
+    /// Bump seed
+    pub bump: u8,
+    /// v5: Total amount refunded to the payer so far. Appended after `bump`
+    /// so invoices created before v5 keep their layout (and read it as 0).
+    pub refunded_amount: u64,
+}
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+impl Invoice {
+    // Space: 8 (disc) + 8 + 32 + 32 + (4+32) + (4+32) + 8 + (4+128) + 1 + 8 + 8 + 8 + 32 + 1 + 8 = 372
+    pub const SIZE: usize = 8 + 8 + 32 + 32 + 36 + 36 + 8 + 132 + 1 + 8 + 8 + 8 + 32 + 1 + 8;
 
-    pub system_program: Program<'info, System>,
+    pub const STATUS_PENDING: u8 = 0;
+    pub const STATUS_PAID: u8 = 1;
+    pub const STATUS_REJECTED: u8 = 2;
+    pub const STATUS_CANCELLED: u8 = 3;
+    pub const STATUS_EXPIRED: u8 = 4;
+    pub const STATUS_REFUNDED: u8 = 5;
 }
 
-#[derive(Accounts)]
-pub struct ExecuteSubscription<'info> {
-    #[account(
-        mut,
-        constraint = subscription.sender == sender_registry.key() @ SolclawError::InvalidSubscription,
-        constraint = subscription.receiver == receiver_registry.key() @ SolclawError::InvalidSubscription,
-    )]
-    pub subscription: Account<'info, Subscription>,
+/// A single payment entry within a batch
+/// v3: Added optional memo
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchPaymentEntry {
+    /// The name of the recipient agent
+    pub recipient_name: String,
+    /// Amount in USDC smallest units (6 decimals)
+    pub amount: u64,
+    /// Optional memo (max 128 bytes)
+    pub memo: Option<String>,
+}
 
-    #[account(
-        mut,
-        seeds = [b"agent", sender_registry.name.as_bytes()],
-        bump = sender_registry.bump,
-    )]
-    pub sender_registry: Account<'info, AgentRegistry>,
+/// v5: Per-entry status codes reported in BatchPaymentEvent
+impl BatchPaymentEntry {
+    pub const STATUS_SENT: u8 = 0;
+    pub const STATUS_INVALID_RECIPIENT: u8 = 1;
+    pub const STATUS_INVALID_AMOUNT: u8 = 2;
+    pub const STATUS_INSUFFICIENT_BALANCE: u8 = 3;
+    pub const STATUS_CAP_EXCEEDED: u8 = 4;
+    pub const STATUS_MEMO_TOO_LONG: u8 = 5;
+    pub const STATUS_RECIPIENT_LIMIT: u8 = 6;
+
+    /// Status of a best-effort entry before its recipient is loaded: the first
+    /// check it fails, or STATUS_SENT. `recipient_headroom` is what the
+    /// recipient may still get under the batch's max_per_recipient.
+    pub fn skip_status(
+        amount: u64,
+        memo_len: usize,
+        balance: u64,
+        cap_headroom: u64,
+        recipient_headroom: u64,
+    ) -> u8 {
+        if memo_len > 128 {
+            Self::STATUS_MEMO_TOO_LONG
+        } else if amount == 0 {
+            Self::STATUS_INVALID_AMOUNT
+        } else if amount > balance {
+            Self::STATUS_INSUFFICIENT_BALANCE
+        } else if amount > cap_headroom {
+            Self::STATUS_CAP_EXCEEDED
+        } else if amount > recipient_headroom {
+            Self::STATUS_RECIPIENT_LIMIT
+        } else {
+            Self::STATUS_SENT
+        }
+    }
+}
 
-    #[account(
-        mut,
-        seeds = [b"agent", receiver_registry.name.as_bytes()],
-        bump = receiver_registry.bump,
-    )]
-    pub receiver_registry: Account<'info, AgentRegistry>,
+/// v5: A packed entry of batch_payment_compact, read in place from the
+/// instruction data: recipient index (u8) followed by amount (u64 LE).
+pub struct CompactPaymentEntry {
+    /// Index of the (registry, vault) pair in remaining accounts
+    pub recipient_index: u8,
+    /// Amount in USDC smallest units (6 decimals)
+    pub amount: u64,
+}
 
-    #[account(
-        mut,
-        seeds = [b"vault", sender_registry.name.as_bytes()],
-        bump = sender_registry.vault_bump,
-    )]
-    pub sender_vault: Account<'info, TokenAccount>,
+impl CompactPaymentEntry {
+    pub const SIZE: usize = 1 + 8;
 
-    #[account(
-        mut,
-        seeds = [b"vault", receiver_registry.name.as_bytes()],
-        bump = receiver_registry.vault_bump,
-    )]
-    pub receiver_vault: Account<'info, TokenAccount>,
+    /// Read an entry from a `SIZE`-byte chunk.
+    pub fn read(chunk: &[u8]) -> Self {
+        let mut amount = [0u8; 8];
+        amount.copy_from_slice(&chunk[1..Self::SIZE]);
+        Self {
+            recipient_index: chunk[0],
+            amount: u64::from_le_bytes(amount),
+        }
+    }
+}
 
-    pub token_program: Program<'info, Token>,
+/// A split recipient with their share in basis points
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SplitRecipient {
+    /// Agent name
+    pub name: String,
+    /// Share in basis points (5000 = 50%)
+    pub share_bps: u16,
+    /// v5: Minimum amount this recipient must receive, or the split fails (0 = none)
+    pub min_amount: u64,
+    /// v5: Forward the share through the recipient's own SplitConfig
+    pub cascade: bool,
+}
 
-    /// Anyone can crank — no authority constraint
-    pub cranker: Signer<'info>,
+/// v5: How rounding dust is allocated when shares don't divide evenly.
+/// Every recipient first gets floor(total * share_bps / 10000); the
+/// leftover units (fewer than the number of recipients) go out one each.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum SplitRounding {
+    /// To the recipients with the largest fractional remainders
+    LargestRemainder,
+    /// One unit each to the recipients with the largest shares, largest first
+    LargestShareFirst,
+    /// All of it to the recipient at this index
+    RemainderTo(u8),
 }
 
-#[derive(Accounts)]
-pub struct CancelSubscription<'info> {
-    #[account(mut)]
-    pub subscription: Account<'info, Subscription>,
+/// v5: Escrowed payment between two agents.
+/// Seeded by ["escrow", payer registry, escrow_id]; funds sit in the
+/// ["escrow_vault", escrow] token account until settled.
+#[account]
+#[derive(InitSpace)]
+pub struct Escrow {
+    pub id: u64,                    // Client-chosen nonce, unique per payer
+    pub payer: Pubkey,              // AgentRegistry PDA of the payer
+    pub payee: Pubkey,              // AgentRegistry PDA of the payee
+    #[max_len(32)]
+    pub payer_name: String,         // Payer name (for display/events)
+    #[max_len(32)]
+    pub payee_name: String,         // Payee name (for display/events)
+    pub amount: u64,                // Locked amount in USDC units
+    pub deadline: i64,              // After this, the payer can refund
+    pub arbiter: Option<Pubkey>,    // Optional wallet that can resolve disputes
+    pub status: u8,                 // 0=Active, 1=Released, 2=Refunded, 3=Resolved
+    pub created_at: i64,            // Creation timestamp
+    pub settled_at: i64,            // Settlement timestamp (0 while active)
+    pub authority: Pubkey,          // Payer's wallet
+    pub bump: u8,                   // PDA bump
+    pub vault_bump: u8,             // PDA bump for the escrow vault
+}
 
-    #[account(
-        constraint = subscription.authority == authority.key() @ SolclawError::Unauthorized
-    )]
-    pub authority: Signer<'info>,
+impl Escrow {
+    pub const STATUS_ACTIVE: u8 = 0;
+    pub const STATUS_RELEASED: u8 = 1;
+    pub const STATUS_REFUNDED: u8 = 2;
+    pub const STATUS_RESOLVED: u8 = 3;
 }
 
-// v3: Spending Cap Accounts
-#[derive(Accounts)]
-pub struct SetDailyLimit<'info> {
-    #[account(
-        mut,
-        seeds = [b"agent", registry.name.as_bytes()],
-        bump = registry.bump,
-        constraint = registry.authority == authority.key() @ SolclawError::Unauthorized
-    )]
-    pub registry: Account<'info, AgentRegistry>,
+/// v5: Milestone-based contract between two agents.
+/// Seeded by ["contract", payer registry, contract_id]; the full amount sits
+/// in the ["escrow_vault", contract] token account until released.
+#[account]
+#[derive(InitSpace)]
+pub struct Contract {
+    pub id: u64,                    // Client-chosen nonce, unique per payer
+    pub payer: Pubkey,              // AgentRegistry PDA of the payer
+    pub payee: Pubkey,              // AgentRegistry PDA of the payee
+    #[max_len(32)]
+    pub payer_name: String,         // Payer name (for display/events)
+    #[max_len(32)]
+    pub payee_name: String,         // Payee name (for display/events)
+    pub total_amount: u64,          // Sum of all milestone amounts
+    pub released_amount: u64,       // Amount released to the payee so far
+    pub refunded_amount: u64,       // Amount returned to the payer by dispute resolutions
+    pub arbiter: Option<Pubkey>,    // Optional wallet that can resolve disputed milestones
+    pub status: u8,                 // 0=Active, 1=Completed, 2=Cancelled
+    #[max_len(10)]
+    pub milestones: Vec<Milestone>, // Up to MAX_MILESTONES stages
+    pub created_at: i64,            // Creation timestamp
+    pub authority: Pubkey,          // Payer's wallet
+    pub bump: u8,                   // PDA bump
+    pub vault_bump: u8,             // PDA bump for the escrow vault
+}
 
-    pub authority: Signer<'info>,
+impl Contract {
+    pub const STATUS_ACTIVE: u8 = 0;
+    pub const STATUS_COMPLETED: u8 = 1;
+    pub const STATUS_CANCELLED: u8 = 2;
+
+    /// Amount still locked in the contract vault.
+    pub fn unpaid_amount(&self) -> Result<u64> {
+        self.total_amount
+            .checked_sub(self.released_amount)
+            .and_then(|rest| rest.checked_sub(self.refunded_amount))
+            .ok_or(error!(SolclawError::Overflow))
+    }
 }
 
-// v3: Allowance Accounts
-#[derive(Accounts)]
-#[instruction(spender_name: String)]
-pub struct Approve<'info> {
-    #[account(
-        init_if_needed,
-        payer = payer,
-        space = 8 + Allowance::INIT_SPACE,
-        seeds = [
-            b"allowance",
-            owner_registry.key().as_ref(),
-            spender_registry.key().as_ref(),
-        ],
-        bump,
-    )]
-    pub allowance: Account<'info, Allowance>,
+/// v5: A single stage of a milestone contract
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct Milestone {
+    /// Amount released when approved, in USDC smallest units
+    pub amount: u64,
+    /// Hash of the off-chain deliverable description
+    pub description_hash: [u8; 32],
+    /// Deadline for the payee to submit
+    pub deadline: i64,
+    /// 0=Pending, 1=Submitted, 2=Released, 3=Disputed, 4=Resolved
+    pub status: u8,
+    /// When the milestone was last submitted (starts the review window)
+    pub submitted_at: i64,
+}
 
-    #[account(
-        seeds = [b"agent", owner_registry.name.as_bytes()],
-        bump = owner_registry.bump,
-    )]
-    pub owner_registry: Account<'info, AgentRegistry>,
+impl Milestone {
+    pub const STATUS_PENDING: u8 = 0;
+    pub const STATUS_SUBMITTED: u8 = 1;
+    pub const STATUS_RELEASED: u8 = 2;
+    pub const STATUS_DISPUTED: u8 = 3;
+    pub const STATUS_RESOLVED: u8 = 4;
+}
 
-    #[account(
-        seeds = [b"agent", spender_registry.name.as_bytes()],
-        bump = spender_registry.bump,
-    )]
-    pub spender_registry: Account<'info, AgentRegistry>,
+/// v5: Milestone definition passed to create_contract
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MilestoneInput {
+    /// Amount in USDC smallest units (6 decimals)
+    pub amount: u64,
+    /// Hash of the off-chain deliverable description
+    pub description_hash: [u8; 32],
+    /// Deadline for the payee to submit
+    pub deadline: i64,
+}
 
-    pub authority: Signer<'info>,
+/// v5: Hash time-locked transfer.
+/// Seeded by ["htlc", sender registry, htlc_id]; funds sit in the
+/// ["escrow_vault", htlc] token account until claimed or refunded.
+#[account]
+#[derive(InitSpace)]
+pub struct Htlc {
+    pub id: u64,                    // Client-chosen nonce, unique per sender
+    pub sender: Pubkey,             // AgentRegistry PDA of the sender
+    pub receiver: Pubkey,           // AgentRegistry PDA of the receiver
+    #[max_len(32)]
+    pub sender_name: String,        // Sender name (for display/events)
+    #[max_len(32)]
+    pub receiver_name: String,      // Receiver name (for display/events)
+    pub amount: u64,                // Locked amount in USDC units
+    pub hashlock: [u8; 32],         // Hash of the secret preimage
+    pub hash_algorithm: u8,         // 0=sha256, 1=keccak256
+    pub timelock: i64,              // After this, the sender can refund
+    pub status: u8,                 // 0=Active, 1=Claimed, 2=Refunded
+    pub created_at: i64,            // Creation timestamp
+    pub authority: Pubkey,          // Sender's wallet
+    pub bump: u8,                   // PDA bump
+    pub vault_bump: u8,             // PDA bump for the escrow vault
+}
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+impl Htlc {
+    pub const HASH_SHA256: u8 = 0;
+    pub const HASH_KECCAK256: u8 = 1;
 
-    pub system_program: Program<'info, System>,
+    pub const STATUS_ACTIVE: u8 = 0;
+    pub const STATUS_CLAIMED: u8 = 1;
+    pub const STATUS_REFUNDED: u8 = 2;
 }
 
-#[derive(Accounts)]
-pub struct TransferFrom<'info> {
-    #[account(
-        mut,
-        constraint = allowance.owner == owner_registry.key() @ SolclawError::AllowanceMismatch,
-        constraint = allowance.spender == spender_registry.key() @ SolclawError::AllowanceMismatch,
-    )]
-    pub allowance: Account<'info, Allowance>,
+/// v5: Continuous payment stream with per-second vesting.
+/// Seeded by ["stream", sender registry, stream_id]; deposits sit in the
+/// ["escrow_vault", stream] token account until withdrawn.
+#[account]
+#[derive(InitSpace)]
+pub struct Stream {
+    pub id: u64,                    // Client-chosen nonce, unique per sender
+    pub sender: Pubkey,             // AgentRegistry PDA of the sender
+    pub receiver: Pubkey,           // AgentRegistry PDA of the receiver
+    #[max_len(32)]
+    pub sender_name: String,        // Sender name (for display/events)
+    #[max_len(32)]
+    pub receiver_name: String,      // Receiver name (for display/events)
+    pub rate_per_second: u64,       // USDC units vested per second
+    pub deposited: u64,             // Total USDC deposited into the stream
+    pub withdrawn: u64,             // Total USDC paid out to the receiver
+    pub vested_at_checkpoint: u64,  // Amount vested as of checkpoint_time
+    pub checkpoint_time: i64,       // Vesting resumes from here (start or last top-up)
+    pub is_active: bool,            // False once cancelled
+    pub created_at: i64,            // Creation timestamp
+    pub authority: Pubkey,          // Sender's wallet
+    pub bump: u8,                   // PDA bump
+    pub vault_bump: u8,             // PDA bump for the escrow vault
+}
 
-    #[account(
-        mut,
-        seeds = [b"agent", owner_registry.name.as_bytes()],
-        bump = owner_registry.bump,
-    )]
-    pub owner_registry: Account<'info, AgentRegistry>,
+impl Stream {
+    /// Total amount vested at `now`, capped at the deposited amount.
+    pub fn vested(&self, now: i64) -> Result<u64> {
+        let elapsed = now.saturating_sub(self.checkpoint_time).max(0) as u128;
+        let streamed = elapsed
+            .checked_mul(self.rate_per_second as u128)
+            .ok_or(SolclawError::Overflow)?;
+        let vested = (self.vested_at_checkpoint as u128).saturating_add(streamed);
+        Ok(vested.min(self.deposited as u128) as u64)
+    }
+}
 
-    #[account(
-        mut,
-        seeds = [b"agent", spender_registry.name.as_bytes()],
-        bump = spender_registry.bump,
-    )]
-    pub spender_registry: Account<'info, AgentRegistry>,
+/// v5: Unidirectional payment channel settled with signed vouchers.
+/// Seeded by ["channel", payer registry, channel_id]; the deposit sits in the
+/// ["escrow_vault", channel] token account.
+#[account]
+#[derive(InitSpace)]
+pub struct PaymentChannel {
+    pub id: u64,                    // Client-chosen nonce, unique per payer
+    pub payer: Pubkey,              // AgentRegistry PDA of the payer
+    pub payee: Pubkey,              // AgentRegistry PDA of the payee
+    #[max_len(32)]
+    pub payer_name: String,         // Payer name (for display/events)
+    #[max_len(32)]
+    pub payee_name: String,         // Payee name (for display/events)
+    pub voucher_signer: Pubkey,     // Ed25519 key that signs vouchers (payer's wallet)
+    pub deposit: u64,               // USDC locked in the channel
+    pub settled: u64,               // Cumulative amount paid to the payee
+    pub challenge_period: i64,      // Seconds between close request and close
+    pub close_requested_at: i64,    // When the payer requested a close (0 if not)
+    pub status: u8,                 // 0=Open, 1=Closing, 2=Closed
+    pub created_at: i64,            // Creation timestamp
+    pub bump: u8,                   // PDA bump
+    pub vault_bump: u8,             // PDA bump for the escrow vault
+}
 
-    #[account(
-        mut,
-        seeds = [b"vault", owner_registry.name.as_bytes()],
-        bump = owner_registry.vault_bump,
-    )]
-    pub owner_vault: Account<'info, TokenAccount>,
+impl PaymentChannel {
+    pub const STATUS_OPEN: u8 = 0;
+    pub const STATUS_CLOSING: u8 = 1;
+    pub const STATUS_CLOSED: u8 = 2;
+}
 
-    #[account(
-        mut,
-        seeds = [b"vault", spender_registry.name.as_bytes()],
-        bump = spender_registry.vault_bump,
-    )]
-    pub spender_vault: Account<'info, TokenAccount>,
+/// v5: Replay protection for payment intents, one per sender.
+/// Seeded by ["intent_nonce", sender registry].
+#[account]
+#[derive(InitSpace)]
+pub struct IntentNonce {
+    pub sender: Pubkey,             // AgentRegistry PDA of the sender
+    pub next_nonce: u64,            // Nonce the next intent must use
+    pub bump: u8,                   // PDA bump
+}
 
-    /// The spender's wallet — they initiate the pull
-    pub spender_authority: Signer<'info>,
+/// v5: Usage-metered billing plan between a provider and a consumer.
+/// Seeded by ["metered_plan", provider registry, consumer registry].
+/// Settlements pull from the consumer's Allowance to the provider.
+#[account]
+#[derive(InitSpace)]
+pub struct MeteredPlan {
+    pub id: u64,                    // Client-chosen nonce, unique per provider/consumer pair
+    pub provider: Pubkey,           // AgentRegistry PDA of the provider
+    pub consumer: Pubkey,           // AgentRegistry PDA of the consumer
+    #[max_len(32)]
+    pub provider_name: String,      // Provider name (for display/events)
+    #[max_len(32)]
+    pub consumer_name: String,      // Consumer name (for display/events)
+    pub price_per_unit: u64,        // USDC units charged per usage unit
+    pub settled_units: u64,         // Cumulative units already billed
+    pub total_billed: u64,          // Total USDC billed so far
+    pub status: u8,                 // 0=Proposed, 1=Active, 2=Cancelled
+    pub created_at: i64,            // Creation timestamp
+    pub accepted_at: i64,           // When the consumer accepted (0 if never)
+    pub cancelled_at: i64,          // When the plan was cancelled (0 if not)
+    pub payer: Pubkey,              // Wallet that paid rent (receives it on close)
+    pub bump: u8,                   // PDA bump
+}
 
-    pub token_program: Program<'info, Token>,
+impl MeteredPlan {
+    pub const STATUS_PROPOSED: u8 = 0;
+    pub const STATUS_ACTIVE: u8 = 1;
+    pub const STATUS_CANCELLED: u8 = 2;
+
+    /// Last moment usage can be settled after a cancel (0 while the plan is
+    /// open, or if it was cancelled before being accepted).
+    pub fn settlement_deadline(&self) -> i64 {
+        if self.status != Self::STATUS_CANCELLED || self.accepted_at == 0 {
+            return 0;
+        }
+        self.cancelled_at.saturating_add(METERED_SETTLEMENT_GRACE_SECONDS)
+    }
+
+    /// Active plans, and accepted plans still within their post-cancel grace
+    /// period, accept usage settlements.
+    pub fn is_settleable(&self, now: i64) -> bool {
+        match self.status {
+            Self::STATUS_ACTIVE => true,
+            Self::STATUS_CANCELLED => self.accepted_at != 0 && now <= self.settlement_deadline(),
+            _ => false,
+        }
+    }
 }
 
-#[derive(Accounts)]
-pub struct RevokeAllowance<'info> {
-    #[account(
-        mut,
-        seeds = [
-            b"allowance",
-            allowance.owner.as_ref(),
-            allowance.spender.as_ref(),
-        ],
-        bump = allowance.bump,
-        constraint = allowance.authority == authority.key() @ SolclawError::Unauthorized
-    )]
-    pub allowance: Account<'info, Allowance>,
+/// v5: One recipient of a persistent split configuration
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct SplitConfigEntry {
+    pub registry: Pubkey,           // AgentRegistry PDA of the recipient
+    pub share_bps: u16,             // Share in basis points (5000 = 50%)
+    pub min_amount: u64,            // Distribution fails below this amount (0 = none)
+    pub cascade: bool,              // Forward through the recipient's own SplitConfig
+}
 
-    pub authority: Signer<'info>,
+/// v5: One node of a split payout tree, as reported in split events.
+/// Cascading nodes forward their amount to their children; the others
+/// received it in their vault.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SplitPayoutNode {
+    pub registry: Pubkey,           // AgentRegistry PDA of the recipient
+    pub parent: u8,                 // Index of the parent node (ROOT for top level)
+    pub depth: u8,                  // 0 for top-level recipients
+    pub amount: u64,                // Amount received or forwarded (0 = skipped)
+    pub cascaded: bool,             // Forwarded through the recipient's SplitConfig
 }
 
-#[derive(Accounts)]
-pub struct ModifyAllowance<'info> {
-    #[account(
-        mut,
-        seeds = [
-            b"allowance",
-            allowance.owner.as_ref(),
-            allowance.spender.as_ref(),
-        ],
-        bump = allowance.bump,
-        constraint = allowance.authority == authority.key() @ SolclawError::Unauthorized
-    )]
-    pub allowance: Account<'info, Allowance>,
+impl SplitPayoutNode {
+    pub const ROOT: u8 = u8::MAX;
+}
 
-    pub authority: Signer<'info>,
+/// v5: Reusable revenue-share configuration owned by an agent.
+/// Seeded by ["split_config", owner registry]; distribute_split pays out the
+/// owner's vault balance above `reserve` according to these shares.
+#[account]
+#[derive(InitSpace)]
+pub struct SplitConfig {
+    pub owner: Pubkey,              // AgentRegistry PDA of the owner
+    #[max_len(32)]
+    pub owner_name: String,         // Owner name (for display/events)
+    #[max_len(20)]
+    pub recipients: Vec<SplitConfigEntry>, // Recipients and shares (sum to 10000 bps)
+    pub rounding: SplitRounding,    // How rounding dust is allocated
+    pub reserve: u64,               // Vault balance distributions leave untouched
+    pub locked: bool,               // Immutable once locked
+    pub total_distributed: u64,     // Lifetime amount distributed
+    pub distribution_count: u64,    // Number of distributions
+    pub created_at: i64,            // Creation timestamp
+    pub updated_at: i64,            // Last change to the recipients
+    pub bump: u8,                   // PDA bump
 }
 
-// v4: Invoice Accounts
+// ============================================================
+// EVENTS
+// ============================================================
 
-#[derive(Accounts)]
-pub struct InitInvoiceCounter<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = InvoiceCounter::SIZE,
-        seeds = [b"invoice_counter"],
-        bump,
-    )]
-    pub counter: Account<'info, InvoiceCounter>,
+/// v3: Transfer event with memo
+/// v5: Echoes Solana Pay reference keys
+#[event]
+pub struct TransferEvent {
+    pub sender: String,
+    pub receiver: String,
+    pub amount: u64,
+    pub memo: String,
+    pub references: Vec<Pubkey>,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+/// v3: Batch payment event with memos
+/// v5: Added per-entry statuses (BatchPaymentEntry::STATUS_*); `total` is the amount actually sent
+#[event]
+pub struct BatchPaymentEvent {
+    pub sender: String,
+    pub recipients: Vec<String>,
+    pub amounts: Vec<u64>,
+    pub memos: Vec<String>,
+    pub statuses: Vec<u8>,
+    pub atomic: bool,
+    pub total: u64,
+    pub timestamp: i64,
+}
 
-    pub system_program: Program<'info, System>,
+/// v5: Compact batch payment event. Recipients are registry PDAs; each
+/// entry points into them by index.
+#[event]
+pub struct CompactBatchPaymentEvent {
+    pub sender: String,
+    pub recipients: Vec<Pubkey>,
+    pub recipient_indices: Vec<u8>,
+    pub amounts: Vec<u64>,
+    pub total: u64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-#[instruction(payer_name: String, amount: u64, memo: String, expires_in_seconds: i64)]
-pub struct CreateInvoice<'info> {
-    #[account(
-        init,
-        payer = fee_payer,
-        space = Invoice::SIZE,
-        seeds = [b"invoice", counter.count.to_le_bytes().as_ref()],
-        bump,
-    )]
-    pub invoice: Account<'info, Invoice>,
+/// v3: Split payment event with memo
+/// v5: Lists every recipient with its exact amount (0 = skipped share),
+/// the rounding policy and how much dust it allocated
+#[event]
+pub struct SplitPaymentEvent {
+    pub sender: String,
+    pub recipients: Vec<String>,
+    pub amounts: Vec<u64>,
+    pub total: u64,
+    pub rounding: SplitRounding,
+    pub dust: u64,
+    pub payouts: Vec<SplitPayoutNode>,
+    pub memo: String,
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        seeds = [b"invoice_counter"],
-        bump = counter.bump,
-    )]
-    pub counter: Account<'info, InvoiceCounter>,
+/// v5: Due subscription payment could not be made
+#[event]
+pub struct SubscriptionPaymentFailedEvent {
+    pub subscription: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub amount: u64,
+    pub reason: SubscriptionFailureReason,
+    pub consecutive_failures: u32,
+    pub suspended: bool,
+    pub timestamp: i64,
+}
 
-    pub requester_registry: Account<'info, AgentRegistry>,
-    pub payer_registry: Account<'info, AgentRegistry>,
+/// v5: Receiver proposed new subscription terms
+#[event]
+pub struct SubscriptionChangeProposedEvent {
+    pub subscription: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub current_amount: u64,
+    pub new_amount: u64,
+    pub effective_at: i64,
+    pub needs_consent: bool,
+    pub timestamp: i64,
+}
 
-    pub authority: Signer<'info>,
+/// v5: Agreed subscription terms took effect
+#[event]
+pub struct SubscriptionChangeAppliedEvent {
+    pub subscription: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub previous_amount: u64,
+    pub new_amount: u64,
+    pub next_due: i64,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub fee_payer: Signer<'info>,
+/// v5: Subscription offer created or updated
+#[event]
+pub struct SubscriptionOfferUpdatedEvent {
+    pub offer: Pubkey,
+    pub receiver: String,
+    pub amount: u64,
+    pub terms_hash: [u8; 32],
+    pub version: u32,
+    pub timestamp: i64,
+}
 
-    pub system_program: Program<'info, System>,
+/// v5: Subscription created by accepting an offer
+#[event]
+pub struct SubscriptionOfferAcceptedEvent {
+    pub offer: Pubkey,
+    pub subscription: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub amount: u64,
+    pub version: u32,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct PayInvoice<'info> {
-    #[account(
-        mut,
-        constraint = invoice.payer == payer_registry.key() @ SolclawError::InvoiceMismatch,
-        constraint = invoice.requester == requester_registry.key() @ SolclawError::InvoiceMismatch,
-    )]
-    pub invoice: Account<'info, Invoice>,
+/// v5: Subscription paused event
+#[event]
+pub struct SubscriptionPausedEvent {
+    pub subscription: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub next_due: i64,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub payer_registry: Account<'info, AgentRegistry>,
+/// v5: Subscription resumed event; next_due already includes the paused time
+#[event]
+pub struct SubscriptionResumedEvent {
+    pub subscription: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub paused_seconds: i64,
+    pub next_due: i64,
+    pub timestamp: i64,
+}
 
-    #[account(mut)]
-    pub requester_registry: Account<'info, AgentRegistry>,
+/// v5: Subscription reached its execution count, end date or total cap
+#[event]
+pub struct SubscriptionCompletedEvent {
+    pub subscription: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub total_paid: u64,
+    pub execution_count: u64,
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        constraint = payer_vault.key() == payer_registry.vault @ SolclawError::VaultMismatch
-    )]
-    pub payer_vault: Account<'info, TokenAccount>,
+/// v5: Final summary of a subscription when its account is closed
+#[event]
+pub struct SubscriptionClosedEvent {
+    pub subscription: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub total_paid: u64,
+    pub execution_count: u64,
+    pub timestamp: i64,
+}
 
-    #[account(
-        mut,
-        constraint = requester_vault.key() == requester_registry.vault @ SolclawError::VaultMismatch
-    )]
-    pub requester_vault: Account<'info, TokenAccount>,
+/// v3: Subscription executed event with auto-memo
+#[event]
+pub struct SubscriptionExecutedEvent {
+    pub sender: String,
+    pub receiver: String,
+    pub amount: u64,
+    pub memo: String,
+    pub execution_count: u64,
+    pub periods_paid: u64,          // v5: Periods covered by this payment
+    pub periods_skipped: u64,       // v5: Missed periods dropped (Skip policy)
+    pub accrued_debt: u64,          // v5: Debt outstanding after this payment
+    pub cranker: Pubkey,            // v5: Who cranked the payment
+    pub crank_tip: u64,             // v5: Tip paid to the cranker
+    pub timestamp: i64,
+}
 
-    /// The payer's wallet
-    pub authority: Signer<'info>,
+/// v5: Accrued subscription debt paid down
+#[event]
+pub struct SubscriptionDebtSettledEvent {
+    pub subscription: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub amount: u64,
+    pub remaining_debt: u64,
+    pub timestamp: i64,
+}
 
-    pub token_program: Program<'info, Token>,
+/// v3: Daily limit set event
+#[event]
+pub struct DailyLimitSetEvent {
+    pub agent: String,
+    pub limit_usdc: u64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct RejectInvoice<'info> {
-    #[account(
-        mut,
-        seeds = [b"invoice", invoice.id.to_le_bytes().as_ref()],
-        bump = invoice.bump,
-        constraint = invoice.payer == payer_registry.key() @ SolclawError::InvoiceMismatch
-    )]
-    pub invoice: Account<'info, Invoice>,
+/// v3: Allowance approved event
+#[event]
+pub struct AllowanceApprovedEvent {
+    pub owner: String,
+    pub spender: String,
+    pub amount: u64,
+    pub timestamp: i64,
+}
 
-    #[account(
-        seeds = [b"agent", payer_registry.name.as_bytes()],
-        bump = payer_registry.bump,
-        constraint = payer_registry.authority == authority.key() @ SolclawError::Unauthorized
-    )]
-    pub payer_registry: Account<'info, AgentRegistry>,
+/// v3: TransferFrom event
+#[event]
+pub struct TransferFromEvent {
+    pub owner: String,
+    pub spender: String,
+    pub amount: u64,
+    pub memo: String,
+    pub remaining_allowance: u64,
+    pub pull_number: u64,
+    pub timestamp: i64,
+}
 
-    pub authority: Signer<'info>,
+/// v3: Allowance revoked event
+#[event]
+pub struct AllowanceRevokedEvent {
+    pub owner: String,
+    pub spender: String,
+    pub total_pulled: u64,
+    pub pull_count: u64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct CancelInvoice<'info> {
-    #[account(
-        mut,
-        seeds = [b"invoice", invoice.id.to_le_bytes().as_ref()],
-        bump = invoice.bump,
-        constraint = invoice.authority == authority.key() @ SolclawError::Unauthorized
-    )]
-    pub invoice: Account<'info, Invoice>,
+/// v3: Allowance modified event
+#[event]
+pub struct AllowanceModifiedEvent {
+    pub owner: String,
+    pub spender: String,
+    pub new_amount: u64,
+    pub action: String,
+    pub timestamp: i64,
+}
 
-    pub authority: Signer<'info>,
+/// v4: Invoice created event
+#[event]
+pub struct InvoiceCreatedEvent {
+    pub invoice_id: u64,
+    pub requester: String,
+    pub payer: String,
+    pub amount: u64,
+    pub memo: String,
+    pub expires_at: i64,
+    pub timestamp: i64,
 }
 
-// ============================================================
-// DATA TYPES
-// ============================================================
+/// v4: Invoice paid event
+/// v5: Echoes Solana Pay reference keys
+#[event]
+pub struct InvoicePaidEvent {
+    pub invoice_id: u64,
+    pub requester: String,
+    pub payer: String,
+    pub amount: u64,
+    pub memo: String,
+    pub references: Vec<Pubkey>,
+    pub timestamp: i64,
+}
 
-#[account]
-#[derive(InitSpace)]
-pub struct AgentRegistry {
-    pub name_hash: [u8; 32],        // Hash of name for indexing
-    #[max_len(32)]
-    pub name: String,               // Human-readable name (max 32 chars)
-    pub authority: Pubkey,          // Wallet that controls this vault
-    pub vault: Pubkey,              // Token account for USDC
-    pub created_at: i64,            // Unix timestamp
-    pub total_sent: u64,            // Total USDC sent (for leaderboard)
-    pub total_received: u64,        // Total USDC received (for leaderboard)
-    pub bump: u8,                   // PDA bump for agent registry
-    pub vault_bump: u8,             // PDA bump for vault
-    // v3: Spending Cap fields
-    pub daily_limit: u64,           // Daily spending limit in USDC units (0 = no limit)
-    pub daily_spent: u64,           // Amount spent today
-    pub last_spend_day: i64,        // Day number of last spend (unix_timestamp / 86400)
+/// v4: Invoice rejected event
+#[event]
+pub struct InvoiceRejectedEvent {
+    pub invoice_id: u64,
+    pub requester: String,
+    pub payer: String,
+    pub amount: u64,
+    pub timestamp: i64,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct Subscription {
-    pub sender: Pubkey,             // AgentRegistry PDA of sender
-    pub receiver: Pubkey,           // AgentRegistry PDA of receiver
-    #[max_len(32)]
-    pub sender_name: String,        // Sender name (for display)
-    #[max_len(32)]
-    pub receiver_name: String,      // Receiver name (for display)
-    pub amount: u64,                // Amount per payment in USDC units
-    pub interval_seconds: i64,      // Interval between payments
-    pub last_executed: i64,         // Timestamp of last execution
-    pub next_due: i64,              // Timestamp of next due payment
-    pub is_active: bool,            // Whether subscription is active
-    pub authority: Pubkey,          // Who can cancel (sender's wallet)
-    pub total_paid: u64,            // Total amount paid so far
-    pub execution_count: u64,       // Number of payments executed
-    pub bump: u8,                   // PDA bump
+/// v4: Invoice cancelled event
+#[event]
+pub struct InvoiceCancelledEvent {
+    pub invoice_id: u64,
+    pub requester: String,
+    pub payer: String,
+    pub amount: u64,
+    pub timestamp: i64,
 }
 
-/// v3: Allowance account for approve/transferFrom pattern
-#[account]
-#[derive(InitSpace)]
-pub struct Allowance {
-    pub owner: Pubkey,              // AgentRegistry PDA of the owner
-    pub spender: Pubkey,            // AgentRegistry PDA of the spender
-    #[max_len(32)]
-    pub owner_name: String,         // Owner's name (for display/events)
-    #[max_len(32)]
-    pub spender_name: String,       // Spender's name (for display/events)
-    pub amount: u64,                // Remaining allowance in USDC units
-    pub total_pulled: u64,          // Total amount ever pulled
-    pub pull_count: u64,            // Number of times transferFrom was called
-    pub is_active: bool,            // Whether this allowance is active
-    pub authority: Pubkey,          // The authority who can modify/revoke (owner's wallet)
-    pub bump: u8,                   // PDA bump
+/// v5: Refund event, linked to the original invoice
+#[event]
+pub struct RefundEvent {
+    pub invoice_id: u64,
+    pub requester: String,
+    pub payer: String,
+    pub amount: u64,
+    pub total_refunded: u64,
+    pub memo: String,
+    pub timestamp: i64,
 }
 
-/// v4: Global counter for generating unique invoice IDs.
-/// Single PDA for the entire program, seeded by ["invoice_counter"].
-#[account]
-pub struct InvoiceCounter {
-    pub count: u64,
-    pub bump: u8,
+/// v5: Escrow created event
+#[event]
+pub struct EscrowCreatedEvent {
+    pub escrow: Pubkey,
+    pub payer: String,
+    pub payee: String,
+    pub amount: u64,
+    pub deadline: i64,
+    pub arbiter: Option<Pubkey>,
+    pub timestamp: i64,
 }
 
-impl InvoiceCounter {
-    pub const SIZE: usize = 8 + 8 + 1; // discriminator + count + bump
+/// v5: Escrow released event
+#[event]
+pub struct EscrowReleasedEvent {
+    pub escrow: Pubkey,
+    pub payer: String,
+    pub payee: String,
+    pub amount: u64,
+    pub timestamp: i64,
 }
 
-/// v4: On-chain payment request.
-/// Created by the requester, paid by the payer.
-#[account]
-pub struct Invoice {
-    /// Unique invoice ID (derived from global counter)
-    pub id: u64,
-    /// The agent requesting payment (AgentRegistry PDA)
-    pub requester: Pubkey,
-    /// The agent who should pay (AgentRegistry PDA)
-    pub payer: Pubkey,
-    /// Requester's readable name (max 32)
-    pub requester_name: String,
-    /// Payer's readable name (max 32)
-    pub payer_name: String,
-    /// Amount requested in USDC smallest units
+/// v5: Escrow refunded event
+#[event]
+pub struct EscrowRefundedEvent {
+    pub escrow: Pubkey,
+    pub payer: String,
+    pub payee: String,
     pub amount: u64,
-    /// Description / reason for the invoice (max 128)
-    pub memo: String,
-    /// Current status: 0=Pending, 1=Paid, 2=Rejected, 3=Cancelled, 4=Expired
-    pub status: u8,
-    /// Creation timestamp
-    pub created_at: i64,
-    /// Expiry timestamp. 0 = never expires.
-    pub expires_at: i64,
-    /// Timestamp when paid (0 if not yet paid)
-    pub paid_at: i64,
-    /// The authority who created this (requester's wallet)
-    pub authority: Pubkey,
-    /// Bump seed
-    pub bump: u8,
+    pub timestamp: i64,
+}
+
+/// v5: Escrow resolved by arbiter event
+#[event]
+pub struct EscrowResolvedEvent {
+    pub escrow: Pubkey,
+    pub payer: String,
+    pub payee: String,
+    pub payee_amount: u64,
+    pub payer_amount: u64,
+    pub arbiter: Pubkey,
+    pub timestamp: i64,
 }
 
-impl Invoice {
-    // Space: 8 (disc) + 8 + 32 + 32 + (4+32) + (4+32) + 8 + (4+128) + 1 + 8 + 8 + 8 + 32 + 1 = 364
-    pub const SIZE: usize = 8 + 8 + 32 + 32 + 36 + 36 + 8 + 132 + 1 + 8 + 8 + 8 + 32 + 1;
+/// v5: Contract created event
+#[event]
+pub struct ContractCreatedEvent {
+    pub contract: Pubkey,
+    pub payer: String,
+    pub payee: String,
+    pub total_amount: u64,
+    pub milestone_count: u8,
+    pub timestamp: i64,
+}
 
-    pub const STATUS_PENDING: u8 = 0;
-    pub const STATUS_PAID: u8 = 1;
-    pub const STATUS_REJECTED: u8 = 2;
-    pub const STATUS_CANCELLED: u8 = 3;
-    pub const STATUS_EXPIRED: u8 = 4;
+/// v5: Milestone submitted event
+#[event]
+pub struct MilestoneSubmittedEvent {
+    pub contract: Pubkey,
+    pub index: u8,
+    pub payee: String,
+    pub timestamp: i64,
 }
 
-/// A single payment entry within a batch
-/// v3: Added optional memo
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct BatchPaymentEntry {
-    /// The name of the recipient agent
-    pub recipient_name: String,
-    /// Amount in USDC smallest units (6 decimals)
+/// v5: Milestone paid out event (approved, claimed after review, or
+/// resolved by the arbiter)
+#[event]
+pub struct MilestoneApprovedEvent {
+    pub contract: Pubkey,
+    pub index: u8,
+    pub payer: String,
+    pub payee: String,
     pub amount: u64,
-    /// Optional memo (max 128 bytes)
-    pub memo: Option<String>,
+    pub refunded: u64,
+    pub released_amount: u64,
+    pub resolved: bool,
+    pub completed: bool,
+    pub timestamp: i64,
 }
 
-/// A split recipient with their share in basis points
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct SplitRecipient {
-    /// Agent name
-    pub name: String,
-    /// Share in basis points (5000 = 50%)
-    pub share_bps: u16,
+/// v5: Milestone disputed event
+#[event]
+pub struct MilestoneDisputedEvent {
+    pub contract: Pubkey,
+    pub index: u8,
+    pub disputed_by: Pubkey,
+    pub reason_hash: [u8; 32],
+    pub timestamp: i64,
 }
 
-// ============================================================
-// EVENTS
-// ============================================================
-
-/// v3: Transfer event with memo
+/// v5: Contract cancelled event
 #[event]
-pub struct TransferEvent {
-    pub sender: String,
-    pub receiver: String,
-    pub amount: u64,
-    pub memo: String,
+pub struct ContractCancelledEvent {
+    pub contract: Pubkey,
+    pub payer: String,
+    pub payee: String,
+    pub released_amount: u64,
+    pub refunded_amount: u64,
+    pub cancelled_by: Pubkey,
     pub timestamp: i64,
 }
 
-/// v3: Batch payment event with memos
+/// v5: HTLC created event
 #[event]
-pub struct BatchPaymentEvent {
+pub struct HtlcCreatedEvent {
+    pub htlc: Pubkey,
     pub sender: String,
-    pub recipients: Vec<String>,
-    pub amounts: Vec<u64>,
-    pub memos: Vec<String>,
-    pub total: u64,
+    pub receiver: String,
+    pub amount: u64,
+    pub hashlock: [u8; 32],
+    pub hash_algorithm: u8,
+    pub timelock: i64,
     pub timestamp: i64,
 }
 
-/// v3: Split payment event with memo
+/// v5: HTLC claimed event, reveals the preimage
 #[event]
-pub struct SplitPaymentEvent {
+pub struct HtlcClaimedEvent {
+    pub htlc: Pubkey,
     pub sender: String,
-    pub recipients: Vec<String>,
-    pub amounts: Vec<u64>,
-    pub total: u64,
-    pub memo: String,
+    pub receiver: String,
+    pub amount: u64,
+    pub preimage: Vec<u8>,
     pub timestamp: i64,
 }
 
-/// v3: Subscription executed event with auto-memo
+/// v5: HTLC refunded event
 #[event]
-pub struct SubscriptionExecutedEvent {
+pub struct HtlcRefundedEvent {
+    pub htlc: Pubkey,
     pub sender: String,
     pub receiver: String,
     pub amount: u64,
-    pub memo: String,
-    pub execution_count: u64,
     pub timestamp: i64,
 }
 
-/// v3: Daily limit set event
+/// v5: Stream created event
 #[event]
-pub struct DailyLimitSetEvent {
-    pub agent: String,
-    pub limit_usdc: u64,
+pub struct StreamCreatedEvent {
+    pub stream: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub rate_per_second: u64,
+    pub deposit: u64,
     pub timestamp: i64,
 }
 
-/// v3: Allowance approved event
+/// v5: Stream withdrawal event
 #[event]
-pub struct AllowanceApprovedEvent {
-    pub owner: String,
-    pub spender: String,
+pub struct StreamWithdrawnEvent {
+    pub stream: Pubkey,
+    pub sender: String,
+    pub receiver: String,
     pub amount: u64,
+    pub total_withdrawn: u64,
     pub timestamp: i64,
 }
 
-/// v3: TransferFrom event
+/// v5: Stream top-up event
 #[event]
-pub struct TransferFromEvent {
-    pub owner: String,
-    pub spender: String,
+pub struct StreamToppedUpEvent {
+    pub stream: Pubkey,
+    pub sender: String,
     pub amount: u64,
-    pub memo: String,
-    pub remaining_allowance: u64,
-    pub pull_number: u64,
+    pub total_deposited: u64,
     pub timestamp: i64,
 }
 
-/// v3: Allowance revoked event
+/// v5: Stream cancelled event
 #[event]
-pub struct AllowanceRevokedEvent {
-    pub owner: String,
-    pub spender: String,
-    pub total_pulled: u64,
-    pub pull_count: u64,
+pub struct StreamCancelledEvent {
+    pub stream: Pubkey,
+    pub sender: String,
+    pub receiver: String,
+    pub receiver_amount: u64,
+    pub sender_refund: u64,
+    pub total_streamed: u64,
     pub timestamp: i64,
 }
 
-/// v3: Allowance modified event
+/// v5: Payment channel opened event
 #[event]
-pub struct AllowanceModifiedEvent {
-    pub owner: String,
-    pub spender: String,
-    pub new_amount: u64,
-    pub action: String,
+pub struct ChannelOpenedEvent {
+    pub channel: Pubkey,
+    pub payer: String,
+    pub payee: String,
+    pub deposit: u64,
+    pub challenge_period: i64,
     pub timestamp: i64,
 }
 
-/// v4: Invoice created event
+/// v5: Payment channel voucher settled event
 #[event]
-pub struct InvoiceCreatedEvent {
-    pub invoice_id: u64,
-    pub requester: String,
+pub struct ChannelSettledEvent {
+    pub channel: Pubkey,
     pub payer: String,
+    pub payee: String,
     pub amount: u64,
-    pub memo: String,
-    pub expires_at: i64,
+    pub cumulative_amount: u64,
     pub timestamp: i64,
 }
 
-/// v4: Invoice paid event
+/// v5: Payment channel close requested event
 #[event]
-pub struct InvoicePaidEvent {
-    pub invoice_id: u64,
-    pub requester: String,
+pub struct ChannelCloseRequestedEvent {
+    pub channel: Pubkey,
     pub payer: String,
-    pub amount: u64,
-    pub memo: String,
+    pub payee: String,
+    pub closes_at: i64,
     pub timestamp: i64,
 }
 
-/// v4: Invoice rejected event
+/// v5: Payment channel closed event
 #[event]
-pub struct InvoiceRejectedEvent {
-    pub invoice_id: u64,
-    pub requester: String,
+pub struct ChannelClosedEvent {
+    pub channel: Pubkey,
     pub payer: String,
+    pub payee: String,
+    pub settled: u64,
+    pub refunded: u64,
+    pub timestamp: i64,
+}
+
+/// v5: Relayed payment intent executed event
+#[event]
+pub struct IntentExecutedEvent {
+    pub sender: String,
+    pub receiver: String,
     pub amount: u64,
+    pub nonce: u64,
+    pub relayer: Pubkey,
+    pub relayer_fee: u64,
     pub timestamp: i64,
 }
 
-/// v4: Invoice cancelled event
+/// v5: Metered plan proposed event
 #[event]
-pub struct InvoiceCancelledEvent {
-    pub invoice_id: u64,
-    pub requester: String,
-    pub payer: String,
+pub struct MeteredPlanCreatedEvent {
+    pub plan: Pubkey,
+    pub provider: String,
+    pub consumer: String,
+    pub price_per_unit: u64,
+    pub timestamp: i64,
+}
+
+/// v5: Metered plan accepted event
+#[event]
+pub struct MeteredPlanAcceptedEvent {
+    pub plan: Pubkey,
+    pub provider: String,
+    pub consumer: String,
+    pub price_per_unit: u64,
+    pub timestamp: i64,
+}
+
+/// v5: Metered plan cancelled event
+#[event]
+pub struct MeteredPlanCancelledEvent {
+    pub plan: Pubkey,
+    pub provider: String,
+    pub consumer: String,
+    pub settled_units: u64,
+    pub total_billed: u64,
+    pub settle_until: i64,
+    pub cancelled_by: Pubkey,
+    pub timestamp: i64,
+}
+
+/// v5: Usage settled event
+#[event]
+pub struct UsageSettledEvent {
+    pub plan: Pubkey,
+    pub provider: String,
+    pub consumer: String,
+    pub units: u64,
+    pub cumulative_units: u64,
     pub amount: u64,
+    pub remaining_allowance: u64,
+    pub timestamp: i64,
+}
+
+/// v5: Split config created or updated event
+#[event]
+pub struct SplitConfigUpdatedEvent {
+    pub split_config: Pubkey,
+    pub owner: String,
+    pub recipients: Vec<Pubkey>,
+    pub shares_bps: Vec<u16>,
+    pub reserve: u64,
+    pub locked: bool,
+    pub timestamp: i64,
+}
+
+/// v5: Split distributed event
+#[event]
+pub struct SplitDistributedEvent {
+    pub split_config: Pubkey,
+    pub owner: String,
+    pub recipients: Vec<Pubkey>,
+    pub amounts: Vec<u64>,
+    pub total: u64,
+    pub payouts: Vec<SplitPayoutNode>,
+    pub cranker: Pubkey,
     pub timestamp: i64,
 }
 
@@ -1968,7 +8360,7 @@ pub enum SolclawError {
     InvalidMint,
     #[msg("Unauthorized: you don't control this vault")]
     Unauthorized,
-    #[msg("Batch must contain 1-10 payments")]
+    #[msg("Batch must contain 1-61 payments (1-64 compact entries)")]
     InvalidBatchSize,
     #[msg("Wrong number of remaining accounts for batch/split")]
     InvalidRemainingAccounts,
@@ -1980,7 +8372,7 @@ pub enum SolclawError {
     Overflow,
     #[msg("Split shares must add up to 10000 basis points")]
     InvalidSplitShares,
-    #[msg("Maximum 10 split recipients allowed")]
+    #[msg("Invalid number of split recipients")]
     TooManySplitRecipients,
     #[msg("Subscription interval must be at least 60 seconds")]
     InvalidInterval,
@@ -2014,4 +8406,760 @@ pub enum SolclawError {
     InvoiceExpired,
     #[msg("Invalid expiry value")]
     InvalidExpiry,
+    // v5: Reference errors
+    #[msg("Too many reference keys (max 5)")]
+    TooManyReferences,
+    #[msg("Reference keys must be read-only and non-signing")]
+    InvalidReference,
+    // v5: Refund errors
+    #[msg("Invoice is not in paid status")]
+    InvoiceNotPaid,
+    #[msg("Refund exceeds the original payment")]
+    RefundExceedsPayment,
+    // v5: Escrow errors
+    #[msg("Cannot escrow to yourself")]
+    CannotEscrowSelf,
+    #[msg("Deadline must be in the future")]
+    InvalidDeadline,
+    #[msg("Escrow is not active")]
+    EscrowNotActive,
+    #[msg("Escrow does not match payer/payee")]
+    EscrowMismatch,
+    #[msg("Escrow deadline has not passed yet")]
+    EscrowNotExpired,
+    #[msg("Escrow has no arbiter")]
+    NoArbiter,
+    #[msg("Payee share exceeds the escrowed amount")]
+    InvalidEscrowSplit,
+    // v5: Milestone contract errors
+    #[msg("Contract must have 1-10 milestones")]
+    InvalidMilestoneCount,
+    #[msg("Contract is not active")]
+    ContractNotActive,
+    #[msg("Contract does not match payer/payee")]
+    ContractMismatch,
+    #[msg("Milestone index out of range")]
+    InvalidMilestone,
+    #[msg("Milestone is not in a valid status for this action")]
+    InvalidMilestoneStatus,
+    #[msg("Milestone deadline has passed")]
+    MilestoneDeadlinePassed,
+    #[msg("A milestone is submitted or disputed")]
+    MilestoneAwaitingApproval,
+    // v5: HTLC errors
+    #[msg("Hash algorithm must be 0 (sha256) or 1 (keccak256)")]
+    InvalidHashAlgorithm,
+    #[msg("HTLC is not active")]
+    HtlcNotActive,
+    #[msg("HTLC does not match sender/receiver")]
+    HtlcMismatch,
+    #[msg("Preimage does not match the hashlock")]
+    InvalidPreimage,
+    #[msg("HTLC timelock has expired")]
+    HtlcExpired,
+    #[msg("HTLC timelock has not expired yet")]
+    HtlcNotExpired,
+    // v5: Stream errors
+    #[msg("Stream rate must be greater than 0")]
+    InvalidStreamRate,
+    #[msg("Stream is not active")]
+    StreamNotActive,
+    #[msg("Stream does not match sender/receiver")]
+    StreamMismatch,
+    #[msg("Nothing has vested since the last withdrawal")]
+    NothingToWithdraw,
+    // v5: Payment channel errors
+    #[msg("Challenge period must be greater than 0")]
+    InvalidChallengePeriod,
+    #[msg("Payment channel does not match payer/payee")]
+    ChannelMismatch,
+    #[msg("Payment channel is not open")]
+    ChannelNotOpen,
+    #[msg("Payment channel is closed")]
+    ChannelClosed,
+    #[msg("Voucher amount is not above the settled amount")]
+    StaleVoucher,
+    #[msg("Voucher amount exceeds the channel deposit")]
+    VoucherExceedsDeposit,
+    #[msg("Challenge period has not elapsed")]
+    ChallengePeriodActive,
+    #[msg("No matching Ed25519 signature verification in this transaction")]
+    MissingSignatureVerification,
+    // v5: Payment intent errors
+    #[msg("Relayer fee exceeds the signed maximum")]
+    RelayerFeeTooHigh,
+    #[msg("Payment intent has expired")]
+    IntentExpired,
+    #[msg("Intent nonce does not match the next expected nonce")]
+    InvalidNonce,
+    // v5: Metered billing errors
+    #[msg("Metered plan does not match provider/consumer")]
+    MeteredPlanMismatch,
+    #[msg("Metered plan is not awaiting acceptance")]
+    MeteredPlanNotProposed,
+    #[msg("Metered plan is not active")]
+    MeteredPlanNotActive,
+    #[msg("Usage report is not above the settled units")]
+    StaleUsageReport,
+    #[msg("Split config is locked")]
+    SplitConfigLocked,
+    #[msg("Cannot split to yourself")]
+    CannotSplitToSelf,
+    #[msg("Recipient does not match split config")]
+    SplitRecipientMismatch,
+    #[msg("Nothing to distribute")]
+    NothingToDistribute,
+    #[msg("Split share is below the recipient's minimum amount")]
+    SplitBelowMinimum,
+    #[msg("Rounding recipient index out of range")]
+    InvalidSplitRounding,
+    #[msg("Nested split exceeds the maximum depth")]
+    SplitTooDeep,
+    #[msg("Nested split contains a cycle")]
+    SplitCycle,
+    #[msg("Split payout tree has too many recipients")]
+    SplitTreeTooLarge,
+    #[msg("Subscription is still active")]
+    SubscriptionStillActive,
+    #[msg("Subscription is paused")]
+    SubscriptionPaused,
+    #[msg("Subscription is not paused")]
+    SubscriptionNotPaused,
+    #[msg("Subscription limits must allow at least one payment")]
+    InvalidSubscriptionLimits,
+    #[msg("No subscription debt can be settled")]
+    NoSubscriptionDebt,
+    #[msg("Invalid subscription schedule")]
+    InvalidSchedule,
+    #[msg("Crank tip exceeds its maximum")]
+    InvalidCrankTip,
+    #[msg("Subscription offer has changed since it was read")]
+    OfferVersionMismatch,
+    #[msg("Effective date must not be in the past")]
+    InvalidEffectiveDate,
+    #[msg("No subscription change awaiting acceptance")]
+    NoPendingSubscriptionChange,
+    #[msg("A failure was recorded for this subscription too recently")]
+    FailureRecordedRecently,
+    #[msg("Subscription can be executed; nothing failed")]
+    SubscriptionPaymentPossible,
+    // v5: Milestone review errors
+    #[msg("Milestone is still under review by the payer")]
+    MilestoneUnderReview,
+    #[msg("Milestone review period has ended")]
+    MilestoneReviewEnded,
+    // v5: Metered plan lifecycle errors
+    #[msg("Metered plan is not cancelled or can still be settled")]
+    MeteredPlanStillOpen,
+    // v5: Batch recipient errors
+    #[msg("Recipient registry must be writable")]
+    RecipientNotWritable,
+    // v5: Compact batch errors
+    #[msg("Compact entries must be a whole number of 9-byte records")]
+    InvalidCompactEntries,
+    #[msg("Entry recipient index out of range")]
+    InvalidRecipientIndex,
+    #[msg("Recipient appears more than once in remaining accounts")]
+    DuplicateRecipient,
+    // v5: Subscription migration errors
+    #[msg("Account is not a pre-v5 subscription")]
+    NotLegacySubscription,
+    // v5: Subscription debt errors
+    #[msg("Subscription has unsettled accrued debt")]
+    SubscriptionDebtOutstanding,
+    // v5: Subscription change consent errors
+    #[msg("Pending change does not match the expected terms")]
+    SubscriptionChangeMismatch,
+    // v5: Batch self-payment errors
+    #[msg("Sender cannot be a recipient of its own payment")]
+    CannotPaySelf,
+    // v5: Batch recipient limit errors
+    #[msg("Payment exceeds the batch's per-recipient limit")]
+    RecipientLimitExceeded,
+    // v5: Subscription suspension errors
+    #[msg("Subscription is suspended after repeated failed payments")]
+    SubscriptionSuspended,
+    #[msg("Subscription accrues unpaid periods as debt; execute it instead")]
+    SubscriptionAccruesDebt,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_amounts_sum_to_total() {
+        let shares = [1500, 6000, 2500];
+        for rounding in [
+            SplitRounding::LargestRemainder,
+            SplitRounding::LargestShareFirst,
+            SplitRounding::RemainderTo(1),
+        ] {
+            for total in [0, 1, 7, 10, 999_999, u64::MAX] {
+                let (amounts, dust) = compute_split_amounts(total, &shares, rounding).unwrap();
+                assert_eq!(amounts.iter().map(|a| *a as u128).sum::<u128>(), total as u128);
+                assert!(dust < shares.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn split_dust_goes_to_largest_remainder() {
+        // 1.5, 6.0 and 2.5 units: the 0.5 remainders tie, list order wins
+        let (amounts, dust) =
+            compute_split_amounts(10, &[1500, 6000, 2500], SplitRounding::LargestRemainder).unwrap();
+        assert_eq!((amounts, dust), (vec![2, 6, 2], 1));
+        let (amounts, _) =
+            compute_split_amounts(100, &[3333, 3333, 3334], SplitRounding::LargestRemainder).unwrap();
+        assert_eq!(amounts, vec![33, 33, 34]);
+    }
+
+    #[test]
+    fn split_dust_goes_to_largest_share_first() {
+        let (amounts, dust) =
+            compute_split_amounts(10, &[1500, 6000, 2500], SplitRounding::LargestShareFirst).unwrap();
+        assert_eq!((amounts, dust), (vec![1, 7, 2], 1));
+        // Equal shares keep list order
+        let (amounts, _) =
+            compute_split_amounts(1, &[5000, 5000], SplitRounding::LargestShareFirst).unwrap();
+        assert_eq!(amounts, vec![1, 0]);
+    }
+
+    #[test]
+    fn split_dust_goes_to_chosen_recipient() {
+        let (amounts, dust) =
+            compute_split_amounts(10, &[1500, 6000, 2500], SplitRounding::RemainderTo(2)).unwrap();
+        assert_eq!((amounts, dust), (vec![1, 6, 3], 1));
+        assert_eq!(
+            compute_split_amounts(10, &[1500, 6000, 2500], SplitRounding::RemainderTo(3)).unwrap_err(),
+            SolclawError::InvalidSplitRounding.into()
+        );
+    }
+
+    /// Leak an account for the 'static lifetime SplitPayout borrows with.
+    fn leak_account<T: AccountSerialize>(key: Pubkey, owner: Pubkey, value: Option<&T>) -> AccountInfo<'static> {
+        let mut data = Vec::new();
+        if let Some(value) = value {
+            value.try_serialize(&mut data).unwrap();
+        }
+        leak_raw(key, owner, data)
+    }
+
+    fn leak_raw(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> AccountInfo<'static> {
+        AccountInfo::new(
+            Box::leak(Box::new(key)),
+            false,
+            true,
+            Box::leak(Box::new(0)),
+            data.leak(),
+            Box::leak(Box::new(owner)),
+            false,
+            0,
+        )
+    }
+
+    fn registry(key: Pubkey) -> AccountInfo<'static> {
+        let registry = AgentRegistry {
+            name_hash: [0; 32],
+            name: key.to_string()[..8].to_string(),
+            authority: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+            created_at: 0,
+            total_sent: 0,
+            total_received: 0,
+            bump: 0,
+            vault_bump: 0,
+            daily_limit: 0,
+            daily_spent: 0,
+            last_spend_day: 0,
+        };
+        leak_account(key, crate::ID, Some(&registry))
+    }
+
+    fn vault() -> AccountInfo<'static> {
+        leak_account::<AgentRegistry>(Pubkey::new_unique(), Pubkey::default(), None)
+    }
+
+    /// A split config owned by `owner` paying `recipients` as (registry, cascade).
+    fn split_config(owner: Pubkey, recipients: &[(Pubkey, bool)]) -> SplitConfig {
+        let share = 10000 / recipients.len() as u16;
+        SplitConfig {
+            owner,
+            owner_name: String::new(),
+            recipients: recipients
+                .iter()
+                .map(|(registry, cascade)| SplitConfigEntry {
+                    registry: *registry,
+                    share_bps: share,
+                    min_amount: 0,
+                    cascade: *cascade,
+                })
+                .collect(),
+            rounding: SplitRounding::LargestRemainder,
+            reserve: 0,
+            locked: false,
+            total_distributed: 0,
+            distribution_count: 0,
+            created_at: 0,
+            updated_at: 0,
+            bump: 0,
+        }
+    }
+
+    fn config_account(config: &SplitConfig) -> AccountInfo<'static> {
+        leak_account(Pubkey::new_unique(), crate::ID, Some(config))
+    }
+
+    /// Walk `root` with nothing to pay, so only accounts are checked.
+    /// Returns the tree as (registry, parent, depth, cascaded) nodes.
+    fn walk(
+        payer: Pubkey,
+        root: &SplitConfig,
+        remaining: Vec<AccountInfo<'static>>,
+    ) -> Result<Vec<(Pubkey, u8, u8, bool)>> {
+        let remaining: &'static [AccountInfo<'static>] = remaining.leak();
+        let mut payout = SplitPayout::new(vault(), vault(), &[], remaining, payer);
+        payout.pay_config(root, 0, SplitPayoutNode::ROOT, 0)?;
+        let nodes = payout.finish()?;
+        Ok(nodes
+            .iter()
+            .map(|n| (n.registry, n.parent, n.depth, n.cascaded))
+            .collect())
+    }
+
+    #[test]
+    fn split_walker_consumes_accounts_in_pre_order() {
+        // A splits to B and C, C splits further to D and E
+        let [a, b, c, d, e] = [(); 5].map(|_| Pubkey::new_unique());
+        let root = split_config(a, &[(c, true), (b, false)]);
+        let nested = split_config(c, &[(d, false), (e, false)]);
+        let remaining = vec![
+            registry(c),
+            config_account(&nested),
+            registry(d),
+            vault(),
+            registry(e),
+            vault(),
+            registry(b),
+            vault(),
+        ];
+        assert_eq!(
+            walk(a, &root, remaining).unwrap(),
+            vec![
+                (c, SplitPayoutNode::ROOT, 0, true),
+                (d, 0, 1, false),
+                (e, 0, 1, false),
+                (b, SplitPayoutNode::ROOT, 0, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_walker_rejects_misordered_or_unused_accounts() {
+        let [a, b, c] = [(); 3].map(|_| Pubkey::new_unique());
+        let root = split_config(a, &[(b, false), (c, false)]);
+        assert_eq!(
+            walk(a, &root, vec![registry(c), vault(), registry(b), vault()]).unwrap_err(),
+            SolclawError::SplitRecipientMismatch.into()
+        );
+        assert_eq!(
+            walk(a, &root, vec![registry(b), vault(), registry(c)]).unwrap_err(),
+            SolclawError::InvalidRemainingAccounts.into()
+        );
+        assert_eq!(
+            walk(a, &root, vec![registry(b), vault(), registry(c), vault(), vault()]).unwrap_err(),
+            SolclawError::InvalidRemainingAccounts.into()
+        );
+    }
+
+    #[test]
+    fn split_walker_rejects_payer_and_cycles() {
+        let [a, b, c] = [(); 3].map(|_| Pubkey::new_unique());
+
+        // The payer as a leaf of a nested config
+        let root = split_config(a, &[(b, true)]);
+        let nested = split_config(b, &[(a, false)]);
+        assert_eq!(
+            walk(a, &root, vec![registry(b), config_account(&nested), registry(a), vault()]).unwrap_err(),
+            SolclawError::CannotSplitToSelf.into()
+        );
+
+        // B and C cascade into each other
+        let root = split_config(a, &[(b, true)]);
+        let to_c = split_config(b, &[(c, true)]);
+        let to_b = split_config(c, &[(b, true)]);
+        let remaining = vec![
+            registry(b),
+            config_account(&to_c),
+            registry(c),
+            config_account(&to_b),
+            registry(b),
+            config_account(&to_c),
+        ];
+        assert_eq!(
+            walk(a, &root, remaining).unwrap_err(),
+            SolclawError::SplitCycle.into()
+        );
+    }
+
+    #[test]
+    fn legacy_subscriptions_are_recognised_by_size() {
+        // Pre-v5 accounts were allocated at 8 + 218 bytes
+        assert_eq!(8 + LegacySubscription::INIT_SPACE, 226);
+
+        let legacy = LegacySubscription {
+            sender: Pubkey::new_unique(),
+            receiver: Pubkey::new_unique(),
+            sender_name: "Alice".to_string(),
+            receiver_name: "Bob".to_string(),
+            amount: 5_000_000,
+            interval_seconds: 86_400,
+            last_executed: 1_700_000_000,
+            next_due: 1_700_086_400,
+            is_active: true,
+            authority: Pubkey::new_unique(),
+            total_paid: 15_000_000,
+            execution_count: 3,
+            bump: 254,
+        };
+        let mut data = Subscription::DISCRIMINATOR.to_vec();
+        legacy.serialize(&mut data).unwrap();
+        data.resize(226, 0);
+
+        let info = leak_raw(Pubkey::new_unique(), crate::ID, data.clone());
+        let loaded = LegacySubscription::load(&info).unwrap();
+        assert_eq!(loaded.authority, legacy.authority);
+        assert_eq!((loaded.next_due, loaded.execution_count), (1_700_086_400, 3));
+
+        // Current-layout accounts, other owners and other sizes are rejected
+        let current = leak_raw(
+            Pubkey::new_unique(),
+            crate::ID,
+            [data.clone(), vec![0; Subscription::INIT_SPACE + 8 - 226]].concat(),
+        );
+        let foreign = leak_raw(Pubkey::new_unique(), Pubkey::new_unique(), data);
+        for info in [current, foreign] {
+            assert_eq!(
+                LegacySubscription::load(&info).err().unwrap(),
+                SolclawError::NotLegacySubscription.into()
+            );
+        }
+    }
+
+    /// 2024-01-01 00:00 UTC
+    const JAN_1: i64 = 1_704_067_200;
+    const DAY: i64 = 86_400;
+
+    /// A subscription started at JAN_1 for 10 USDC per period.
+    fn subscription(
+        schedule: Schedule,
+        limits: SubscriptionLimits,
+        policy: MissedPeriodPolicy,
+    ) -> Subscription {
+        let mut subscription = Subscription {
+            id: 0,
+            sender: Pubkey::new_unique(),
+            receiver: Pubkey::new_unique(),
+            sender_name: "Alice".to_string(),
+            receiver_name: "Bob".to_string(),
+            amount: 0,
+            schedule,
+            last_executed: 0,
+            next_due: 0,
+            is_active: false,
+            authority: Pubkey::new_unique(),
+            total_paid: 0,
+            execution_count: 0,
+            payer: Pubkey::new_unique(),
+            paused_at: 0,
+            max_executions: 0,
+            end_at: 0,
+            max_total: 0,
+            completed_at: 0,
+            missed_period_policy: policy,
+            accrued_debt: 0,
+            crank_tip: 0,
+            max_crank_tip: 0,
+            missed_periods: 0,
+            total_crank_delay: 0,
+            total_crank_tips: 0,
+            offer: Pubkey::default(),
+            terms_hash: [0; 32],
+            pending_change: None,
+            consecutive_failures: 0,
+            max_consecutive_failures: 0,
+            last_failure_reason: SubscriptionFailureReason::None,
+            last_failure_at: 0,
+            suspended_at: 0,
+            bump: 0,
+            version: 0,
+        };
+        subscription.start(10_000_000, schedule, limits, policy, JAN_1).unwrap();
+        subscription
+    }
+
+    #[test]
+    fn resume_shifts_schedule_by_pause() {
+        let daily = subscription(
+            Schedule::Interval { seconds: DAY },
+            SubscriptionLimits::default(),
+            MissedPeriodPolicy::Skip,
+        );
+        assert_eq!(daily.next_due, JAN_1 + DAY);
+        assert_eq!(daily.resumed_next_due(0), Some(JAN_1 + DAY));
+        assert_eq!(daily.resumed_next_due(5 * DAY + 7), Some(JAN_1 + 6 * DAY + 7));
+
+        // Calendar schedules land on the first occurrence after the shift
+        let mut monthly = subscription(
+            Schedule::Monthly { day: 1 },
+            SubscriptionLimits::default(),
+            MissedPeriodPolicy::Skip,
+        );
+        monthly.next_due = JAN_1;
+        assert_eq!(monthly.resumed_next_due(0), Some(JAN_1));
+        assert_eq!(monthly.resumed_next_due(10 * DAY), Some(JAN_1 + 31 * DAY));
+        assert_eq!(monthly.resumed_next_due(i64::MAX), None);
+    }
+
+    #[test]
+    fn limits_bound_periods_remaining() {
+        let daily = Schedule::Interval { seconds: DAY };
+        let unlimited = subscription(daily, SubscriptionLimits::default(), MissedPeriodPolicy::Skip);
+        assert_eq!(unlimited.periods_remaining(u64::MAX), u64::MAX);
+        assert!(!unlimited.limit_reached());
+
+        let mut capped = subscription(
+            daily,
+            SubscriptionLimits { max_executions: 5, ..Default::default() },
+            MissedPeriodPolicy::Skip,
+        );
+        assert_eq!(capped.periods_remaining(10), 5);
+        capped.execution_count = 5;
+        assert!(capped.limit_reached());
+
+        // First due JAN_1 + 1 day; periods due up to and including end_at count
+        let mut ending = subscription(
+            daily,
+            SubscriptionLimits { end_at: JAN_1 + 3 * DAY, ..Default::default() },
+            MissedPeriodPolicy::Skip,
+        );
+        assert_eq!(ending.periods_remaining(10), 3);
+        ending.next_due = JAN_1 + 4 * DAY;
+        assert!(ending.limit_reached());
+
+        // 10 USDC periods under a 35 USDC cap; accrued debt counts as billed
+        let mut total = subscription(
+            daily,
+            SubscriptionLimits { max_total: 35_000_000, ..Default::default() },
+            MissedPeriodPolicy::Accrue,
+        );
+        assert_eq!(total.periods_remaining(10), 3);
+        total.total_paid = 10_000_000;
+        total.accrued_debt = 10_000_000;
+        assert_eq!(total.periods_remaining(10), 1);
+        total.accrued_debt = 20_000_000;
+        assert!(total.limit_reached());
+    }
+
+    #[test]
+    fn missed_periods_follow_policy() {
+        let daily = Schedule::Interval { seconds: DAY };
+        let outcome = |policy, limits, headroom| {
+            let subscription = subscription(daily, limits, policy);
+            // Due on days 1-4, cranked late on day 4
+            let now = JAN_1 + 4 * DAY + 100;
+            let (periods_due, due_after) = subscription.periods_due(now).unwrap();
+            assert_eq!((periods_due, due_after), (4, JAN_1 + 5 * DAY));
+            subscription
+                .missed_period_outcome(now, periods_due, due_after, headroom)
+                .unwrap()
+        };
+        let none = SubscriptionLimits::default();
+
+        // (paid, accrued, skipped, next_due)
+        assert_eq!(
+            outcome(MissedPeriodPolicy::Skip, none, u64::MAX),
+            (1, 0, 3, JAN_1 + 5 * DAY)
+        );
+
+        // Catch up as far as the headroom allows, but always at least one period
+        assert_eq!(
+            outcome(MissedPeriodPolicy::CatchUp, none, u64::MAX),
+            (4, 0, 0, JAN_1 + 5 * DAY)
+        );
+        assert_eq!(
+            outcome(MissedPeriodPolicy::CatchUp, none, 25_000_000),
+            (2, 0, 0, JAN_1 + 3 * DAY)
+        );
+        assert_eq!(
+            outcome(MissedPeriodPolicy::CatchUp, none, 0),
+            (1, 0, 0, JAN_1 + 2 * DAY)
+        );
+
+        // Accrue pays what the headroom covers and bills the rest as debt,
+        // dropping periods past the limits
+        assert_eq!(
+            outcome(MissedPeriodPolicy::Accrue, none, u64::MAX),
+            (4, 0, 0, JAN_1 + 5 * DAY)
+        );
+        assert_eq!(
+            outcome(MissedPeriodPolicy::Accrue, none, 25_000_000),
+            (2, 2, 0, JAN_1 + 5 * DAY)
+        );
+        assert_eq!(
+            outcome(MissedPeriodPolicy::Accrue, none, 9_999_999),
+            (0, 4, 0, JAN_1 + 5 * DAY)
+        );
+        assert_eq!(
+            outcome(MissedPeriodPolicy::Accrue, none, 0),
+            (0, 4, 0, JAN_1 + 5 * DAY)
+        );
+        let two = SubscriptionLimits { max_executions: 2, ..Default::default() };
+        assert_eq!(
+            outcome(MissedPeriodPolicy::Accrue, two, u64::MAX),
+            (2, 0, 2, JAN_1 + 5 * DAY)
+        );
+        assert_eq!(
+            outcome(MissedPeriodPolicy::Accrue, two, 0),
+            (0, 2, 2, JAN_1 + 5 * DAY)
+        );
+    }
+
+    #[test]
+    fn crank_tip_is_bounded_by_max_crank_tip() {
+        let mut subscription = subscription(
+            Schedule::Interval { seconds: DAY },
+            SubscriptionLimits::default(),
+            MissedPeriodPolicy::Skip,
+        );
+        // No maximum set: never tip
+        assert_eq!(subscription.crank_tip(50_000), 0);
+
+        subscription.max_crank_tip = 20_000;
+        assert_eq!(subscription.crank_tip(10_000), 10_000);
+        assert_eq!(subscription.crank_tip(50_000), 20_000);
+
+        // A per-subscription tip overrides the protocol default, still bounded
+        subscription.crank_tip = 15_000;
+        assert_eq!(subscription.crank_tip(5_000), 15_000);
+        subscription.crank_tip = 30_000;
+        assert_eq!(subscription.crank_tip(5_000), 20_000);
+    }
+
+    #[test]
+    fn agreed_changes_apply_from_effective_date() {
+        let mut subscription = subscription(
+            Schedule::Interval { seconds: DAY },
+            SubscriptionLimits::default(),
+            MissedPeriodPolicy::Skip,
+        );
+        let change = PendingSubscriptionChange {
+            amount: 12_000_000,
+            schedule: Schedule::Weekly { weekday: 0 },
+            effective_at: JAN_1 + 2 * DAY,
+            proposed_at: JAN_1,
+            accepted: false,
+        };
+
+        // Not agreed yet
+        subscription.pending_change = Some(change.clone());
+        assert_eq!(subscription.apply_pending_change().unwrap(), None);
+
+        // Agreed, but the next payment (day 1) is before the effective date
+        subscription.pending_change = Some(PendingSubscriptionChange { accepted: true, ..change });
+        assert_eq!(subscription.apply_pending_change().unwrap(), None);
+        assert_eq!(subscription.amount, 10_000_000);
+
+        // From day 3 on; 2024-01-01 is a Monday, so the next Monday is day 7
+        subscription.next_due = JAN_1 + 3 * DAY;
+        assert_eq!(subscription.apply_pending_change().unwrap(), Some(10_000_000));
+        assert_eq!(subscription.amount, 12_000_000);
+        assert_eq!(subscription.schedule, Schedule::Weekly { weekday: 0 });
+        assert_eq!(subscription.next_due, JAN_1 + 7 * DAY);
+        assert!(subscription.pending_change.is_none());
+        assert_eq!(subscription.apply_pending_change().unwrap(), None);
+    }
+
+    #[test]
+    fn failures_suspend_at_threshold() {
+        let mut subscription = subscription(
+            Schedule::Interval { seconds: DAY },
+            SubscriptionLimits::default(),
+            MissedPeriodPolicy::Skip,
+        );
+        assert_eq!(subscription.max_consecutive_failures, DEFAULT_MAX_CONSECUTIVE_FAILURES);
+        subscription.max_consecutive_failures = 3;
+
+        let reason = SubscriptionFailureReason::InsufficientFunds;
+        assert!(!subscription.record_failure(reason, JAN_1 + DAY));
+        assert!(!subscription.record_failure(reason, JAN_1 + 2 * DAY));
+        assert_eq!(subscription.suspended_at, 0);
+        assert!(subscription.record_failure(SubscriptionFailureReason::SpendingCapExceeded, JAN_1 + 3 * DAY));
+        assert_eq!(subscription.consecutive_failures, 3);
+        assert_eq!(subscription.last_failure_reason, SubscriptionFailureReason::SpendingCapExceeded);
+        assert_eq!(subscription.suspended_at, JAN_1 + 3 * DAY);
+        assert_eq!(subscription.paused_at, 0);
+
+        // 0 never suspends
+        subscription.suspended_at = 0;
+        subscription.max_consecutive_failures = 0;
+        for day in 4..20 {
+            assert!(!subscription.record_failure(reason, JAN_1 + day * DAY));
+        }
+        assert_eq!(subscription.suspended_at, 0);
+    }
+
+    #[test]
+    fn resume_after_suspension_keeps_missed_periods() {
+        let mut subscription = subscription(
+            Schedule::Interval { seconds: DAY },
+            SubscriptionLimits::default(),
+            MissedPeriodPolicy::Accrue,
+        );
+        subscription.max_consecutive_failures = 1;
+        assert!(subscription.record_failure(SubscriptionFailureReason::SpendingCapExceeded, JAN_1 + DAY));
+
+        // Resuming a suspension leaves the schedule alone, so the days spent
+        // suspended are accrued on the next crank
+        let now = JAN_1 + 5 * DAY + 100;
+        assert_eq!(subscription.resume(now), Some(0));
+        assert_eq!((subscription.suspended_at, subscription.consecutive_failures), (0, 0));
+        assert_eq!(subscription.next_due, JAN_1 + DAY);
+        let (periods_due, due_after) = subscription.periods_due(now).unwrap();
+        assert_eq!(
+            subscription.missed_period_outcome(now, periods_due, due_after, 10_000_000),
+            Some((1, 4, 0, JAN_1 + 6 * DAY))
+        );
+
+        // A pause on top of a suspension only forgives the paused time
+        subscription.suspended_at = JAN_1 + 6 * DAY;
+        subscription.paused_at = JAN_1 + 8 * DAY;
+        assert_eq!(subscription.resume(JAN_1 + 10 * DAY), Some(2 * DAY));
+        assert_eq!(subscription.next_due, JAN_1 + 3 * DAY);
+        assert_eq!((subscription.paused_at, subscription.suspended_at), (0, 0));
+    }
+
+    #[test]
+    fn sender_is_never_a_recipient() {
+        let sender = Pubkey::new_unique();
+        let sender_registry: &'static AccountInfo<'static> = Box::leak(Box::new(registry(sender)));
+        assert_eq!(
+            load_recipient(sender_registry, &vault(), None, sender).err().unwrap(),
+            SolclawError::CannotPaySelf.into()
+        );
+    }
+
+    #[test]
+    fn best_effort_entries_report_first_failed_check() {
+        let status = |amount, memo_len, recipient_headroom| {
+            BatchPaymentEntry::skip_status(amount, memo_len, 100, 50, recipient_headroom)
+        };
+        assert_eq!(status(10, 0, u64::MAX), BatchPaymentEntry::STATUS_SENT);
+        assert_eq!(status(10, 128, 10), BatchPaymentEntry::STATUS_SENT);
+        assert_eq!(status(10, 129, u64::MAX), BatchPaymentEntry::STATUS_MEMO_TOO_LONG);
+        assert_eq!(status(0, 0, u64::MAX), BatchPaymentEntry::STATUS_INVALID_AMOUNT);
+        assert_eq!(status(101, 0, u64::MAX), BatchPaymentEntry::STATUS_INSUFFICIENT_BALANCE);
+        assert_eq!(status(51, 0, u64::MAX), BatchPaymentEntry::STATUS_CAP_EXCEEDED);
+        assert_eq!(status(11, 0, 10), BatchPaymentEntry::STATUS_RECIPIENT_LIMIT);
+    }
 }
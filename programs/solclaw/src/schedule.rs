@@ -0,0 +1,302 @@
+//! v5: Due-date computation for subscription schedules.
+//!
+//! Everything here is pure integer arithmetic on unix timestamps (the
+//! `Clock::unix_timestamp` the program sees), so it can be unit-tested off
+//! chain. Calendar schedules are evaluated in UTC.
+
+use anchor_lang::prelude::*;
+
+const SECONDS_PER_HOUR: i64 = 3_600;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Longest gap between two occurrences of a valid calendar schedule: a cron
+/// schedule on day 31 only skips from Aug 31 to Oct 31 (61 days).
+const MAX_SEARCH_DAYS: i64 = 64;
+
+/// When a subscription payment falls due.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub enum Schedule {
+    /// Every `seconds` (at least 60), anchored to the first due date
+    Interval { seconds: i64 },
+    /// 00:00 UTC on `day` (1-31) of every month, clamped to the month's last day
+    Monthly { day: u8 },
+    /// 00:00 UTC every week on `weekday` (0 = Monday, 6 = Sunday)
+    Weekly { weekday: u8 },
+    /// Top of every hour whose bit is set in `hours` (bit 0 = 00:00 UTC), on
+    /// days matching `weekdays` (bit 0 = Monday) or `month_days` (bit 0 = the
+    /// 1st). An empty day mask matches every day; when both are set, either
+    /// may match, as in cron.
+    Cron { hours: u32, weekdays: u8, month_days: u32 },
+}
+
+impl Schedule {
+    /// Whether the schedule has well-formed parameters.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Schedule::Interval { seconds } => seconds >= 60,
+            Schedule::Monthly { day } => (1..=31).contains(&day),
+            Schedule::Weekly { weekday } => weekday < 7,
+            Schedule::Cron { hours, weekdays, month_days } => {
+                hours != 0 && hours < 1 << 24 && weekdays < 1 << 7 && month_days < 1 << 31
+            }
+        }
+    }
+
+    /// First occurrence strictly after `t`. Interval schedules run through
+    /// `anchor`; calendar schedules ignore it. `None` only for invalid schedules.
+    pub fn next_after(&self, anchor: i64, t: i64) -> Option<i64> {
+        match *self {
+            Schedule::Interval { seconds } => {
+                if seconds <= 0 {
+                    return None;
+                }
+                let steps = t.checked_sub(anchor)?.div_euclid(seconds).checked_add(1)?;
+                anchor.checked_add(steps.checked_mul(seconds)?)
+            }
+            Schedule::Monthly { day } => {
+                if !(1..=31).contains(&day) {
+                    return None;
+                }
+                let (mut year, mut month, _) = civil_from_days(t.div_euclid(SECONDS_PER_DAY));
+                loop {
+                    let due_day = (day as u32).min(days_in_month(year, month));
+                    let due = days_from_civil(year, month, due_day) * SECONDS_PER_DAY;
+                    if due > t {
+                        return Some(due);
+                    }
+                    (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                }
+            }
+            Schedule::Weekly { weekday } => {
+                if weekday >= 7 {
+                    return None;
+                }
+                let today = t.div_euclid(SECONDS_PER_DAY);
+                let mut due_day = today + (weekday as i64 - weekday_of(today)).rem_euclid(7);
+                if due_day * SECONDS_PER_DAY <= t {
+                    due_day += 7;
+                }
+                Some(due_day * SECONDS_PER_DAY)
+            }
+            Schedule::Cron { hours, weekdays, month_days } => {
+                if hours & 0x00ff_ffff == 0 {
+                    return None;
+                }
+                let first_hour = t.div_euclid(SECONDS_PER_HOUR) + 1;
+                let first_day = first_hour.div_euclid(24);
+                for day in first_day..first_day + MAX_SEARCH_DAYS {
+                    if !cron_day_matches(day, weekdays, month_days) {
+                        continue;
+                    }
+                    let start = if day == first_day { first_hour.rem_euclid(24) } else { 0 };
+                    if let Some(hour) = (start..24).find(|h| hours & (1 << h) != 0) {
+                        return Some(day * SECONDS_PER_DAY + hour * SECONDS_PER_HOUR);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Count occurrences in `[from, until]`, where `from` is itself an
+    /// occurrence, stopping after `cap`. Returns the count and the first
+    /// occurrence after the counted ones.
+    pub fn count(&self, from: i64, until: i64, cap: u64) -> Option<(u64, i64)> {
+        if from > until || cap == 0 {
+            return Some((0, from));
+        }
+        if let Schedule::Interval { seconds } = *self {
+            if seconds <= 0 {
+                return None;
+            }
+            let count = (((until - from) / seconds) as u64).saturating_add(1).min(cap);
+            let next = from.checked_add((count as i64).checked_mul(seconds)?)?;
+            return Some((count, next));
+        }
+
+        let mut count = 0;
+        let mut next = from;
+        while next <= until && count < cap {
+            count += 1;
+            next = self.next_after(from, next)?;
+        }
+        Some((count, next))
+    }
+}
+
+/// Day of the week for a day number since the epoch, 0 = Monday.
+/// 1970-01-01 was a Thursday.
+fn weekday_of(days: i64) -> i64 {
+    (days + 3).rem_euclid(7)
+}
+
+fn cron_day_matches(days: i64, weekdays: u8, month_days: u32) -> bool {
+    let weekday_match = weekdays & (1 << weekday_of(days)) != 0;
+    let (_, _, day) = civil_from_days(days);
+    let month_day_match = month_days & (1 << (day - 1)) != 0;
+    match (weekdays, month_days) {
+        (0, 0) => true,
+        (0, _) => month_day_match,
+        (_, 0) => weekday_match,
+        _ => weekday_match || month_day_match,
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's
+/// `days_from_civil`).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Inverse of [`days_from_civil`]: (year, month, day) for a day number.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unix timestamp for a UTC date and time.
+    fn ts(year: i64, month: u32, day: u32, hour: i64) -> i64 {
+        days_from_civil(year, month, day) * SECONDS_PER_DAY + hour * SECONDS_PER_HOUR
+    }
+
+    fn clock_at(unix_timestamp: i64) -> Clock {
+        Clock {
+            unix_timestamp,
+            ..Clock::default()
+        }
+    }
+
+    #[test]
+    fn civil_dates_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(ts(2024, 2, 29, 0), 1_709_164_800);
+        for days in [-800_000, -1, 0, 11_016, 19_782, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn interval_stays_anchored() {
+        let schedule = Schedule::Interval { seconds: 3_600 };
+        let anchor = 1_000_000;
+        assert_eq!(schedule.next_after(anchor, anchor), Some(anchor + 3_600));
+        assert_eq!(schedule.next_after(anchor, anchor + 5_000), Some(anchor + 7_200));
+        assert_eq!(schedule.next_after(anchor, anchor - 1), Some(anchor));
+        assert_eq!(schedule.count(anchor, anchor + 7_200, u64::MAX), Some((3, anchor + 10_800)));
+        assert_eq!(schedule.count(anchor, anchor + 7_200, 2), Some((2, anchor + 7_200)));
+    }
+
+    #[test]
+    fn monthly_clamps_to_end_of_month() {
+        let schedule = Schedule::Monthly { day: 31 };
+        let clock = clock_at(ts(2024, 1, 15, 9));
+        let jan = schedule.next_after(0, clock.unix_timestamp).unwrap();
+        assert_eq!(jan, ts(2024, 1, 31, 0));
+        let feb = schedule.next_after(0, jan).unwrap();
+        assert_eq!(feb, ts(2024, 2, 29, 0));
+        let mar = schedule.next_after(0, feb).unwrap();
+        assert_eq!(mar, ts(2024, 3, 31, 0));
+        let apr = schedule.next_after(0, mar).unwrap();
+        assert_eq!(apr, ts(2024, 4, 30, 0));
+        assert_eq!(schedule.next_after(0, ts(2023, 2, 1, 0)), Some(ts(2023, 2, 28, 0)));
+    }
+
+    #[test]
+    fn monthly_first_of_month_rolls_over_year() {
+        let schedule = Schedule::Monthly { day: 1 };
+        assert_eq!(schedule.next_after(0, ts(2024, 12, 1, 0)), Some(ts(2025, 1, 1, 0)));
+        assert_eq!(
+            schedule.count(ts(2024, 1, 1, 0), ts(2024, 6, 15, 0), u64::MAX),
+            Some((6, ts(2024, 7, 1, 0)))
+        );
+    }
+
+    #[test]
+    fn weekly_lands_on_weekday() {
+        // 2024-10-16 is a Wednesday
+        let schedule = Schedule::Weekly { weekday: 4 };
+        let friday = schedule.next_after(0, ts(2024, 10, 16, 12)).unwrap();
+        assert_eq!(friday, ts(2024, 10, 18, 0));
+        assert_eq!(schedule.next_after(0, friday), Some(ts(2024, 10, 25, 0)));
+        let monday = Schedule::Weekly { weekday: 0 };
+        assert_eq!(monday.next_after(0, ts(2024, 10, 16, 0)), Some(ts(2024, 10, 21, 0)));
+    }
+
+    #[test]
+    fn cron_every_weekday_at_nine() {
+        let schedule = Schedule::Cron {
+            hours: 1 << 9,
+            weekdays: 0b001_1111,
+            month_days: 0,
+        };
+        // Friday 2024-10-18 10:00 -> Monday 2024-10-21 09:00
+        let clock = clock_at(ts(2024, 10, 18, 10));
+        assert_eq!(schedule.next_after(0, clock.unix_timestamp), Some(ts(2024, 10, 21, 9)));
+        assert_eq!(schedule.next_after(0, ts(2024, 10, 18, 8)), Some(ts(2024, 10, 18, 9)));
+        assert_eq!(
+            schedule.count(ts(2024, 10, 14, 9), ts(2024, 10, 27, 23), u64::MAX),
+            Some((10, ts(2024, 10, 28, 9)))
+        );
+    }
+
+    #[test]
+    fn cron_month_days_or_weekdays() {
+        // 00:00 and 12:00 on the 31st, or on Sundays
+        let schedule = Schedule::Cron {
+            hours: 1 | 1 << 12,
+            weekdays: 1 << 6,
+            month_days: 1 << 30,
+        };
+        // 2024-10-27 is a Sunday
+        assert_eq!(schedule.next_after(0, ts(2024, 10, 27, 0)), Some(ts(2024, 10, 27, 12)));
+        assert_eq!(schedule.next_after(0, ts(2024, 10, 27, 12)), Some(ts(2024, 10, 31, 0)));
+
+        let day_31 = Schedule::Cron { hours: 1, weekdays: 0, month_days: 1 << 30 };
+        assert_eq!(day_31.next_after(0, ts(2024, 8, 31, 0)), Some(ts(2024, 10, 31, 0)));
+    }
+
+    #[test]
+    fn invalid_schedules_are_rejected() {
+        assert!(!Schedule::Interval { seconds: 59 }.is_valid());
+        assert!(!Schedule::Monthly { day: 0 }.is_valid());
+        assert!(!Schedule::Monthly { day: 32 }.is_valid());
+        assert!(!Schedule::Weekly { weekday: 7 }.is_valid());
+        assert!(!Schedule::Cron { hours: 0, weekdays: 0, month_days: 0 }.is_valid());
+        assert!(!Schedule::Cron { hours: 1 << 24, weekdays: 0, month_days: 0 }.is_valid());
+        assert!(Schedule::Cron { hours: 1, weekdays: 0, month_days: 0 }.is_valid());
+        assert_eq!(Schedule::Monthly { day: 0 }.next_after(0, 0), None);
+    }
+}
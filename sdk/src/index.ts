@@ -48,21 +48,82 @@ export interface AgentRegistry {
 
 // Subscription type
 export interface Subscription {
+  id: BN;
   sender: PublicKey;
   receiver: PublicKey;
   senderName: string;
   receiverName: string;
   amount: BN;
-  intervalSeconds: BN;
+  schedule: Schedule;
   lastExecuted: BN;
   nextDue: BN;
   isActive: boolean;
   authority: PublicKey;
   totalPaid: BN;
   executionCount: BN;
+  payer: PublicKey;
+  pausedAt: BN;
+  maxExecutions: BN;
+  endAt: BN;
+  maxTotal: BN;
+  completedAt: BN;
+  missedPeriodPolicy: MissedPeriodPolicy;
+  accruedDebt: BN;
+  crankTip: BN;
+  maxCrankTip: BN;
+  missedPeriods: BN;
+  totalCrankDelay: BN;
+  totalCrankTips: BN;
+  offer: PublicKey;
+  termsHash: number[];
+  pendingChange: PendingSubscriptionChange | null;
+  consecutiveFailures: number;
+  maxConsecutiveFailures: number;
+  lastFailureReason: SubscriptionFailureReason;
+  lastFailureAt: BN;
+  suspendedAt: BN;
   bump: number;
 }
 
+// Why the last due subscription payment could not be made
+export type SubscriptionFailureReason =
+  | { none: {} }
+  | { insufficientFunds: {} }
+  | { spendingCapExceeded: {} };
+
+// New subscription terms proposed by the receiver
+export interface PendingSubscriptionChange {
+  amount: BN;
+  schedule: Schedule;
+  effectiveAt: BN;
+  proposedAt: BN;
+  accepted: boolean;
+}
+
+// Subscription terms published by a receiver
+export interface SubscriptionOffer {
+  id: BN;
+  receiver: PublicKey;
+  receiverName: string;
+  amount: BN;
+  schedule: Schedule;
+  missedPeriodPolicy: MissedPeriodPolicy;
+  termsHash: number[];
+  version: number;
+  subscriberCount: BN;
+  createdAt: BN;
+  updatedAt: BN;
+  authority: PublicKey;
+  bump: number;
+}
+
+// Optional fixed-term bounds for a subscription (0 or omitted = no limit)
+export interface SubscriptionLimits {
+  maxExecutions?: number;
+  endAt?: number;
+  maxTotal?: number; // in USDC
+}
+
 // Batch payment entry
 export interface BatchPaymentEntry {
   recipientName: string;
@@ -73,8 +134,29 @@ export interface BatchPaymentEntry {
 export interface SplitRecipient {
   name: string;
   shareBps: number; // Basis points (5000 = 50%)
+  minAmount?: number; // Minimum USDC this recipient must receive
+  cascade?: boolean; // Forward the share through the recipient's SplitConfig
 }
 
+// Split rounding policy for dust that doesn't divide evenly
+export type SplitRounding =
+  | { largestRemainder: {} }
+  | { largestShareFirst: {} }
+  | { remainderTo: { 0: number } };
+
+// When subscription payments fall due (calendar schedules are in UTC)
+export type Schedule =
+  | { interval: { seconds: BN } }
+  | { monthly: { day: number } } // 1-31, clamped to the month's last day
+  | { weekly: { weekday: number } } // 0 = Monday
+  | { cron: { hours: number; weekdays: number; monthDays: number } }; // bitmaps
+
+// What a late crank does with missed subscription periods
+export type MissedPeriodPolicy =
+  | { skip: {} }
+  | { catchUp: {} }
+  | { accrue: {} };
+
 // SDK class
 export class SolclawSDK {
   private connection: Connection;
@@ -120,10 +202,88 @@ export class SolclawSDK {
     return { agentRegistry, vault };
   }
 
+  /**
+   * Get PDA for an agent's split configuration
+   */
+  getSplitConfigPDA(agentRegistry: PublicKey): PublicKey {
+    const [config] = PublicKey.findProgramAddressSync(
+      [Buffer.from("split_config"), agentRegistry.toBuffer()],
+      PROGRAM_ID
+    );
+    return config;
+  }
+
+  /**
+   * Append the remaining accounts of one split recipient in the pre-order
+   * the program walks them: [registry, vault] for a direct recipient,
+   * [registry, split_config] followed by that config's recipients for a
+   * cascading one
+   */
+  private async pushSplitAccounts(
+    remainingAccounts: { pubkey: PublicKey; isSigner: boolean; isWritable: boolean }[],
+    agentRegistry: PublicKey,
+    cascade: boolean,
+    depth: number = 0
+  ): Promise<void> {
+    if (!cascade) {
+      const registry = await (this.program!.account as any).agentRegistry.fetch(agentRegistry);
+      remainingAccounts.push(
+        { pubkey: agentRegistry, isSigner: false, isWritable: true },
+        { pubkey: registry.vault, isSigner: false, isWritable: true }
+      );
+      return;
+    }
+
+    if (depth >= 3) {
+      throw new Error("Split cascades more than 3 levels deep");
+    }
+    const splitConfig = this.getSplitConfigPDA(agentRegistry);
+    const config = await (this.program!.account as any).splitConfig.fetch(splitConfig);
+    remainingAccounts.push(
+      { pubkey: agentRegistry, isSigner: false, isWritable: false },
+      { pubkey: splitConfig, isSigner: false, isWritable: false }
+    );
+    for (const entry of config.recipients) {
+      await this.pushSplitAccounts(remainingAccounts, entry.registry, entry.cascade, depth + 1);
+    }
+  }
+
+  /**
+   * Get PDA for the global protocol config
+   */
+  getProtocolConfigPDA(): PublicKey {
+    const [config] = PublicKey.findProgramAddressSync(
+      [Buffer.from("protocol_config")],
+      PROGRAM_ID
+    );
+    return config;
+  }
+
+  /**
+   * Get PDA for a receiver's subscription offer
+   */
+  getSubscriptionOfferPDA(receiverName: string, offerId: number | BN = 0): PublicKey {
+    const receiverPDAs = this.getAgentPDAs(receiverName);
+    const [offer] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("subscription_offer"),
+        receiverPDAs.agentRegistry.toBuffer(),
+        new BN(offerId).toArrayLike(Buffer, "le", 8),
+      ],
+      PROGRAM_ID
+    );
+    return offer;
+  }
+
   /**
    * Get PDA for a subscription
+   * v5: Seeded by a client-chosen subscription ID
    */
-  getSubscriptionPDA(senderName: string, receiverName: string): PublicKey {
+  getSubscriptionPDA(
+    senderName: string,
+    receiverName: string,
+    subscriptionId: number | BN = 0
+  ): PublicKey {
     const senderPDAs = this.getAgentPDAs(senderName);
     const receiverPDAs = this.getAgentPDAs(receiverName);
 
@@ -132,6 +292,7 @@ export class SolclawSDK {
         Buffer.from("subscription"),
         senderPDAs.agentRegistry.toBuffer(),
         receiverPDAs.agentRegistry.toBuffer(),
+        new BN(subscriptionId).toArrayLike(Buffer, "le", 8),
       ],
       PROGRAM_ID
     );
@@ -366,18 +527,25 @@ export class SolclawSDK {
 
   /**
    * Batch pay multiple agents in one transaction
-   * Max 10 recipients per batch
+   * Max 61 entries per batch; this sends a legacy transaction, which fits
+   * about 10 (use a v0 transaction with a lookup table for more)
+   * maxPerRecipient caps what any one recipient gets across the batch (0 = no limit)
+   * With atomic = false, failing entries are skipped instead of aborting:
+   * memo over 128 characters, invalid recipient, zero amount, insufficient
+   * balance, no daily-cap headroom left or over maxPerRecipient
    */
   async batchPayment(
     senderName: string,
-    payments: BatchPaymentEntry[]
+    payments: BatchPaymentEntry[],
+    atomic: boolean = true,
+    maxPerRecipient: number = 0
   ): Promise<string> {
     if (!this.program || !this.provider) {
       throw new Error("SDK not initialized. Call initialize() first.");
     }
 
-    if (payments.length < 1 || payments.length > 10) {
-      throw new Error("Batch must contain 1-10 payments");
+    if (payments.length < 1 || payments.length > 61) {
+      throw new Error("Batch must contain 1-61 payments");
     }
 
     const senderPDAs = this.getAgentPDAs(senderName);
@@ -410,7 +578,7 @@ export class SolclawSDK {
     }
 
     const tx = await this.program.methods
-      .batchPayment(paymentEntries)
+      .batchPayment(paymentEntries, atomic, new BN(Math.floor(maxPerRecipient * 1_000_000)))
       .accounts({
         senderRegistry: senderPDAs.agentRegistry,
         senderVault: senderPDAs.vault,
@@ -430,19 +598,23 @@ export class SolclawSDK {
   /**
    * Split a total amount across multiple agents proportionally
    * Shares must sum to 10000 (100% in basis points)
-   * Max 10 recipients
+   * Max 20 recipients
+   * Recipients with cascade = true forward their share through their own
+   * SplitConfig (up to 3 levels); the payer can't appear anywhere in the tree
    */
   async splitPayment(
     senderName: string,
     totalAmount: number,
-    recipients: SplitRecipient[]
+    recipients: SplitRecipient[],
+    memo: string | null = null,
+    rounding: SplitRounding = { largestRemainder: {} }
   ): Promise<string> {
     if (!this.program || !this.provider) {
       throw new Error("SDK not initialized. Call initialize() first.");
     }
 
-    if (recipients.length < 2 || recipients.length > 10) {
-      throw new Error("Split must have 2-10 recipients");
+    if (recipients.length < 2 || recipients.length > 20) {
+      throw new Error("Split must have 2-20 recipients");
     }
 
     // Validate shares sum to 10000
@@ -464,10 +636,16 @@ export class SolclawSDK {
     const splitRecipients = recipients.map((r) => ({
       name: r.name,
       shareBps: r.shareBps,
+      minAmount: new BN(Math.floor((r.minAmount || 0) * 1_000_000)),
+      cascade: r.cascade || false,
     }));
 
     for (const recipient of recipients) {
       const recipientPDAs = this.getAgentPDAs(recipient.name);
+      if (recipient.cascade) {
+        await this.pushSplitAccounts(remainingAccounts, recipientPDAs.agentRegistry, true);
+        continue;
+      }
       remainingAccounts.push({
         pubkey: recipientPDAs.agentRegistry,
         isSigner: false,
@@ -483,7 +661,7 @@ export class SolclawSDK {
     const amountUnits = Math.floor(totalAmount * 1_000_000);
 
     const tx = await this.program.methods
-      .splitPayment(new BN(amountUnits), splitRecipients)
+      .splitPayment(new BN(amountUnits), splitRecipients, memo, rounding)
       .accounts({
         senderRegistry: senderPDAs.agentRegistry,
         senderVault: senderPDAs.vault,
@@ -505,30 +683,50 @@ export class SolclawSDK {
    * @param senderName - Name of the paying agent
    * @param receiverName - Name of the receiving agent
    * @param amount - Amount per payment in USDC
-   * @param intervalSeconds - Interval between payments in seconds (min 60)
+   * @param schedule - Interval between payments in seconds (min 60), or a calendar schedule
+   * @param subscriptionId - Nonce distinguishing subscriptions to the same receiver
+   * @param limits - Optional max executions, end date (unix seconds) and total cap in USDC
+   * @param missedPeriodPolicy - Skip, catch up or accrue periods missed between cranks
    */
   async createSubscription(
     senderName: string,
     receiverName: string,
     amount: number,
-    intervalSeconds: number
+    schedule: number | Schedule,
+    subscriptionId: number = 0,
+    limits: SubscriptionLimits = {},
+    missedPeriodPolicy: MissedPeriodPolicy = { skip: {} }
   ): Promise<string> {
     if (!this.program || !this.provider) {
       throw new Error("SDK not initialized. Call initialize() first.");
     }
 
-    if (intervalSeconds < 60) {
-      throw new Error("Interval must be at least 60 seconds");
+    if (typeof schedule === "number") {
+      if (schedule < 60) {
+        throw new Error("Interval must be at least 60 seconds");
+      }
+      schedule = { interval: { seconds: new BN(schedule) } };
     }
 
     const senderPDAs = this.getAgentPDAs(senderName);
     const receiverPDAs = this.getAgentPDAs(receiverName);
-    const subscription = this.getSubscriptionPDA(senderName, receiverName);
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
 
     const amountUnits = Math.floor(amount * 1_000_000);
 
     const tx = await this.program.methods
-      .createSubscription(receiverName, new BN(amountUnits), new BN(intervalSeconds))
+      .createSubscription(
+        new BN(subscriptionId),
+        receiverName,
+        new BN(amountUnits),
+        schedule,
+        {
+          maxExecutions: new BN(limits.maxExecutions ?? 0),
+          endAt: new BN(limits.endAt ?? 0),
+          maxTotal: new BN(Math.floor((limits.maxTotal ?? 0) * 1_000_000)),
+        },
+        missedPeriodPolicy
+      )
       .accounts({
         subscription,
         senderRegistry: senderPDAs.agentRegistry,
@@ -545,10 +743,13 @@ export class SolclawSDK {
   /**
    * Execute a due subscription payment (permissionless crank)
    * Anyone can call this for any due subscription
+   * @param claimTip - Receive the crank tip in the wallet's USDC token account
    */
   async executeSubscription(
     senderName: string,
-    receiverName: string
+    receiverName: string,
+    subscriptionId: number = 0,
+    claimTip: boolean = true
   ): Promise<string> {
     if (!this.program || !this.provider) {
       throw new Error("SDK not initialized. Call initialize() first.");
@@ -556,7 +757,13 @@ export class SolclawSDK {
 
     const senderPDAs = this.getAgentPDAs(senderName);
     const receiverPDAs = this.getAgentPDAs(receiverName);
-    const subscription = this.getSubscriptionPDA(senderName, receiverName);
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
+
+    const crankerTokenAccount = claimTip
+      ? await getAssociatedTokenAddress(USDC_MINT, this.provider.wallet.publicKey)
+      : null;
+    const protocolConfig = this.getProtocolConfigPDA();
+    const protocolConfigInfo = await this.connection.getAccountInfo(protocolConfig);
 
     const tx = await this.program.methods
       .executeSubscription()
@@ -568,6 +775,217 @@ export class SolclawSDK {
         receiverVault: receiverPDAs.vault,
         tokenProgram: TOKEN_PROGRAM_ID,
         cranker: this.provider.wallet.publicKey,
+        crankerTokenAccount,
+        protocolConfig: protocolConfigInfo ? protocolConfig : null,
+      })
+      .rpc();
+
+    return tx;
+  }
+
+  /**
+   * Pay down debt accrued under the accrue policy (permissionless crank)
+   * Pays as much as the sender vault and spending cap allow
+   */
+  async settleSubscriptionDebt(
+    senderName: string,
+    receiverName: string,
+    subscriptionId: number = 0
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    const senderPDAs = this.getAgentPDAs(senderName);
+    const receiverPDAs = this.getAgentPDAs(receiverName);
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
+
+    const tx = await this.program.methods
+      .settleSubscriptionDebt()
+      .accounts({
+        subscription,
+        senderRegistry: senderPDAs.agentRegistry,
+        receiverRegistry: receiverPDAs.agentRegistry,
+        senderVault: senderPDAs.vault,
+        receiverVault: receiverPDAs.vault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        cranker: this.provider.wallet.publicKey,
+        crankerTokenAccount: null,
+        protocolConfig: null,
+      })
+      .rpc();
+
+    return tx;
+  }
+
+  /**
+   * Record that a due subscription can't be paid (permissionless crank)
+   * Suspends the subscription after maxConsecutiveFailures failures in a row
+   * Not used under the accrue policy, where executeSubscription bills shortfalls as debt
+   */
+  async recordFailedExecution(
+    senderName: string,
+    receiverName: string,
+    subscriptionId: number = 0
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    const senderPDAs = this.getAgentPDAs(senderName);
+    const receiverPDAs = this.getAgentPDAs(receiverName);
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
+
+    const tx = await this.program.methods
+      .recordFailedExecution()
+      .accounts({
+        subscription,
+        senderRegistry: senderPDAs.agentRegistry,
+        receiverRegistry: receiverPDAs.agentRegistry,
+        senderVault: senderPDAs.vault,
+        receiverVault: receiverPDAs.vault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        cranker: this.provider.wallet.publicKey,
+        crankerTokenAccount: null,
+        protocolConfig: null,
+      })
+      .rpc();
+
+    return tx;
+  }
+
+  /**
+   * Propose new terms as the receiver, effective from the first payment due
+   * at or after effectiveAt. A lower price on the same schedule applies
+   * without consent; anything else waits for acceptSubscriptionChange
+   */
+  async proposeSubscriptionChange(
+    senderName: string,
+    receiverName: string,
+    newAmount: number,
+    newSchedule: number | Schedule,
+    effectiveAt: number,
+    subscriptionId: number = 0
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    if (typeof newSchedule === "number") {
+      newSchedule = { interval: { seconds: new BN(newSchedule) } };
+    }
+
+    const receiverPDAs = this.getAgentPDAs(receiverName);
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
+
+    const tx = await this.program.methods
+      .proposeSubscriptionChange(
+        new BN(Math.floor(newAmount * 1_000_000)),
+        newSchedule,
+        new BN(effectiveAt)
+      )
+      .accounts({
+        subscription,
+        receiverRegistry: receiverPDAs.agentRegistry,
+        authority: this.provider.wallet.publicKey,
+      })
+      .rpc();
+
+    return tx;
+  }
+
+  /**
+   * Consent to the receiver's pending change
+   * The expected terms must match the proposal the sender reviewed, or the
+   * transaction fails, so the receiver can't swap them in the meantime
+   * Only the sender (authority) can accept
+   */
+  async acceptSubscriptionChange(
+    senderName: string,
+    receiverName: string,
+    expectedAmount: number,
+    expectedSchedule: number | Schedule,
+    expectedEffectiveAt: number,
+    subscriptionId: number = 0
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    if (typeof expectedSchedule === "number") {
+      expectedSchedule = { interval: { seconds: new BN(expectedSchedule) } };
+    }
+
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
+
+    const tx = await this.program.methods
+      .acceptSubscriptionChange(
+        new BN(Math.floor(expectedAmount * 1_000_000)),
+        expectedSchedule,
+        new BN(expectedEffectiveAt)
+      )
+      .accounts({
+        subscription,
+        authority: this.provider.wallet.publicKey,
+      })
+      .rpc();
+
+    return tx;
+  }
+
+  /**
+   * Set the tip paid to whoever cranks a subscription
+   * A tip of 0 uses the protocol default; no tip ever exceeds maxTip (0 = never tip)
+   * Only the sender (authority) can set it
+   */
+  async setSubscriptionCrankTip(
+    senderName: string,
+    receiverName: string,
+    tip: number,
+    maxTip: number,
+    subscriptionId: number = 0
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
+
+    const tx = await this.program.methods
+      .setSubscriptionCrankTip(
+        new BN(Math.floor(tip * 1_000_000)),
+        new BN(Math.floor(maxTip * 1_000_000))
+      )
+      .accounts({
+        subscription,
+        authority: this.provider.wallet.publicKey,
+      })
+      .rpc();
+
+    return tx;
+  }
+
+  /**
+   * Set how many consecutive failed executions suspend a subscription
+   * @param maxFailures - 0 never suspends
+   */
+  async setSubscriptionMaxFailures(
+    senderName: string,
+    receiverName: string,
+    maxFailures: number,
+    subscriptionId: number = 0
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
+
+    const tx = await this.program.methods
+      .setSubscriptionMaxFailures(maxFailures)
+      .accounts({
+        subscription,
+        authority: this.provider.wallet.publicKey,
       })
       .rpc();
 
@@ -580,13 +998,14 @@ export class SolclawSDK {
    */
   async cancelSubscription(
     senderName: string,
-    receiverName: string
+    receiverName: string,
+    subscriptionId: number = 0
   ): Promise<string> {
     if (!this.program || !this.provider) {
       throw new Error("SDK not initialized. Call initialize() first.");
     }
 
-    const subscription = this.getSubscriptionPDA(senderName, receiverName);
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
 
     const tx = await this.program.methods
       .cancelSubscription()
@@ -599,18 +1018,233 @@ export class SolclawSDK {
     return tx;
   }
 
+  /**
+   * Pause or resume a subscription; resuming skips the paused period
+   * Resuming also lifts a suspension, without skipping the suspended period
+   * Only the sender (authority) can pause or resume
+   */
+  async setSubscriptionPaused(
+    senderName: string,
+    receiverName: string,
+    paused: boolean,
+    subscriptionId: number = 0
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
+    const method = paused
+      ? this.program.methods.pauseSubscription()
+      : this.program.methods.resumeSubscription();
+
+    const tx = await method
+      .accounts({
+        subscription,
+        authority: this.provider.wallet.publicKey,
+      })
+      .rpc();
+
+    return tx;
+  }
+
+  /**
+   * Close a cancelled subscription, returning its rent to the original payer
+   * Only the sender (authority) can close, after any accrued debt is settled
+   */
+  async closeSubscription(
+    senderName: string,
+    receiverName: string,
+    subscriptionId: number = 0
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
+    const account = await (this.program.account as any).subscription.fetch(subscription);
+
+    const tx = await this.program.methods
+      .closeSubscription()
+      .accounts({
+        subscription,
+        authority: this.provider.wallet.publicKey,
+        payer: account.payer,
+      })
+      .rpc();
+
+    return tx;
+  }
+
+  /**
+   * Move a pre-v5 subscription (seeded without an ID) to the current layout
+   * under `subscriptionId`. Legacy subscriptions can't be executed, changed
+   * or cancelled until migrated. Only the sender (authority) can migrate
+   */
+  async migrateSubscription(
+    senderName: string,
+    receiverName: string,
+    subscriptionId: number = 0
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    const senderPDAs = this.getAgentPDAs(senderName);
+    const receiverPDAs = this.getAgentPDAs(receiverName);
+    const [legacySubscription] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("subscription"),
+        senderPDAs.agentRegistry.toBuffer(),
+        receiverPDAs.agentRegistry.toBuffer(),
+      ],
+      PROGRAM_ID
+    );
+
+    const tx = await this.program.methods
+      .migrateSubscription(new BN(subscriptionId))
+      .accounts({
+        legacySubscription,
+        subscription: this.getSubscriptionPDA(senderName, receiverName, subscriptionId),
+        senderRegistry: senderPDAs.agentRegistry,
+        receiverRegistry: receiverPDAs.agentRegistry,
+        authority: this.provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    return tx;
+  }
+
+  /**
+   * Publish (or, if it exists, update) a subscription offer as the receiver
+   * Updates never change the terms of existing subscribers
+   * @param termsHash - 32-byte hash of the off-chain service terms
+   */
+  async publishSubscriptionOffer(
+    receiverName: string,
+    amount: number,
+    schedule: number | Schedule,
+    termsHash: number[],
+    offerId: number = 0,
+    missedPeriodPolicy: MissedPeriodPolicy = { skip: {} }
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    if (typeof schedule === "number") {
+      schedule = { interval: { seconds: new BN(schedule) } };
+    }
+
+    const receiverPDAs = this.getAgentPDAs(receiverName);
+    const offer = this.getSubscriptionOfferPDA(receiverName, offerId);
+    const amountUnits = new BN(Math.floor(amount * 1_000_000));
+    const existing = await this.connection.getAccountInfo(offer);
+
+    if (existing) {
+      return this.program.methods
+        .updateSubscriptionOffer(amountUnits, schedule, missedPeriodPolicy, termsHash)
+        .accounts({
+          offer,
+          authority: this.provider.wallet.publicKey,
+        })
+        .rpc();
+    }
+
+    return this.program.methods
+      .createSubscriptionOffer(
+        new BN(offerId),
+        amountUnits,
+        schedule,
+        missedPeriodPolicy,
+        termsHash
+      )
+      .accounts({
+        offer,
+        receiverRegistry: receiverPDAs.agentRegistry,
+        authority: this.provider.wallet.publicKey,
+        payer: this.provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+  }
+
+  /**
+   * Subscribe to a receiver's offer on the terms currently published
+   * Fails if the offer changed since `expectedVersion` was read
+   */
+  async acceptOffer(
+    senderName: string,
+    receiverName: string,
+    offerId: number,
+    expectedVersion: number,
+    subscriptionId: number = 0,
+    limits: SubscriptionLimits = {}
+  ): Promise<string> {
+    if (!this.program || !this.provider) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    const senderPDAs = this.getAgentPDAs(senderName);
+    const receiverPDAs = this.getAgentPDAs(receiverName);
+    const offer = this.getSubscriptionOfferPDA(receiverName, offerId);
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
+
+    const tx = await this.program.methods
+      .acceptOffer(new BN(subscriptionId), expectedVersion, {
+        maxExecutions: new BN(limits.maxExecutions ?? 0),
+        endAt: new BN(limits.endAt ?? 0),
+        maxTotal: new BN(Math.floor((limits.maxTotal ?? 0) * 1_000_000)),
+      })
+      .accounts({
+        offer,
+        subscription,
+        senderRegistry: senderPDAs.agentRegistry,
+        receiverRegistry: receiverPDAs.agentRegistry,
+        authority: this.provider.wallet.publicKey,
+        payer: this.provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    return tx;
+  }
+
+  /**
+   * Get a subscription offer
+   */
+  async getSubscriptionOffer(
+    receiverName: string,
+    offerId: number = 0
+  ): Promise<SubscriptionOffer | null> {
+    if (!this.program) {
+      throw new Error("SDK not initialized. Call initialize() first.");
+    }
+
+    const offer = this.getSubscriptionOfferPDA(receiverName, offerId);
+
+    try {
+      const account = await (this.program.account as any).subscriptionOffer.fetch(offer);
+      return account as SubscriptionOffer;
+    } catch {
+      return null;
+    }
+  }
+
   /**
    * Get subscription details
    */
   async getSubscription(
     senderName: string,
-    receiverName: string
+    receiverName: string,
+    subscriptionId: number = 0
   ): Promise<Subscription | null> {
     if (!this.program) {
       throw new Error("SDK not initialized. Call initialize() first.");
     }
 
-    const subscription = this.getSubscriptionPDA(senderName, receiverName);
+    const subscription = this.getSubscriptionPDA(senderName, receiverName, subscriptionId);
 
     try {
       const account = await (this.program.account as any).subscription.fetch(
@@ -653,7 +1287,13 @@ export class SolclawSDK {
     const now = Math.floor(Date.now() / 1000);
 
     return subscriptions
-      .filter((s) => s.isActive && Number(s.nextDue) <= now)
+      .filter(
+        (s) =>
+          s.isActive &&
+          Number(s.pausedAt) === 0 &&
+          Number(s.suspendedAt) === 0 &&
+          Number(s.nextDue) <= now
+      )
       .map((s) => ({
         subscription: s,
         senderName: s.senderName,
@@ -1,6 +1,15 @@
 const anchor = require("@coral-xyz/anchor");
-const { PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY } = require("@solana/web3.js");
-const { TOKEN_PROGRAM_ID } = require("@solana/spl-token");
+const {
+  PublicKey,
+  SystemProgram,
+  SYSVAR_RENT_PUBKEY,
+  AddressLookupTableAccount,
+  AddressLookupTableProgram,
+  ComputeBudgetProgram,
+  TransactionMessage,
+  VersionedTransaction,
+} = require("@solana/web3.js");
+const { TOKEN_PROGRAM_ID, getAssociatedTokenAddress } = require("@solana/spl-token");
 
 const PROGRAM_ID = new PublicKey("J4qipHcPyaPkVs8ymCLcpgqSDJeoSn3k1LJLK7Q9DZ5H");
 const USDC_MINT = new PublicKey("4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU");
@@ -99,4 +108,177 @@ describe("solclaw", () => {
       }
     }
   });
+
+  it("Benchmarks a full compact batch payment", async () => {
+    // Must match MAX_BATCH_RECIPIENTS and MAX_COMPACT_BATCH_SIZE in lib.rs
+    const RECIPIENTS = 61;
+    const ENTRIES = 64;
+    const AMOUNT = 1n;
+    // Compute budget a full compact batch must stay within (see README,
+    // "Large Batches"); the transaction limit is 1.4M
+    const CU_BUDGET = 1_000_000;
+    const senderName = "SolClawTest";
+    const connection = provider.connection;
+    const wallet = provider.wallet.publicKey;
+
+    const agentPda = (name) =>
+      PublicKey.findProgramAddressSync([Buffer.from("agent"), Buffer.from(name)], PROGRAM_ID)[0];
+    const vaultPda = (name) =>
+      PublicKey.findProgramAddressSync([Buffer.from("vault"), Buffer.from(name)], PROGRAM_ID)[0];
+    const vaultBalance = async (name) =>
+      BigInt((await connection.getTokenAccountBalance(vaultPda(name))).value.amount);
+
+    // Close lookup tables deactivated by earlier runs (they need ~513 slots
+    // after deactivation before they can be closed)
+    const ownTables = await connection.getProgramAccounts(AddressLookupTableProgram.programId, {
+      filters: [{ memcmp: { offset: 22, bytes: wallet.toBase58() } }],
+    });
+    for (const { pubkey, account } of ownTables) {
+      const state = AddressLookupTableAccount.deserialize(account.data);
+      if (state.deactivationSlot === BigInt("18446744073709551615")) continue;
+      try {
+        await provider.sendAndConfirm(
+          new anchor.web3.Transaction().add(
+            AddressLookupTableProgram.closeLookupTable({
+              lookupTable: pubkey,
+              authority: wallet,
+              recipient: wallet,
+            })
+          )
+        );
+        console.log("Closed old lookup table:", pubkey.toBase58());
+      } catch (error) {
+        console.log("Lookup table still cooling down:", pubkey.toBase58());
+      }
+    }
+
+    // Register bench recipients (idempotent)
+    const names = [...Array(RECIPIENTS).keys()].map((i) => `BenchAgent${i}`);
+    for (const name of names) {
+      try {
+        await program.methods
+          .registerAgent(name)
+          .accounts({
+            agentRegistry: agentPda(name),
+            vault: vaultPda(name),
+            usdcMint: USDC_MINT,
+            authority: wallet,
+            systemProgram: SystemProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            rent: SYSVAR_RENT_PUBKEY,
+          })
+          .rpc();
+      } catch (error) {
+        if (!(error.message && error.message.includes("already in use"))) throw error;
+      }
+    }
+
+    // Fund the sender vault from the wallet's USDC account if needed
+    const needed = BigInt(ENTRIES) * AMOUNT;
+    const senderBalance = await vaultBalance(senderName);
+    if (senderBalance < needed) {
+      await program.methods
+        .deposit(new anchor.BN((needed - senderBalance).toString()))
+        .accounts({
+          agentRegistry: agentPda(senderName),
+          vault: vaultPda(senderName),
+          userTokenAccount: await getAssociatedTokenAddress(USDC_MINT, wallet),
+          authority: wallet,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    }
+
+    // Lookup table with every recipient (registry, vault) pair, extended in
+    // chunks that fit a legacy transaction
+    const recipientAccounts = names.flatMap((name) => [agentPda(name), vaultPda(name)]);
+    const slot = await connection.getSlot("finalized");
+    const [createIx, lookupTable] = AddressLookupTableProgram.createLookupTable({
+      authority: wallet,
+      payer: wallet,
+      recentSlot: slot,
+    });
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(createIx));
+
+    try {
+      for (let i = 0; i < recipientAccounts.length; i += 20) {
+        const extendIx = AddressLookupTableProgram.extendLookupTable({
+          lookupTable,
+          authority: wallet,
+          payer: wallet,
+          addresses: recipientAccounts.slice(i, i + 20),
+        });
+        await provider.sendAndConfirm(new anchor.web3.Transaction().add(extendIx));
+      }
+      // Lookup table entries become usable one slot after extension
+      const extendedAt = await connection.getSlot();
+      while ((await connection.getSlot()) <= extendedAt) {
+        await new Promise((resolve) => setTimeout(resolve, 400));
+      }
+      const table = (await connection.getAddressLookupTable(lookupTable)).value;
+
+      // Packed entries: u8 recipient index + u64 LE amount
+      const entries = Buffer.alloc(ENTRIES * 9);
+      const expected = new Array(RECIPIENTS).fill(0n);
+      for (let i = 0; i < ENTRIES; i++) {
+        entries.writeUInt8(i % RECIPIENTS, i * 9);
+        entries.writeBigUInt64LE(AMOUNT, i * 9 + 1);
+        expected[i % RECIPIENTS] += AMOUNT;
+      }
+
+      const batchIx = await program.methods
+        .batchPaymentCompact(entries)
+        .accounts({
+          senderRegistry: agentPda(senderName),
+          senderVault: vaultPda(senderName),
+          authority: wallet,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          recipientAccounts.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }))
+        )
+        .instruction();
+
+      const { blockhash } = await connection.getLatestBlockhash();
+      const message = new TransactionMessage({
+        payerKey: wallet,
+        recentBlockhash: blockhash,
+        instructions: [ComputeBudgetProgram.setComputeUnitLimit({ units: 1_400_000 }), batchIx],
+      }).compileToV0Message([table]);
+      const tx = await provider.wallet.signTransaction(new VersionedTransaction(message));
+
+      console.log("Serialized size:", tx.serialize().length, "bytes");
+      const sim = await connection.simulateTransaction(tx, { sigVerify: false });
+      console.log(`Compact batch: ${ENTRIES} entries, ${RECIPIENTS} recipients`);
+      console.log("Units consumed:", sim.value.unitsConsumed);
+      if (sim.value.err !== null) {
+        console.log(sim.value.logs);
+        throw new Error(`Compact batch simulation failed: ${JSON.stringify(sim.value.err)}`);
+      }
+      if (sim.value.unitsConsumed > CU_BUDGET) {
+        throw new Error(
+          `Compact batch used ${sim.value.unitsConsumed} CU, over the ${CU_BUDGET} CU budget`
+        );
+      }
+
+      // Send it for real and check every recipient was paid exactly once per entry
+      const before = await Promise.all(names.map(vaultBalance));
+      const signature = await connection.sendTransaction(tx);
+      await connection.confirmTransaction(signature, "confirmed");
+      const after = await Promise.all(names.map(vaultBalance));
+      names.forEach((name, i) => {
+        if (after[i] - before[i] !== expected[i]) {
+          throw new Error(
+            `${name} received ${after[i] - before[i]}, expected ${expected[i]}`
+          );
+        }
+      });
+    } finally {
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(
+          AddressLookupTableProgram.deactivateLookupTable({ lookupTable, authority: wallet })
+        )
+      );
+    }
+  });
 });